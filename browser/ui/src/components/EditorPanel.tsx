@@ -9,6 +9,7 @@ import {
   LuLoaderCircle,
   LuPlus,
   LuSave,
+  LuTimer,
 } from "react-icons/lu";
 import Editor from "react-simple-code-editor";
 import {
@@ -16,6 +17,7 @@ import {
   newEditorGroup,
   runCode,
   saveNotebook,
+  setNotebookTimeout,
 } from "../core/actions";
 import {
   EditorCell,
@@ -359,9 +361,34 @@ function crawlOpen(
 const EditorPanel: React.FC<{ notebook: Notebook }> = ({ notebook }) => {
   const dispatch = useDispatch()!;
   const sendCommand = useSendCommand()!;
+  const pushNotification = usePushNotification();
   const onSave = useCallback(() => {
     saveNotebook(notebook, dispatch, sendCommand);
   }, [notebook, dispatch, sendCommand]);
+  const onTimeout = useCallback(() => {
+    dispatch({
+      type: "set_dialog",
+      dialog: {
+        title: "Default cell timeout in seconds (0 = no timeout)",
+        value: (notebook.default_timeout ?? 0).toString(),
+        okText: "Set timeout",
+        onCancel: () => {},
+        onConfirm: (value: string) => {
+          const timeout = Number(value);
+          if (!Number.isInteger(timeout) || timeout < 0) {
+            pushNotification("Invalid timeout", "error");
+            return;
+          }
+          setNotebookTimeout(
+            notebook,
+            timeout > 0 ? timeout : null,
+            dispatch,
+            sendCommand,
+          );
+        },
+      },
+    });
+  }, [notebook, dispatch, sendCommand, pushNotification]);
   const orderedNodes: EditorNode[] = [];
   crawlOpen(notebook.editor_root, notebook.editor_open_nodes, orderedNodes);
   return (
@@ -376,6 +403,16 @@ const EditorPanel: React.FC<{ notebook: Notebook }> = ({ notebook }) => {
               <LuSave className="w-4 h-4" />
             )}
           </ToolButton>
+          <ToolButton onClick={onTimeout}>
+            <span className="flex items-center">
+              <LuTimer className="w-4 h-4" />
+              {notebook.default_timeout !== null && (
+                <span className="ml-1 text-xs">
+                  {notebook.default_timeout}s
+                </span>
+              )}
+            </span>
+          </ToolButton>
         </div>
       </div>
 
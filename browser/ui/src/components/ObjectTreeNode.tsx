@@ -1,4 +1,4 @@
-import React from "react";
+import React, { useEffect } from "react";
 import {
   LuChevronRight,
   LuChevronDown,
@@ -11,7 +11,13 @@ import {
   LuCog,
 } from "react-icons/lu";
 import { VscCircle } from "react-icons/vsc";
-import { JsonObjectId, JsonObjectStruct } from "../core/jobject";
+import {
+  InspectTarget,
+  inspectKey,
+  JsonObjectId,
+  JsonObjectStruct,
+} from "../core/jobject";
+import { InspectedObject } from "../core/notebook";
 
 // Tree Node Component
 const ObjectTreeNode: React.FC<{
@@ -22,6 +28,9 @@ const ObjectTreeNode: React.FC<{
   depth: number;
   openObjects: Set<string>;
   toggleOpenObject: (path: string) => void;
+  target: InspectTarget;
+  inspected: Record<string, InspectedObject>;
+  inspect: (target: InspectTarget, offset: number) => void;
 }> = ({
   struct,
   id,
@@ -30,9 +39,23 @@ const ObjectTreeNode: React.FC<{
   depth,
   openObjects,
   toggleOpenObject,
+  target,
+  inspected,
+  inspect,
 }) => {
   const object = struct.objects.get(id)!;
   const isOpen = openObjects.has(slotPath);
+  // Children of containers are not sent in globals, they are fetched on opening
+  const isLazy = object.len !== undefined;
+  const entry = isLazy ? inspected[inspectKey(target)] : undefined;
+  // Inspected object knows also types of its children
+  const valueType = entry?.value_type ?? object.value_type;
+
+  useEffect(() => {
+    if (isLazy && isOpen && !entry) {
+      inspect(target, 0);
+    }
+  }, [isLazy, isOpen, entry, target, inspect]);
   //const indent = `ml-${depth * 4}`;
 
   const getIcon = () => {
@@ -83,36 +106,63 @@ const ObjectTreeNode: React.FC<{
     return (
       <span className="text-teal-600">
         {object?.repr}
-        {object?.value_type && (
+        {valueType && (
           <>
             :{" "}
-            <span className="font-bold text-amber-600">
-              {" "}
-              {object?.value_type}
-            </span>
+            <span className="font-bold text-amber-600"> {valueType}</span>
           </>
         )}
       </span>
     );
   };
 
-  const hasChildren = object.children?.length ?? 0 > 0;
+  const children: [string, JsonObjectStruct, JsonObjectId][] = isLazy
+    ? (entry?.children ?? []).map(([name, s]) => [name, s, s.root])
+    : (object.children ?? []).map(([name, child]) => [name, struct, child]);
+  const hasChildren = isLazy ? object.len! > 0 : children.length > 0;
+  const truncated = isLazy ? entry?.truncated : object.truncated;
 
   // Render children
   const renderChildren = () => {
     if (!hasChildren || !isOpen) return null;
-    return object.children!.map(([slotName, child]) => (
-      <ObjectTreeNode
-        key={slotName}
-        slotName={slotName}
-        slotPath={`${slotPath}/${slotName}`}
-        struct={struct}
-        id={child}
-        depth={depth + 1}
-        openObjects={openObjects}
-        toggleOpenObject={toggleOpenObject}
-      />
-    ));
+    return (
+      <>
+        {children.map(([slotName, childStruct, child], index) => (
+          <ObjectTreeNode
+            key={slotName}
+            slotName={slotName}
+            slotPath={`${slotPath}/${slotName}`}
+            struct={childStruct}
+            id={child}
+            depth={depth + 1}
+            openObjects={openObjects}
+            toggleOpenObject={toggleOpenObject}
+            target={{ ...target, path: [...target.path, index] }}
+            inspected={inspected}
+            inspect={inspect}
+          />
+        ))}
+        {entry?.error && <div className="py-1 text-red-600">{entry.error}</div>}
+        {entry?.loading && <div className="py-1 text-gray-500">Loading…</div>}
+        {truncated !== undefined && (
+          <div className="py-1 text-gray-500">
+            truncated: {truncated} more items
+          </div>
+        )}
+        {entry &&
+          !entry.loading &&
+          !entry.error &&
+          truncated === undefined &&
+          entry.children.length < entry.len && (
+            <button
+              className="py-1 text-blue-600 hover:underline"
+              onClick={() => inspect(target, entry.children.length)}
+            >
+              {entry.len - entry.children.length} more items, load more
+            </button>
+          )}
+      </>
+    );
   };
 
   return (
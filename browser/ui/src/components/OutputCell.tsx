@@ -2,14 +2,29 @@ import React, { useEffect, useRef, useState } from "react";
 import {
   LuCircleAlert,
   LuCircleCheck,
+  LuCircleSlash,
   LuClock,
   LuCirclePlay,
 } from "react-icons/lu";
-import { EditorNode, OutputCell, OutputValue } from "../core/notebook";
+import {
+  EditorNode,
+  EditorNodeId,
+  InputRequest,
+  LogOutputValue,
+  OutputCell,
+  OutputPage,
+  OutputValue,
+  RunId,
+  StyleSpan,
+  TextColor,
+  Truncation,
+} from "../core/notebook";
 import Editor from "react-simple-code-editor";
 import { highlight, languages } from "prismjs/components/prism-core";
 import "prismjs/components/prism-python";
 import { useGlobalState } from "./StateProvider";
+import { useSendCommand } from "./WsProvider";
+import { cancelCompute, fetchOutput, sendInputReply } from "../core/actions";
 
 const CodeTree: React.FC<{ node: EditorNode; depth: number }> = ({
   node,
@@ -43,34 +58,359 @@ const CodeTree: React.FC<{ node: EditorNode; depth: number }> = ({
   }
 };
 
-const OutputValueView: React.FC<{ value: OutputValue }> = (props: {
+// MIME types that can be shown, ordered from the richest one
+const MIME_PRIORITY = [
+  "text/html",
+  "image/svg+xml",
+  "image/png",
+  "image/jpeg",
+  "text/markdown",
+  "text/latex",
+  "application/json",
+  "text/plain",
+];
+
+const MimeBundleView: React.FC<{ bundle: Record<string, string> }> = (props: {
+  bundle: Record<string, string>;
+}) => {
+  const mime = MIME_PRIORITY.find((m) => m in props.bundle);
+  if (mime === undefined) {
+    return null;
+  }
+  const data = props.bundle[mime];
+  switch (mime) {
+    case "text/html":
+      return <div dangerouslySetInnerHTML={{ __html: data }} />;
+    case "image/svg+xml":
+      return (
+        <img
+          src={`data:image/svg+xml;charset=utf-8,${encodeURIComponent(data)}`}
+        />
+      );
+    case "image/png":
+    case "image/jpeg":
+      return <img src={`data:${mime};base64,${data}`} />;
+    case "application/json":
+      return (
+        <pre className="text-left">
+          {JSON.stringify(JSON.parse(data), null, 2)}
+        </pre>
+      );
+    default:
+      return <pre className="text-left">{data}</pre>;
+  }
+};
+
+// Standard and bright colours of terminals (VS Code palette)
+const ANSI_COLORS = [
+  "#000000",
+  "#cd3131",
+  "#0dbc79",
+  "#e5e510",
+  "#2472c8",
+  "#bc3fbc",
+  "#11a8cd",
+  "#e5e5e5",
+  "#666666",
+  "#f14c4c",
+  "#23d18b",
+  "#f5f543",
+  "#3b8eea",
+  "#d670d6",
+  "#29b8db",
+  "#e5e5e5",
+];
+
+function colorToCss(color: TextColor): string {
+  if (Array.isArray(color)) {
+    return `rgb(${color[0]}, ${color[1]}, ${color[2]})`;
+  }
+  if (color < 16) {
+    return ANSI_COLORS[color];
+  }
+  if (color < 232) {
+    // 6x6x6 colour cube
+    const level = (v: number) => (v === 0 ? 0 : 55 + v * 40);
+    const i = color - 16;
+    return `rgb(${level(Math.floor(i / 36))}, ${level(Math.floor(i / 6) % 6)}, ${level(i % 6)})`;
+  }
+  const gray = 8 + (color - 232) * 10;
+  return `rgb(${gray}, ${gray}, ${gray})`;
+}
+
+function spanStyle(span: StyleSpan): React.CSSProperties {
+  return {
+    color: span.fg !== undefined ? colorToCss(span.fg) : undefined,
+    backgroundColor: span.bg !== undefined ? colorToCss(span.bg) : undefined,
+    fontWeight: span.bold ? "bold" : undefined,
+    opacity: span.dim ? 0.6 : undefined,
+    fontStyle: span.italic ? "italic" : undefined,
+    textDecoration: span.underline ? "underline" : undefined,
+  };
+}
+
+const StyledText: React.FC<{ text: string; spans?: StyleSpan[] }> = (props: {
+  text: string;
+  spans?: StyleSpan[];
+}) => {
+  if (!props.spans || props.spans.length === 0) {
+    return <>{props.text}</>;
+  }
+  const chars = Array.from(props.text);
+  const parts = [];
+  let position = 0;
+  for (const span of props.spans) {
+    if (span.start > position) {
+      parts.push(chars.slice(position, span.start).join(""));
+    }
+    parts.push(
+      <span key={span.start} style={spanStyle(span)}>
+        {chars.slice(span.start, span.end).join("")}
+      </span>,
+    );
+    position = span.end;
+  }
+  parts.push(chars.slice(position).join(""));
+  return <>{parts}</>;
+};
+
+// Text whose middle part is on the server, loaded pages are shown after the head
+const TruncatedText: React.FC<{
+  text: string;
+  spans?: StyleSpan[];
+  truncated: Truncation;
+  page?: OutputPage;
+  onLoadMore: (offset: number) => void;
+}> = (props: {
+  text: string;
+  spans?: StyleSpan[];
+  truncated: Truncation;
+  page?: OutputPage;
+  onLoadMore: (offset: number) => void;
+}) => {
+  const chars = Array.from(props.text);
+  const offset = props.truncated.offset;
+  const spans = props.spans ?? [];
+  const headSpans = spans
+    .filter((s) => s.start < offset)
+    .map((s) => ({ ...s, end: Math.min(s.end, offset) }));
+  const tailSpans = spans
+    .filter((s) => s.end > offset)
+    .map((s) => ({
+      ...s,
+      start: Math.max(s.start, offset) - offset,
+      end: s.end - offset,
+    }));
+  const loaded = props.page?.next_offset ?? 0;
+  const remaining = props.truncated.omitted - loaded;
+  return (
+    <>
+      <StyledText text={chars.slice(0, offset).join("")} spans={headSpans} />
+      {props.page?.text}
+      {remaining > 0 && (
+        <button
+          onClick={() => props.onLoadMore(loaded)}
+          className="block my-1 px-2 py-1 bg-gray-200 rounded text-xs font-medium font-sans hover:bg-gray-300 transition-colors"
+        >
+          {remaining} bytes omitted, load more
+        </button>
+      )}
+      <StyledText text={chars.slice(offset).join("")} spans={tailSpans} />
+    </>
+  );
+};
+
+function collectCellIds(node: EditorNode, out: EditorNodeId[]) {
+  if (node.type === "Cell") {
+    out.push(node.id);
+  } else {
+    node.children.forEach((child) => collectCellIds(child, out));
+  }
+}
+
+function logSource(
+  source: LogOutputValue["value"]["source"],
+  editorNode: EditorNode,
+): string | null {
+  if (source === null) {
+    return null;
+  }
+  if (source.cell_id === null) {
+    return `${source.filename}:${source.lineno}`;
+  }
+  const ids: EditorNodeId[] = [];
+  collectCellIds(editorNode, ids);
+  const index = ids.indexOf(source.cell_id);
+  if (index === -1) {
+    return `other cell, line ${source.lineno}`;
+  }
+  return ids.length > 1
+    ? `cell ${index + 1}, line ${source.lineno}`
+    : `line ${source.lineno}`;
+}
+
+function logClass(level: number): string {
+  if (level >= 40) {
+    return "bg-red-50 text-red-800";
+  }
+  if (level >= 30) {
+    return "bg-yellow-50 text-yellow-800";
+  }
+  return "text-gray-600";
+}
+
+const LogView: React.FC<{ log: LogOutputValue; editorNode: EditorNode }> = (
+  props: { log: LogOutputValue; editorNode: EditorNode },
+) => {
+  const log = props.log.value;
+  const source = logSource(log.source, props.editorNode);
+  return (
+    <div className={`flex text-sm space-x-2 ${logClass(log.level)}`}>
+      <span className="font-medium">{log.category}</span>
+      {log.logger !== null && <span>{log.logger}</span>}
+      <pre className="text-left flex-grow">{log.message}</pre>
+      {source !== null && <span className="text-xs opacity-70">{source}</span>}
+    </div>
+  );
+};
+
+const OutputValueView: React.FC<{
+  value: OutputValue;
+  editorNode: EditorNode;
+  page?: OutputPage;
+  onLoadMore: (offset: number) => void;
+}> = (props: {
   value: OutputValue;
+  editorNode: EditorNode;
+  page?: OutputPage;
+  onLoadMore: (offset: number) => void;
 }) => {
   const value = props.value;
   if (value.type === "None") {
     return null;
   }
-  if (value.type === "Text") {
-    return <pre className="text-left">{value.value}</pre>;
+  if (value.type === "Stream") {
+    return (
+      <pre
+        className={`text-left ${value.name === "stderr" ? "bg-red-50 text-red-800" : ""}`}
+      >
+        {value.truncated ? (
+          <TruncatedText
+            text={value.value}
+            spans={value.spans}
+            truncated={value.truncated}
+            page={props.page}
+            onLoadMore={props.onLoadMore}
+          />
+        ) : (
+          <StyledText text={value.value} spans={value.spans} />
+        )}
+      </pre>
+    );
+  } else if (value.type === "Text") {
+    return (
+      <>
+        {value.dropped && value.dropped.length > 0 && (
+          <div className="text-xs text-gray-500 font-sans">
+            Rich output ({value.dropped.join(", ")}) was over the output limit
+            and was dropped, only the plain text is shown
+          </div>
+        )}
+        <pre className="text-left">
+          {value.truncated ? (
+            <TruncatedText
+              text={value.value}
+              truncated={value.truncated}
+              page={props.page}
+              onLoadMore={props.onLoadMore}
+            />
+          ) : (
+            value.value
+          )}
+        </pre>
+      </>
+    );
   } else if (value.type === "Html") {
     return <div dangerouslySetInnerHTML={{ __html: value.value }} />;
+  } else if (value.type === "MimeBundle") {
+    return <MimeBundleView bundle={value.value} />;
   } else if (value.type === "Exception") {
     return (
       <pre className="text-left">
         {value.value.message + "\n" + value.value.traceback}
       </pre>
     );
+  } else if (value.type === "Log") {
+    return <LogView log={value} editorNode={props.editorNode} />;
   }
   return null;
 };
 
+const formatDuration = (ms: number): string => {
+  if (ms < 1000) {
+    return `${ms} ms`;
+  }
+  if (ms < 60_000) {
+    return `${(ms / 1000).toFixed(2)} s`;
+  }
+  const minutes = Math.floor(ms / 60_000);
+  const seconds = Math.floor((ms % 60_000) / 1000);
+  return `${minutes} min ${seconds} s`;
+};
+
+const InputRequestView: React.FC<{
+  request: InputRequest;
+  runId: RunId;
+}> = (props: { request: InputRequest; runId: RunId }) => {
+  const state = useGlobalState();
+  const sendCommand = useSendCommand()!;
+  const [value, setValue] = useState("");
+  const submit = (e: React.FormEvent) => {
+    e.preventDefault();
+    sendInputReply(
+      state.selected_notebook!.id,
+      props.runId,
+      props.request.request_id,
+      value,
+      sendCommand,
+    );
+    setValue("");
+  };
+  return (
+    <form onSubmit={submit} className="flex items-center space-x-1 text-sm">
+      <span className="font-mono whitespace-pre">{props.request.prompt}</span>
+      <input
+        type={props.request.password ? "password" : "text"}
+        value={value}
+        onChange={(e) => setValue(e.target.value)}
+        autoFocus
+        autoComplete="off"
+        className="flex-grow border border-gray-300 px-1 font-mono"
+      />
+    </form>
+  );
+};
+
 const OutputCellView: React.FC<{
   cell: OutputCell;
+  runId: RunId;
   isLast: boolean;
-}> = (props: { cell: OutputCell; isLast: boolean }) => {
+  inputRequest?: InputRequest | null;
+}> = (props: {
+  cell: OutputCell;
+  runId: RunId;
+  isLast: boolean;
+  inputRequest?: InputRequest | null;
+}) => {
   const state = useGlobalState();
+  const sendCommand = useSendCommand()!;
   const notebook = state.selected_notebook!;
+  const outputPages = notebook.runs.find(
+    (r) => r.id === props.runId,
+  )?.output_pages;
   const [showMetadata, setShowMetadata] = useState(false);
+  const [showLogs, setShowLogs] = useState(true);
+  const hasLogs = props.cell.values.some((v) => v.type === "Log");
   const ref = useRef<HTMLDivElement>(null);
 
   useEffect(() => {
@@ -90,6 +430,8 @@ const OutputCellView: React.FC<{
         return <LuCircleCheck className="h-4 w-4 text-green-700" />;
       case "Fail":
         return <LuCircleAlert className="h-4 w-4 text-red-700" />;
+      case "Cancelled":
+        return <LuCircleSlash className="h-4 w-4 text-gray-500" />;
       default:
         return null;
     }
@@ -106,11 +448,15 @@ const OutputCellView: React.FC<{
         return <span className="text-green-700 text-xs">Done</span>;
       case "Fail":
         return <span className="text-red-700 text-xs">Error</span>;
+      case "Cancelled":
+        return <span className="text-gray-500 text-xs">Cancelled</span>;
       default:
         return null;
     }
   };
 
+  const durationMs = props.cell.timing?.duration_ms;
+
   return (
     <div
       className={`border-l-6 pl-1 ${notebook.selected_editor_node_id === props.cell.called_id ? "border-orange-200" : "border-white"}`}
@@ -123,15 +469,50 @@ const OutputCellView: React.FC<{
           <div className="flex items-center space-x-1">
             {getStatusIcon()}
             {getStatusText()}
+            {durationMs != null && (
+              <span
+                className="text-gray-500 text-xs"
+                title={`Started ${props.cell.timing!.started}`}
+              >
+                {formatDuration(durationMs)}
+              </span>
+            )}
+          </div>
+          <div className="flex items-center space-x-1">
+            {props.cell.flag === "Pending" && (
+              <button
+                onClick={() =>
+                  cancelCompute(
+                    notebook.id,
+                    props.runId,
+                    props.cell.id,
+                    sendCommand,
+                  )
+                }
+                className="flex items-center justify-center px-2 py-1 bg-gray-200 rounded text-xs font-medium hover:bg-gray-300 transition-colors"
+                aria-label="Cancel computation"
+              >
+                <span>Cancel</span>
+              </button>
+            )}
+            {hasLogs && (
+              <button
+                onClick={() => setShowLogs(!showLogs)}
+                className="flex items-center justify-center px-2 py-1 bg-gray-200 rounded text-xs font-medium hover:bg-gray-300 transition-colors"
+                aria-label="Toggle warnings and logs"
+              >
+                <span>{showLogs ? "Hide logs" : "Show logs"}</span>
+              </button>
+            )}
+            <button
+              onClick={() => setShowMetadata(!showMetadata)}
+              className="flex items-center justify-center px-2 py-1 bg-gray-200 rounded text-xs font-medium hover:bg-gray-300 transition-colors"
+              aria-label="Toggle metadata"
+            >
+              {/*<Info className="h-3 w-3 text-gray-600 mr-1" />*/}
+              <span>Code</span>
+            </button>
           </div>
-          <button
-            onClick={() => setShowMetadata(!showMetadata)}
-            className="flex items-center justify-center px-2 py-1 bg-gray-200 rounded text-xs font-medium hover:bg-gray-300 transition-colors"
-            aria-label="Toggle metadata"
-          >
-            {/*<Info className="h-3 w-3 text-gray-600 mr-1" />*/}
-            <span>Code</span>
-          </button>
         </div>
 
         {/* Metadata (conditionally rendered) */}
@@ -143,9 +524,33 @@ const OutputCellView: React.FC<{
 
         {/* Content */}
         <div className={`p-1 ${props.cell.flag === "Fail" ? "bg-red-50" : ""}`}>
-          {props.cell.values.map((value, index) => (
-            <OutputValueView key={index} value={value} />
-          ))}
+          {props.cell.values.map((value, index) =>
+            value.type === "Log" && !showLogs ? null : (
+              <OutputValueView
+                key={index}
+                value={value}
+                editorNode={props.cell.editor_node}
+                page={outputPages?.[`${props.cell.id}/${index}`]}
+                onLoadMore={(offset) =>
+                  fetchOutput(
+                    notebook.id,
+                    props.runId,
+                    props.cell.id,
+                    index,
+                    offset,
+                    sendCommand,
+                  )
+                }
+              />
+            ),
+          )}
+          {props.inputRequest && (
+            <InputRequestView
+              key={props.inputRequest.request_id}
+              request={props.inputRequest}
+              runId={props.runId}
+            />
+          )}
         </div>
       </div>
     </div>
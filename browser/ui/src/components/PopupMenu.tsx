@@ -1,4 +1,4 @@
-import { LuBan, LuSquare } from "react-icons/lu";
+import { LuBan, LuRotateCw, LuSkull, LuSquare } from "react-icons/lu";
 import { ReactNode, useEffect, useRef, useState } from "react";
 import { TbRowInsertBottom, TbRowInsertTop, TbArrowFork } from "react-icons/tb";
 import { PiTreeView } from "react-icons/pi";
@@ -6,6 +6,8 @@ import { PiTreeView } from "react-icons/pi";
 type Icon =
   | "ban"
   | "square"
+  | "kill"
+  | "restart"
   | "insert_above"
   | "insert_below"
   | "insert_child"
@@ -23,6 +25,10 @@ function getIcon(name: Icon): ReactNode {
       return <LuBan size={18} className="mr-2" />;
     case "square":
       return <LuSquare size={18} className="mr-2" />;
+    case "kill":
+      return <LuSkull size={18} className="mr-2" />;
+    case "restart":
+      return <LuRotateCw size={18} className="mr-2" />;
     case "insert_above":
       return <TbRowInsertTop size={18} className="mr-2" />;
     case "insert_below":
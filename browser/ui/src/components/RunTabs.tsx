@@ -6,12 +6,20 @@ import {
   LuPlus,
   LuX,
 } from "react-icons/lu";
-import { closeRun, forkRun, newRun } from "../core/actions";
+import {
+  closeRun,
+  forkRun,
+  interruptRun,
+  newRun,
+  restartKernel,
+  stopKernel,
+} from "../core/actions";
 import { Notebook, Run } from "../core/notebook";
-import { PopupMenu } from "./PopupMenu";
+import { MenuItem, PopupMenu } from "./PopupMenu";
 import RunView from "./RunView";
+import { usePushNotification } from "./NotificationProvider";
 import { useDispatch } from "./StateProvider";
-import { StatusIndicator } from "./StatusIndicator";
+import { KernelUsage, StatusIndicator } from "./StatusIndicator";
 import Workspace from "./Workspace";
 import { useSendCommand } from "./WsProvider";
 
@@ -21,6 +29,19 @@ const RunMenu: React.FC<{ notebook: Notebook; run: Run }> = (props: {
 }) => {
   const dispatch = useDispatch()!;
   const sendCommand = useSendCommand()!;
+  const pushNotification = usePushNotification();
+  const stop = (force: boolean) => {
+    if (props.run.kernel_info) {
+      stopKernel(props.run.kernel_info.kernel_id, force, sendCommand);
+    } else {
+      pushNotification("Kernel for this run is not running", "error");
+    }
+  };
+  const kill: MenuItem = {
+    icon: "kill",
+    title: "Kill kernel",
+    onClick: () => stop(true),
+  };
   return (
     <PopupMenu
       createButton={(toggleMenu) => (
@@ -32,25 +53,39 @@ const RunMenu: React.FC<{ notebook: Notebook; run: Run }> = (props: {
           <LuMenu size={24} />
         </button>
       )}
-      items={[
-        {
-          icon: "fork",
-          title: "Fork kernel",
-          onClick: () => {
-            forkRun(props.notebook.id, props.run, dispatch, sendCommand);
-          },
-        },
-        {
-          icon: "ban",
-          title: "Interrupt computation",
-          onClick: () => {},
-        },
-        {
-          icon: "square",
-          title: "Stop kernel",
-          onClick: () => {},
-        },
-      ]}
+      items={
+        props.run.kernel_info?.stopping
+          ? [kill]
+          : [
+              {
+                icon: "fork",
+                title: "Fork kernel",
+                onClick: () => {
+                  forkRun(props.notebook.id, props.run, dispatch, sendCommand);
+                },
+              },
+              {
+                icon: "ban",
+                title: "Interrupt computation",
+                onClick: () => {
+                  interruptRun(props.notebook.id, props.run.id, sendCommand);
+                },
+              },
+              {
+                icon: "restart",
+                title: "Restart kernel",
+                onClick: () => {
+                  restartKernel(props.notebook.id, props.run.id, sendCommand);
+                },
+              },
+              {
+                icon: "square",
+                title: "Stop kernel",
+                onClick: () => stop(false),
+              },
+              kill,
+            ]
+      }
     />
   );
 };
@@ -259,6 +294,7 @@ const RunTabs: React.FC<{ notebook: Notebook }> = (props: {
             <RunMenu notebook={notebook} run={run} />
             <ViewSwitch notebook={notebook} run={run} />
             <StatusIndicator status={run.kernel_state} />
+            {run.kernel_info && <KernelUsage info={run.kernel_info} />}
             {/* {(run.kernel_state.type !== "Running" ||
               run.output_cells.length === 0) && (
 
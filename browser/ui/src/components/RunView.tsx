@@ -1,16 +1,80 @@
-import { Run } from "../core/notebook";
+import { KernelCrash, KernelRestart, Run } from "../core/notebook";
 import OutputCell from "./OutputCell";
 
+const KernelRestartView: React.FC<{ restart: KernelRestart }> = (props: {
+  restart: KernelRestart;
+}) => {
+  return (
+    <div className="flex items-center mb-2 mr-6 ml-2 text-xs text-gray-500">
+      <div className="flex-grow border-t border-gray-300" />
+      <span className="px-2">
+        Kernel restarted at {new Date(props.restart.time).toLocaleString()}
+      </span>
+      <div className="flex-grow border-t border-gray-300" />
+    </div>
+  );
+};
+
+const KernelCrashView: React.FC<{
+  crash: KernelCrash;
+  stderr?: string;
+}> = (props: { crash: KernelCrash; stderr?: string }) => {
+  const crash = props.crash;
+  const details = [];
+  if (crash.exit_code !== null) {
+    details.push(`exit code ${crash.exit_code}`);
+  }
+  if (crash.signal !== null) {
+    details.push(`signal ${crash.signal}`);
+  }
+  if (crash.oom) {
+    details.push("possibly out of memory");
+  }
+  return (
+    <div className="mb-2 mr-6 ml-2">
+      <div className="text-xs text-red-700 mb-1">
+        Kernel crashed
+        {crash.time && ` at ${new Date(crash.time).toLocaleString()}`}
+        {details.length > 0 && ` (${details.join(", ")})`}
+      </div>
+      {props.stderr && (
+        <pre className="text-xs bg-red-50 p-2 overflow-auto">
+          {props.stderr}
+        </pre>
+      )}
+    </div>
+  );
+};
+
 const RunView: React.FC<{ run: Run }> = (props: { run: Run }) => {
+  const restartsAt = (position: number) =>
+    props.run.kernel_restarts
+      .filter((r) => r.position === position)
+      .map((r) => <KernelRestartView key={r.time} restart={r} />);
   return (
     <div className="overflow-auto" style={{ height: "calc(100vh - 150px)" }}>
       {props.run.output_cells.map((cell, index) => (
-        <OutputCell
-          key={cell.id}
-          cell={cell}
-          isLast={index === props.run.output_cells.length - 1}
-        />
+        <div key={cell.id}>
+          {restartsAt(index)}
+          <OutputCell
+            cell={cell}
+            runId={props.run.id}
+            isLast={index === props.run.output_cells.length - 1}
+            inputRequest={
+              props.run.input_request?.cell_id === cell.id
+                ? props.run.input_request
+                : null
+            }
+          />
+        </div>
       ))}
+      {restartsAt(props.run.output_cells.length)}
+      {props.run.kernel_state.type === "Crashed" && (
+        <KernelCrashView
+          crash={props.run.kernel_state}
+          stderr={props.run.kernel_state.stderr}
+        />
+      )}
     </div>
   );
 };
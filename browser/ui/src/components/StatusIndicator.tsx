@@ -1,6 +1,11 @@
 import React from "react";
-import { LuLoaderCircle, LuX, LuCircle } from "react-icons/lu";
-import { KernelState } from "../core/notebook";
+import {
+  LuLoaderCircle,
+  LuX,
+  LuCircle,
+  LuCircleAlert,
+} from "react-icons/lu";
+import { KernelInfo, KernelState } from "../core/notebook";
 
 interface StatusIndicatorProps {
   status: KernelState;
@@ -26,6 +31,12 @@ export const StatusIndicator: React.FC<StatusIndicatorProps> = ({ status }) => {
       icon: <LuLoaderCircle className="w-4 h-4 mr-2 animate-spin" />,
       label: "Running",
     },
+    Unresponsive: {
+      color: "bg-orange-300",
+      textColor: "text-orange-700",
+      icon: <LuCircleAlert className="w-4 h-4 mr-2" />,
+      label: "Kernel not responding",
+    },
     Crashed: {
       color: "bg-red-300",
       textColor: "text-red-700",
@@ -57,3 +68,41 @@ export const StatusIndicator: React.FC<StatusIndicatorProps> = ({ status }) => {
     </div>
   );
 };
+
+function formatBytes(bytes: number): string {
+  const units = ["B", "KiB", "MiB", "GiB", "TiB"];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit += 1;
+  }
+  return `${value.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+}
+
+function formatSeconds(seconds: number): string {
+  if (seconds < 60) {
+    return `${seconds.toFixed(1)} s`;
+  }
+  const minutes = Math.floor(seconds / 60);
+  if (minutes < 60) {
+    return `${minutes} min ${Math.floor(seconds % 60)} s`;
+  }
+  return `${Math.floor(minutes / 60)} h ${minutes % 60} min`;
+}
+
+export const KernelUsage: React.FC<{ info: KernelInfo }> = ({ info }) => {
+  return (
+    <div
+      className="flex items-center ml-4 text-xs text-gray-500 space-x-3"
+      title={`PID ${info.pid}, uptime ${formatSeconds(info.uptime)}`}
+    >
+      {info.rss !== null && <span>RAM {formatBytes(info.rss)}</span>}
+      {info.cpu_time !== null && (
+        <span>CPU {formatSeconds(info.cpu_time)}</span>
+      )}
+      {info.queue > 0 && <span>Queue {info.queue}</span>}
+      {info.stopping && <span className="text-orange-600">Stopping</span>}
+    </div>
+  );
+};
@@ -1,18 +1,92 @@
 import { LuChevronDown, LuChevronRight, LuGlobe } from "react-icons/lu";
-import { Globals, NotebookId, Run } from "../core/notebook";
+import { inspectObject, setJObjectLimits } from "../core/actions";
+import {
+  DEFAULT_JOBJECT_LIMITS,
+  InspectTarget,
+  JObjectLimits,
+} from "../core/jobject";
+import { Globals, InspectedObject, NotebookId, Run } from "../core/notebook";
+import { usePushNotification } from "./NotificationProvider";
 import ObjectTreeNode from "./ObjectTreeNode";
 import { useDispatch } from "./StateProvider";
+import { useSendCommand } from "./WsProvider";
+
+const LIMITS: [keyof JObjectLimits, string, string][] = [
+  ["max_depth", "Depth", "Maximal depth of inspected objects in a variable"],
+  ["max_children", "Items", "Maximal number of shown items of a container"],
+  ["max_objects", "Objects", "Maximal number of objects loaded at once"],
+];
+
+const LimitsToolbar: React.FC<{ notebook_id: NotebookId; run: Run }> = ({
+  notebook_id,
+  run,
+}) => {
+  const dispatch = useDispatch()!;
+  const sendCommand = useSendCommand()!;
+  const pushNotification = usePushNotification();
+  const limits = run.jobject_limits ?? DEFAULT_JOBJECT_LIMITS;
+  const onClick = (key: keyof JObjectLimits, title: string) => {
+    dispatch({
+      type: "set_dialog",
+      dialog: {
+        title,
+        value: limits[key].toString(),
+        okText: "Set limit",
+        onCancel: () => {},
+        onConfirm: (value: string) => {
+          const limit = Number(value);
+          if (!Number.isInteger(limit) || limit < 1) {
+            pushNotification("Invalid limit", "error");
+            return;
+          }
+          setJObjectLimits(
+            notebook_id,
+            run.id,
+            { ...limits, [key]: limit },
+            dispatch,
+            sendCommand,
+          );
+        },
+      },
+    });
+  };
+  return (
+    <div className="sticky top-0 flex space-x-2 bg-white pb-2 text-xs">
+      {LIMITS.map(([key, name, title]) => (
+        <button
+          key={key}
+          title={title}
+          onClick={() => onClick(key, title)}
+          className="rounded bg-gray-200 px-2 py-1 hover:bg-gray-300"
+        >
+          {name}: {limits[key]}
+        </button>
+      ))}
+    </div>
+  );
+};
 
 const Scope: React.FC<{
   slotPath: string;
+  scope: string[];
   globals: Globals;
   openObjects: Set<string>;
   toggleOpenObject: (path: string) => void;
-}> = ({ globals, slotPath, openObjects, toggleOpenObject }) => {
+  inspected: Record<string, InspectedObject>;
+  inspect: (target: InspectTarget, offset: number) => void;
+}> = ({
+  globals,
+  slotPath,
+  scope,
+  openObjects,
+  toggleOpenObject,
+  inspected,
+  inspect,
+}) => {
   return (
     <>
-      {globals.children.map(([id, scope]) => {
-        const hasChildren = scope.children.length + scope.variables.length > 0;
+      {globals.children.map(([id, child]) => {
+        const hasChildren = child.children.length + child.variables.length > 0;
         const childSlotPath = slotPath + "/" + id;
         const isOpen = openObjects.has(childSlotPath);
         return (
@@ -33,15 +107,18 @@ const Scope: React.FC<{
                 <span className="mr-1 w-4"></span>
               )}
               <LuGlobe size={16} className="text-purple-400" />
-              <span className="mx-1 font-mono text-blue-800">{scope.name}</span>
+              <span className="mx-1 font-mono text-blue-800">{child.name}</span>
             </div>
             {isOpen && (
               <div className="ml-4">
                 <Scope
                   slotPath={childSlotPath}
-                  globals={scope}
+                  scope={[...scope, id]}
+                  globals={child}
                   openObjects={openObjects}
                   toggleOpenObject={toggleOpenObject}
+                  inspected={inspected}
+                  inspect={inspect}
                 />
               </div>
             )}
@@ -58,6 +135,9 @@ const Scope: React.FC<{
           slotPath={slotPath + ":" + name}
           openObjects={openObjects}
           toggleOpenObject={toggleOpenObject}
+          target={{ scope, variable: name, path: [] }}
+          inspected={inspected}
+          inspect={inspect}
         />
       ))}
     </>
@@ -69,6 +149,7 @@ const Workspace: React.FC<{ notebook_id: NotebookId; run: Run }> = ({
   run,
 }) => {
   const dispatch = useDispatch()!;
+  const sendCommand = useSendCommand()!;
   const toggleOpenObject = (object_path: string) => {
     dispatch({
       type: "toggle_open_object",
@@ -77,13 +158,20 @@ const Workspace: React.FC<{ notebook_id: NotebookId; run: Run }> = ({
       object_path,
     });
   };
+  const inspect = (target: InspectTarget, offset: number) => {
+    inspectObject(notebook_id, run.id, target, offset, dispatch, sendCommand);
+  };
   return (
     <div className="overflow-auto" style={{ height: "calc(100vh - 150px)" }}>
+      <LimitsToolbar notebook_id={notebook_id} run={run} />
       <Scope
         globals={run.globals}
         slotPath=""
+        scope={[]}
         openObjects={run.open_objects}
         toggleOpenObject={toggleOpenObject}
+        inspected={run.inspected ?? {}}
+        inspect={inspect}
       />
     </div>
   );
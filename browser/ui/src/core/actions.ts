@@ -2,6 +2,7 @@ import { Dispatch } from "react";
 import { v4 as uuidv4 } from "uuid";
 import { focusId } from "../components/EditorPanel";
 import { PushNotification } from "../components/NotificationProvider";
+import { InspectTarget, JObjectLimits } from "./jobject";
 import { SendCommand } from "./messages";
 import {
   EditorNode,
@@ -77,7 +78,7 @@ export function runCode(
       run.kernel_state.type === "Closed"
     ) {
       pushNotification(
-        "Kernel for this run is inactive. Start new one.",
+        "Kernel for this run is inactive. Restart it or start new one.",
         "error",
       );
       return;
@@ -130,6 +131,115 @@ export function closeRun(
   });
 }
 
+export function interruptRun(
+  notebook_id: NotebookId,
+  run_id: RunId,
+  sendCommand: SendCommand,
+) {
+  sendCommand({
+    type: "InterruptRun",
+    notebook_id: notebook_id,
+    run_id: run_id,
+  });
+}
+
+export function cancelCompute(
+  notebook_id: NotebookId,
+  run_id: RunId,
+  cell_id: string,
+  sendCommand: SendCommand,
+) {
+  sendCommand({
+    type: "CancelCompute",
+    notebook_id,
+    run_id,
+    cell_id,
+  });
+}
+
+// Size (in bytes) of a part of an omitted output that is loaded at once
+const OUTPUT_PAGE_SIZE = 256 * 1024;
+
+export function fetchOutput(
+  notebook_id: NotebookId,
+  run_id: RunId,
+  cell_id: EditorNodeId,
+  index: number,
+  offset: number,
+  sendCommand: SendCommand,
+) {
+  sendCommand({
+    type: "FetchOutput",
+    notebook_id,
+    run_id,
+    cell_id,
+    index,
+    offset,
+    size: OUTPUT_PAGE_SIZE,
+  });
+}
+
+// Number of children of an inspected object that are loaded at once
+const INSPECT_PAGE_SIZE = 100;
+
+export function inspectObject(
+  notebook_id: NotebookId,
+  run_id: RunId,
+  target: InspectTarget,
+  offset: number,
+  dispatch: Dispatch<StateAction>,
+  sendCommand: SendCommand,
+) {
+  dispatch({ type: "inspect_request", notebook_id, run_id, target });
+  sendCommand({
+    type: "Inspect",
+    notebook_id,
+    run_id,
+    ...target,
+    offset,
+    limit: INSPECT_PAGE_SIZE,
+  });
+}
+
+export function sendInputReply(
+  notebook_id: NotebookId,
+  run_id: RunId,
+  request_id: string,
+  value: string,
+  sendCommand: SendCommand,
+) {
+  sendCommand({
+    type: "InputReply",
+    notebook_id,
+    run_id,
+    request_id,
+    value,
+  });
+}
+
+export function restartKernel(
+  notebook_id: NotebookId,
+  run_id: RunId,
+  sendCommand: SendCommand,
+) {
+  sendCommand({
+    type: "RestartKernel",
+    notebook_id: notebook_id,
+    run_id: run_id,
+  });
+}
+
+export function stopKernel(
+  kernel_id: string,
+  force: boolean,
+  sendCommand: SendCommand,
+) {
+  sendCommand({
+    type: force ? "KillKernel" : "StopKernel",
+    kernel_id,
+  });
+}
+
 export function forkRun(
   notebook_id: NotebookId,
   run: Run,
@@ -239,6 +349,45 @@ export function saveNotebook(
   });
 }
 
+export function setNotebookTimeout(
+  notebook: Notebook,
+  timeout: number | null,
+  dispatch: Dispatch<StateAction>,
+  send_command: SendCommand,
+) {
+  send_command({
+    type: "SetNotebookTimeout",
+    notebook_id: notebook.id,
+    timeout,
+  });
+  dispatch({
+    type: "set_default_timeout",
+    notebook_id: notebook.id,
+    timeout,
+  });
+}
+
+export function setJObjectLimits(
+  notebook_id: NotebookId,
+  run_id: RunId,
+  limits: JObjectLimits,
+  dispatch: Dispatch<StateAction>,
+  send_command: SendCommand,
+) {
+  send_command({
+    type: "SetJObjectLimits",
+    notebook_id,
+    run_id,
+    limits,
+  });
+  dispatch({
+    type: "set_jobject_limits",
+    notebook_id,
+    run_id,
+    limits,
+  });
+}
+
 export function loadNotebook(
   state: State,
   path: string,
@@ -14,6 +14,34 @@ export interface JsonObject {
   value_type?: string;
   kind?: string;
   children?: [string, JsonObjectId][];
+  // Number of all children when only some of them (or none) were sent
+  len?: number;
+  // Number of children after the sent ones that were cut off by limits
+  truncated?: number;
+}
+
+export interface JObjectLimits {
+  max_depth: number;
+  max_children: number;
+  max_objects: number;
+}
+
+// Limits used by the server for runs where they were not changed
+export const DEFAULT_JOBJECT_LIMITS: JObjectLimits = {
+  max_depth: 16,
+  max_children: 10000,
+  max_objects: 100000,
+};
+
+// Object inside of a variable that can be fetched from the kernel
+export interface InspectTarget {
+  scope: string[];
+  variable: string;
+  path: number[];
+}
+
+export function inspectKey(target: InspectTarget): string {
+  return `${target.scope.join("/")}:${target.variable}/${target.path.join("/")}`;
 }
 
 export interface JsonObjectStruct {
@@ -1,8 +1,14 @@
 import { Dispatch } from "react";
+import { JObjectLimits } from "./jobject";
 import {
+  CellTiming,
   EditorGroupNode as EditorGroup,
   EditorNode,
   EditorNodeId,
+  InputRequest,
+  KernelRestart,
+  KernelCrash,
+  KernelInfo,
   KernelState,
   NotebookDesc,
   NotebookId,
@@ -30,7 +36,30 @@ interface KernelCrashedMsg {
   type: "KernelCrashed";
   notebook_id: NotebookId;
   run_id: RunId;
-  message: string;
+  crash: KernelCrash;
+  stderr: string;
+  // New flags of cells that were unfinished when the kernel crashed
+  cell_flags: Record<EditorNodeId, OutputCellFlag>;
+}
+
+interface KernelStateChangedMsg {
+  type: "KernelStateChanged";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  kernel_state: KernelState;
+}
+
+interface KernelClosedMsg {
+  type: "KernelClosed";
+  notebook_id: NotebookId;
+  run_id: RunId;
+}
+
+interface KernelRestartedMsg {
+  type: "KernelRestarted";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  restart: KernelRestart;
 }
 
 export interface SerializedGlobalsUpdate {
@@ -52,10 +81,67 @@ interface OutputMsg {
   cell_id: EditorNodeId;
   flag: OutputCellFlag;
   value: OutputValue;
+  replace_from: number | null;
+  timing: CellTiming;
   update: null | SerializedGlobalsUpdate;
   kernel_state: KernelState;
 }
 
+interface OutputReplacedMsg {
+  type: "OutputReplaced";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  cell_id: EditorNodeId;
+  index: number;
+  value: OutputValue;
+}
+
+interface ComputeCancelledMsg {
+  type: "ComputeCancelled";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  cell_id: string;
+  kernel_state: KernelState;
+}
+
+interface OutputPageMsg {
+  type: "OutputPage";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  cell_id: EditorNodeId;
+  index: number;
+  offset: number;
+  next_offset: number;
+  omitted: number;
+  text: string;
+}
+
+interface InspectResultMsg {
+  type: "InspectResult";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  scope: string[];
+  variable: string;
+  path: number[];
+  offset: number;
+  object: string | null;
+  error: string | null;
+}
+
+interface InputRequestMsg {
+  type: "InputRequest";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  request: InputRequest;
+}
+
+interface InputAnsweredMsg {
+  type: "InputAnswered";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  request_id: string;
+}
+
 interface NewGlobalsMsg {
   type: "NewGlobals";
   notebook_id: NotebookId;
@@ -74,6 +160,16 @@ interface DirList {
   entries: DirEntry[];
 }
 
+interface KernelsUpdateMsg {
+  type: "KernelsUpdate";
+  notebook_id: NotebookId;
+  kernels: KernelInfo[];
+}
+
+interface ServerShutdownMsg {
+  type: "ServerShutdown";
+}
+
 interface Error {
   type: "Error";
   message: string;
@@ -84,10 +180,21 @@ export type ToClientMessage =
   | NewNotebookMsg
   | KernelReadyMsg
   | KernelCrashedMsg
+  | KernelStateChangedMsg
+  | KernelClosedMsg
+  | KernelRestartedMsg
   | OutputMsg
+  | OutputReplacedMsg
+  | ComputeCancelledMsg
+  | OutputPageMsg
+  | InspectResultMsg
+  | InputRequestMsg
+  | InputAnsweredMsg
   | NewGlobalsMsg
   | SaveCompletedMsg
-  | DirList;
+  | DirList
+  | KernelsUpdateMsg
+  | ServerShutdownMsg;
 
 interface CreateNewNotebookMsg {
   type: "CreateNewNotebook";
@@ -114,6 +221,20 @@ interface RunCodeMsg {
   cell_id: EditorNodeId;
   editor_node: EditorNode;
   called_id: EditorNodeId;
+  timeout?: number;
+}
+
+interface SetNotebookTimeoutMsg {
+  type: "SetNotebookTimeout";
+  notebook_id: NotebookId;
+  timeout: number | null;
+}
+
+interface SetJObjectLimitsMsg {
+  type: "SetJObjectLimits";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  limits: JObjectLimits;
 }
 
 interface LoadNotebookMsg {
@@ -127,6 +248,59 @@ interface CloseRunMsg {
   run_id: RunId;
 }
 
+interface InterruptRunMsg {
+  type: "InterruptRun";
+  notebook_id: NotebookId;
+  run_id: RunId;
+}
+
+interface CancelComputeMsg {
+  type: "CancelCompute";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  cell_id: string;
+}
+
+interface FetchOutputMsg {
+  type: "FetchOutput";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  cell_id: EditorNodeId;
+  index: number;
+  offset: number;
+  size: number;
+}
+
+interface InspectMsg {
+  type: "Inspect";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  scope: string[];
+  variable: string;
+  path: number[];
+  offset: number;
+  limit: number;
+}
+
+interface InputReplyMsg {
+  type: "InputReply";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  request_id: string;
+  value: string;
+}
+
+interface RestartKernelMsg {
+  type: "RestartKernel";
+  notebook_id: NotebookId;
+  run_id: RunId;
+}
+
+interface KernelMsg {
+  type: "StopKernel" | "KillKernel";
+  kernel_id: string;
+}
+
 interface ForkRunMsg {
   type: "Fork";
   notebook_id: NotebookId;
@@ -140,9 +314,18 @@ export type FromClientMessage =
   | CreateNewKernelMsg
   | RunCodeMsg
   | CloseRunMsg
+  | InterruptRunMsg
+  | CancelComputeMsg
+  | FetchOutputMsg
+  | InspectMsg
+  | InputReplyMsg
+  | RestartKernelMsg
+  | KernelMsg
   | ForkRunMsg
   | LoadNotebookMsg
-  | SaveNotebookMsg;
+  | SaveNotebookMsg
+  | SetNotebookTimeoutMsg
+  | SetJObjectLimitsMsg;
 
 export function processMessage(
   message: ToClientMessage,
@@ -175,7 +358,39 @@ export function processMessage(
         type: "kernel_changed",
         notebook_id: message.notebook_id,
         run_id: message.run_id,
-        kernel_state: { type: "Crashed", message: message.message },
+        kernel_state: {
+          type: "Crashed",
+          ...message.crash,
+          stderr: message.stderr,
+        },
+        cell_flags: message.cell_flags,
+      });
+      break;
+    }
+    case "KernelStateChanged": {
+      dispatch({
+        type: "kernel_changed",
+        notebook_id: message.notebook_id,
+        run_id: message.run_id,
+        kernel_state: message.kernel_state,
+      });
+      break;
+    }
+    case "KernelClosed": {
+      dispatch({
+        type: "kernel_changed",
+        notebook_id: message.notebook_id,
+        run_id: message.run_id,
+        kernel_state: { type: "Closed" },
+      });
+      break;
+    }
+    case "KernelRestarted": {
+      dispatch({
+        type: "kernel_restarted",
+        notebook_id: message.notebook_id,
+        run_id: message.run_id,
+        restart: message.restart,
       });
       break;
     }
@@ -187,11 +402,84 @@ export function processMessage(
         cell_id: message.cell_id,
         flag: message.flag,
         value: message.value,
+        replace_from: message.replace_from,
+        timing: message.timing,
         update: message.update,
         kernel_state: message.kernel_state,
       });
       break;
     }
+    case "OutputReplaced": {
+      dispatch({
+        type: "output_replaced",
+        notebook_id: message.notebook_id,
+        run_id: message.run_id,
+        cell_id: message.cell_id,
+        index: message.index,
+        value: message.value,
+      });
+      break;
+    }
+    case "OutputPage": {
+      dispatch({
+        type: "output_page",
+        notebook_id: message.notebook_id,
+        run_id: message.run_id,
+        cell_id: message.cell_id,
+        index: message.index,
+        offset: message.offset,
+        page: {
+          text: message.text,
+          next_offset: message.next_offset,
+          omitted: message.omitted,
+        },
+      });
+      break;
+    }
+    case "InspectResult": {
+      dispatch({
+        type: "inspect_result",
+        notebook_id: message.notebook_id,
+        run_id: message.run_id,
+        target: {
+          scope: message.scope,
+          variable: message.variable,
+          path: message.path,
+        },
+        offset: message.offset,
+        object: message.object,
+        error: message.error,
+      });
+      break;
+    }
+    case "InputRequest": {
+      dispatch({
+        type: "input_request",
+        notebook_id: message.notebook_id,
+        run_id: message.run_id,
+        request: message.request,
+      });
+      break;
+    }
+    case "InputAnswered": {
+      dispatch({
+        type: "input_answered",
+        notebook_id: message.notebook_id,
+        run_id: message.run_id,
+        request_id: message.request_id,
+      });
+      break;
+    }
+    case "ComputeCancelled": {
+      dispatch({
+        type: "cell_cancelled",
+        notebook_id: message.notebook_id,
+        run_id: message.run_id,
+        cell_id: message.cell_id,
+        kernel_state: message.kernel_state,
+      });
+      break;
+    }
     case "NewGlobals": {
       dispatch({
         type: "new_globals",
@@ -221,6 +509,18 @@ export function processMessage(
       });
       break;
     }
+    case "KernelsUpdate": {
+      dispatch({
+        type: "kernels_update",
+        notebook_id: message.notebook_id,
+        kernels: message.kernels,
+      });
+      break;
+    }
+    case "ServerShutdown": {
+      pushNotification("Server is shutting down", "error");
+      break;
+    }
     case "Error": {
       pushNotification(message.message, "error");
       break;
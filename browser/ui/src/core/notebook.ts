@@ -1,18 +1,33 @@
-import { JsonObjectStruct } from "./jobject";
+import { JObjectLimits, JsonObjectStruct } from "./jobject";
 import { SerializedGlobals } from "./messages";
 
 export type RunId = string;
 export type NotebookId = number;
 export type EditorNodeId = string;
 
+export interface KernelCrash {
+  message: string;
+  exit_code: number | null;
+  signal: string | null;
+  oom: boolean;
+  cell_id: string | null;
+  time: string | null;
+}
+
 export type KernelState =
-  | { type: "Crashed"; message: string }
+  | ({ type: "Crashed"; stderr?: string } & KernelCrash)
   | { type: "Init" }
   | { type: "Ready" }
   | { type: "Running" }
+  | { type: "Unresponsive" }
   | { type: "Closed" };
 
-export type OutputCellFlag = "Pending" | "Running" | "Success" | "Fail";
+export type OutputCellFlag =
+  | "Pending"
+  | "Running"
+  | "Success"
+  | "Fail"
+  | "Cancelled";
 
 export interface EditorCell {
   type: "Cell";
@@ -35,9 +50,45 @@ export interface EditorGroupNode {
 
 export type EditorNode = EditorGroupNode | EditorCell;
 
+// Index into the 256 colour palette or RGB
+export type TextColor = number | [number, number, number];
+
+// Style of a part of a stream, offsets are in characters (code points)
+export interface StyleSpan {
+  start: number;
+  end: number;
+  fg?: TextColor;
+  bg?: TextColor;
+  bold?: boolean;
+  dim?: boolean;
+  italic?: boolean;
+  underline?: boolean;
+}
+
+// Middle part of a long text that was moved into the spool on the server,
+// `offset` is in characters, `omitted` in bytes
+export interface Truncation {
+  offset: number;
+  omitted: number;
+  spool: string;
+}
+
+export interface StreamOutputValue {
+  type: "Stream";
+  name: "stdout" | "stderr";
+  value: string;
+  spans?: StyleSpan[];
+  truncated?: Truncation;
+  // Number of code points of the value, it is cached by the client for merging chunks
+  length?: number;
+}
+
 export interface TextOutputValue {
   type: "Text";
   value: string;
+  truncated?: Truncation;
+  // MIME types of rich representations dropped because they were over the output limit
+  dropped?: string[];
 }
 
 export interface HtmlOutputValue {
@@ -45,6 +96,12 @@ export interface HtmlOutputValue {
   value: string;
 }
 
+// Representations keyed by MIME type, binary data are encoded by base64
+export interface MimeBundleOutputValue {
+  type: "MimeBundle";
+  value: Record<string, string>;
+}
+
 export interface ExceptionOutputValue {
   type: "Exception";
   value: {
@@ -53,20 +110,53 @@ export interface ExceptionOutputValue {
   };
 }
 
+// Warning or record of the logging module, `level` is as in the logging module
+export interface LogOutputValue {
+  type: "Log";
+  value: {
+    kind: "Warning" | "Logging";
+    category: string;
+    level: number;
+    logger: string | null;
+    message: string;
+    source: {
+      filename: string;
+      lineno: number;
+      cell_id: EditorNodeId | null;
+    } | null;
+  };
+}
+
 export type OutputValue =
+  | StreamOutputValue
   | TextOutputValue
   | HtmlOutputValue
+  | MimeBundleOutputValue
   | ExceptionOutputValue
+  | LogOutputValue
   | { type: "None" };
 
+export interface CellTiming {
+  queued: string | null;
+  started: string | null;
+  finished: string | null;
+  duration_ms: number | null;
+}
+
 export interface OutputCell {
   id: EditorNodeId;
   values: OutputValue[];
   flag: OutputCellFlag;
+  timing?: CellTiming;
   editor_node: EditorNode;
   called_id: EditorNodeId;
 }
 
+export interface KernelRestart {
+  position: number;
+  time: string;
+}
+
 export type RunViewMode = "outputs" | "workspace";
 
 export interface Globals {
@@ -80,9 +170,54 @@ export interface Run {
   title: string;
   kernel_state: KernelState;
   output_cells: OutputCell[];
+  kernel_restarts: KernelRestart[];
   view_mode: RunViewMode;
   globals: Globals;
   open_objects: Set<string>;
+  kernel_info?: KernelInfo;
+  input_request?: InputRequest | null;
+  // Loaded parts of omitted texts, keyed by "<cell_id>/<index of value>"
+  output_pages?: Record<string, OutputPage>;
+  // Lazily loaded children of objects in globals, keyed by inspectKey()
+  inspected?: Record<string, InspectedObject>;
+  jobject_limits?: JObjectLimits;
+}
+
+export interface InspectedObject {
+  value_type?: string;
+  // Each child is in the dump of the page where it was loaded
+  children: [string, JsonObjectStruct][];
+  len: number;
+  truncated?: number;
+  loading: boolean;
+  error?: string;
+}
+
+export interface OutputPage {
+  text: string;
+  next_offset: number;
+  omitted: number;
+}
+
+export interface InputRequest {
+  cell_id: string;
+  request_id: string;
+  prompt: string;
+  password: boolean;
+}
+
+export interface KernelInfo {
+  kernel_id: string;
+  notebook_id: NotebookId;
+  run_id: RunId;
+  pid: number;
+  capabilities: string[];
+  uptime: number;
+  rss: number | null;
+  cpu_time: number | null;
+  queue: number;
+  state: KernelState;
+  stopping: boolean;
 }
 
 export interface Notebook {
@@ -95,6 +230,7 @@ export interface Notebook {
   current_run_id: RunId | null;
   selected_editor_node_id: EditorNodeId | null;
   save_in_progress: boolean;
+  default_timeout: number | null;
 }
 
 export interface NotebookDesc {
@@ -103,6 +239,7 @@ export interface NotebookDesc {
   editor_open_nodes: string[];
   runs: RunDesc[];
   path: string;
+  default_timeout: number | null;
 }
 
 export interface RunDesc {
@@ -110,5 +247,7 @@ export interface RunDesc {
   title: string;
   kernel_state: KernelState;
   output_cells: OutputCell[];
+  kernel_restarts: KernelRestart[];
   globals: SerializedGlobals;
+  jobject_limits: JObjectLimits;
 }
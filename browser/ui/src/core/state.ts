@@ -1,20 +1,31 @@
-import { applyGlobalsUpdate } from "./jobject";
+import {
+  applyGlobalsUpdate,
+  InspectTarget,
+  inspectKey,
+  JObjectLimits,
+  JsonObjectStruct,
+  parseJsonObjectStruct,
+} from "./jobject";
 import { SerializedGlobals, SerializedGlobalsUpdate } from "./messages";
 import {
+  CellTiming,
   EditorGroupNode,
   EditorNode,
   EditorNodeId,
+  InputRequest,
+  KernelInfo,
+  KernelRestart,
   KernelState,
   Notebook,
   NotebookDesc,
   NotebookId,
   OutputCell,
   OutputCellFlag,
+  OutputPage,
   OutputValue,
   Run,
   RunId,
   RunViewMode,
-  TextOutputValue,
 } from "./notebook";
 
 interface SetSelectedNotebookAction {
@@ -46,6 +57,14 @@ interface KernelStateChangedAction {
   notebook_id: NotebookId;
   run_id: RunId;
   kernel_state: KernelState;
+  cell_flags?: Record<EditorNodeId, OutputCellFlag>;
+}
+
+interface KernelRestartedAction {
+  type: "kernel_restarted";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  restart: KernelRestart;
 }
 
 interface NewOutputAction {
@@ -55,10 +74,70 @@ interface NewOutputAction {
   cell_id: EditorNodeId;
   flag: OutputCellFlag;
   value: OutputValue;
+  replace_from: number | null;
+  timing: CellTiming;
   update: null | SerializedGlobalsUpdate;
   kernel_state: KernelState;
 }
 
+interface OutputReplacedAction {
+  type: "output_replaced";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  cell_id: EditorNodeId;
+  index: number;
+  value: OutputValue;
+}
+
+interface CellCancelledAction {
+  type: "cell_cancelled";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  cell_id: string;
+  kernel_state: KernelState;
+}
+
+interface OutputPageAction {
+  type: "output_page";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  cell_id: EditorNodeId;
+  index: number;
+  offset: number;
+  page: OutputPage;
+}
+
+interface InspectRequestAction {
+  type: "inspect_request";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  target: InspectTarget;
+}
+
+interface InspectResultAction {
+  type: "inspect_result";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  target: InspectTarget;
+  offset: number;
+  object: string | null;
+  error: string | null;
+}
+
+interface InputRequestAction {
+  type: "input_request";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  request: InputRequest;
+}
+
+interface InputAnsweredAction {
+  type: "input_answered";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  request_id: string;
+}
+
 interface SetCurrentRunAction {
   type: "set_current_run";
   notebook_id: NotebookId;
@@ -84,6 +163,19 @@ interface SaveNotebookAction {
   save_in_progress: boolean;
 }
 
+interface SetDefaultTimeoutAction {
+  type: "set_default_timeout";
+  notebook_id: NotebookId;
+  timeout: number | null;
+}
+
+interface SetJObjectLimitsAction {
+  type: "set_jobject_limits";
+  notebook_id: NotebookId;
+  run_id: RunId;
+  limits: JObjectLimits;
+}
+
 interface ToggleEditorNode {
   type: "toggle_editor_node";
   notebook_id: NotebookId;
@@ -148,11 +240,25 @@ interface NewGlobals {
   globals: SerializedGlobals;
 }
 
+interface KernelsUpdateAction {
+  type: "kernels_update";
+  notebook_id: NotebookId;
+  kernels: KernelInfo[];
+}
+
 export type StateAction =
   | AddNotebookAction
   | FreshRunAction
   | KernelStateChangedAction
+  | KernelRestartedAction
   | NewOutputAction
+  | OutputReplacedAction
+  | CellCancelledAction
+  | InputRequestAction
+  | InputAnsweredAction
+  | OutputPageAction
+  | InspectRequestAction
+  | InspectResultAction
   | NewOutputCellAction
   | SetCurrentRunAction
   | SetRunViewModeAction
@@ -161,12 +267,15 @@ export type StateAction =
   | SetSelectedNotebookAction
   | SetDirEntries
   | SaveNotebookAction
+  | SetDefaultTimeoutAction
+  | SetJObjectLimitsAction
   | CloseRunAction
   | ToggleEditorNode
   | ToggleOpenObjectAction
   | UpdateEditorNode
   | RemoveEditorNode
   | NewGlobals
+  | KernelsUpdateAction
   | SetDialog;
 
 export interface DialogConfig {
@@ -201,6 +310,32 @@ function updateNotebooks(state: State, notebook: Notebook): State {
   };
 }
 
+// Offsets of stream outputs are in code points, i.e. surrogate pairs count as one
+function codePointLength(text: string): number {
+  let length = text.length;
+  for (let i = 0; i < text.length; i++) {
+    const c = text.charCodeAt(i);
+    if (c >= 0xdc00 && c <= 0xdfff) {
+      length--;
+    }
+  }
+  return length;
+}
+
+// Keeps the first `from` code points of a text with `length` code points;
+// it walks from the end, so cutting a short tail of a long text is cheap
+function cutCodePoints(text: string, length: number, from: number): string {
+  if (from === 0) {
+    return "";
+  }
+  let end = text.length;
+  for (let i = from; i < length; i++) {
+    const c = text.charCodeAt(end - 1);
+    end -= c >= 0xdc00 && c <= 0xdfff ? 2 : 1;
+  }
+  return text.slice(0, end);
+}
+
 function getEditorNode(
   node: EditorGroupNode,
   path: EditorNodeId[],
@@ -239,6 +374,17 @@ function updateEditor(
   };
 }
 
+// Server fails the running cell and cancels queued cells when their kernel is gone
+function failUnfinishedCell(cell: OutputCell): OutputCell {
+  if (cell.flag == "Running") {
+    return { ...cell, flag: "Fail" };
+  }
+  if (cell.flag == "Pending") {
+    return { ...cell, flag: "Cancelled" };
+  }
+  return cell;
+}
+
 export function stateReducer(state: State, action: StateAction): State {
   console.log("action", action);
   switch (action.type) {
@@ -352,6 +498,7 @@ export function stateReducer(state: State, action: StateAction): State {
         current_run_id: runs.length > 0 ? runs[0].id : null,
         selected_editor_node_id: null,
         save_in_progress: false,
+        default_timeout: action.notebook.default_timeout,
         globals: [],
         path,
       } as Notebook;
@@ -402,6 +549,7 @@ export function stateReducer(state: State, action: StateAction): State {
             title: action.run_title,
             kernel_state: { type: "Init" },
             output_cells: [],
+            kernel_restarts: [],
             kernel_state_message: null,
             globals: { name: "", variables: [], children: [] },
             view_mode: "outputs",
@@ -430,6 +578,27 @@ export function stateReducer(state: State, action: StateAction): State {
                 kernel_state: action.kernel_state,
                 output_cells,
               } as Run;
+            } else if (action.kernel_state.type == "Closed") {
+              const output_cells = r.output_cells.map(failUnfinishedCell);
+              return {
+                ...r,
+                kernel_state: action.kernel_state,
+                kernel_info: undefined,
+                output_cells,
+              } as Run;
+            } else if (action.kernel_state.type == "Crashed") {
+              const cell_flags = action.cell_flags ?? {};
+              const output_cells = r.output_cells.map((c) =>
+                cell_flags[c.id]
+                  ? ({ ...c, flag: cell_flags[c.id] } as OutputCell)
+                  : c,
+              );
+              return {
+                ...r,
+                kernel_state: action.kernel_state,
+                kernel_info: undefined,
+                output_cells,
+              } as Run;
             } else {
               return {
                 ...r,
@@ -443,6 +612,30 @@ export function stateReducer(state: State, action: StateAction): State {
       };
       return updateNotebooks(state, new_notebook);
     }
+    case "kernel_restarted": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) => {
+          if (r.id == action.run_id) {
+            const output_cells = r.output_cells.map(failUnfinishedCell);
+            return {
+              ...r,
+              kernel_state: { type: "Init" },
+              output_cells,
+              kernel_restarts: [...r.kernel_restarts, action.restart],
+              kernel_info: undefined,
+              input_request: null,
+              globals: { name: "", variables: [], children: [] },
+              open_objects: new Set(),
+            } as Run;
+          } else {
+            return r;
+          }
+        }),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
     case "new_output_cell": {
       const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
       const new_notebook = {
@@ -467,28 +660,52 @@ export function stateReducer(state: State, action: StateAction): State {
         runs: notebook.runs.map((r) => {
           if (r.id == action.run_id) {
             let finished = action.flag == "Success" || action.flag == "Fail";
+            const input_request =
+              finished && r.input_request?.cell_id === action.cell_id
+                ? null
+                : r.input_request;
             const output_cells = r.output_cells.map((c) => {
               if (c.id === action.cell_id) {
                 let values;
+                const last = c.values[c.values.length - 1];
                 if (
-                  action.value.type == "Text" &&
-                  c.values.length > 0 &&
-                  c.values[c.values.length - 1].type == "Text"
+                  action.replace_from !== null &&
+                  action.value.type == "Stream" &&
+                  last?.type == "Stream"
                 ) {
-                  // Concatenate text values if both are Text type
+                  // Chunk of the same stream rewrites the end of the last value
+                  const length = last.length ?? codePointLength(last.value);
+                  const from = Math.min(action.replace_from, length);
+                  const spans = last.spans ?? [];
+                  let keep = spans.length;
+                  while (keep > 0 && spans[keep - 1].start >= from) {
+                    keep--;
+                  }
+                  const kept = spans.slice(0, keep);
+                  if (keep > 0 && kept[keep - 1].end > from) {
+                    kept[keep - 1] = { ...kept[keep - 1], end: from };
+                  }
                   values = [
                     ...c.values.slice(0, -1),
                     {
-                      type: "Text",
+                      ...last,
                       value:
-                        (c.values[c.values.length - 1] as TextOutputValue)
-                          .value + (action.value as TextOutputValue).value,
+                        cutCodePoints(last.value, length, from) +
+                        action.value.value,
+                      length: from + codePointLength(action.value.value),
+                      spans: [...kept, ...(action.value.spans ?? [])],
+                      truncated: action.value.truncated ?? last.truncated,
                     },
                   ];
                 } else {
                   values = [...c.values, action.value];
                 }
-                return { ...c, flag: action.flag, values } as OutputCell;
+                return {
+                  ...c,
+                  flag: action.flag,
+                  timing: action.timing,
+                  values,
+                } as OutputCell;
               }
               if (finished && c.flag == "Pending") {
                 finished = false;
@@ -498,13 +715,18 @@ export function stateReducer(state: State, action: StateAction): State {
               }
             });
             let globals = r.globals;
+            let inspected = r.inspected;
             if (action.update) {
               globals = applyGlobalsUpdate(action.update, r.globals);
+              // Loaded children may be outdated, opened objects fetch them again
+              inspected = undefined;
             }
             return {
               ...r,
               globals,
+              inspected,
               output_cells,
+              input_request,
               kernel_state: action.kernel_state,
             } as Run;
           } else {
@@ -524,6 +746,7 @@ export function stateReducer(state: State, action: StateAction): State {
             return {
               ...r,
               globals,
+              inspected: undefined,
             } as Run;
           } else {
             return r;
@@ -532,6 +755,177 @@ export function stateReducer(state: State, action: StateAction): State {
       };
       return updateNotebooks(state, new_notebook);
     }
+    case "output_replaced": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) =>
+          r.id == action.run_id
+            ? ({
+                ...r,
+                output_cells: r.output_cells.map((c) =>
+                  c.id === action.cell_id
+                    ? ({
+                        ...c,
+                        values: c.values.map((v, i) =>
+                          i === action.index ? action.value : v,
+                        ),
+                      } as OutputCell)
+                    : c,
+                ),
+              } as Run)
+            : r,
+        ),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
+    case "output_page": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const key = `${action.cell_id}/${action.index}`;
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) => {
+          if (r.id != action.run_id) {
+            return r;
+          }
+          const loaded = r.output_pages?.[key];
+          // Pages are appended in order, a repeated reply is ignored
+          if ((loaded?.next_offset ?? 0) !== action.offset) {
+            return r;
+          }
+          return {
+            ...r,
+            output_pages: {
+              ...r.output_pages,
+              [key]: {
+                ...action.page,
+                text: (loaded?.text ?? "") + action.page.text,
+              },
+            },
+          } as Run;
+        }),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
+    case "inspect_request": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const key = inspectKey(action.target);
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) => {
+          if (r.id != action.run_id) {
+            return r;
+          }
+          const loaded = r.inspected?.[key] ?? { children: [], len: 0 };
+          return {
+            ...r,
+            inspected: {
+              ...r.inspected,
+              [key]: { ...loaded, loading: true, error: undefined },
+            },
+          } as Run;
+        }),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
+    case "inspect_result": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const key = inspectKey(action.target);
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) => {
+          const loaded = r.inspected?.[key];
+          // Reply to a request made before globals were updated is ignored
+          if (r.id != action.run_id || !loaded) {
+            return r;
+          }
+          let entry;
+          if (action.object === null) {
+            entry = { ...loaded, loading: false, error: action.error! };
+          } else if (loaded.children.length !== action.offset) {
+            entry = { ...loaded, loading: false };
+          } else {
+            const struct = parseJsonObjectStruct(action.object);
+            const root = struct.objects.get(struct.root)!;
+            const children = (root.children ?? []).map(
+              ([name, id]) =>
+                [name, { ...struct, root: id }] as [string, JsonObjectStruct],
+            );
+            entry = {
+              value_type: root.value_type,
+              children: [...loaded.children, ...children],
+              len: root.len ?? action.offset + children.length,
+              truncated: root.truncated,
+              loading: false,
+            };
+          }
+          return {
+            ...r,
+            inspected: { ...r.inspected, [key]: entry },
+          } as Run;
+        }),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
+    case "input_request": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) =>
+          r.id == action.run_id
+            ? ({ ...r, input_request: action.request } as Run)
+            : r,
+        ),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
+    case "input_answered": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) =>
+          r.id == action.run_id &&
+          r.input_request?.request_id === action.request_id
+            ? ({ ...r, input_request: null } as Run)
+            : r,
+        ),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
+    case "cell_cancelled": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) =>
+          r.id == action.run_id
+            ? ({
+                ...r,
+                kernel_state: action.kernel_state,
+                output_cells: r.output_cells.map((c) =>
+                  c.id === action.cell_id
+                    ? ({ ...c, flag: "Cancelled" } as OutputCell)
+                    : c,
+                ),
+              } as Run)
+            : r,
+        ),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
+    case "kernels_update": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id);
+      if (!notebook) {
+        return state;
+      }
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) => ({
+          ...r,
+          kernel_info: action.kernels.find((k) => k.run_id == r.id),
+        })),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
 
     case "set_current_run": {
       const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
@@ -571,6 +965,31 @@ export function stateReducer(state: State, action: StateAction): State {
       };
       return updateNotebooks(state, new_notebook);
     }
+    case "set_default_timeout": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const new_notebook = {
+        ...notebook,
+        default_timeout: action.timeout,
+      };
+      return updateNotebooks(state, new_notebook);
+    }
+    case "set_jobject_limits": {
+      const notebook = state.notebooks.find((n) => n.id == action.notebook_id)!;
+      const new_notebook = {
+        ...notebook,
+        runs: notebook.runs.map((r) =>
+          r.id == action.run_id
+            ? ({
+                ...r,
+                jobject_limits: action.limits,
+                // Opened objects are fetched again with the new limits
+                inspected: undefined,
+              } as Run)
+            : r,
+        ),
+      };
+      return updateNotebooks(state, new_notebook);
+    }
     case "set_dir_entries": {
       return {
         ...state,
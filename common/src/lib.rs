@@ -8,6 +8,7 @@ use tokio_util::codec::{Framed, LengthDelimitedCodec};
 pub type Codec = Framed<TcpStream, LengthDelimitedCodec>;
 
 pub use protocol::{
-    make_protocol_builder, parse_from_kernel_message, parse_to_kernel_message,
-    serialize_from_kernel_message, serialize_to_kernel_message,
+    KERNEL_CAPABILITIES, PROTOCOL_VERSION, make_protocol_builder, parse_from_kernel_message,
+    parse_login_header, parse_to_kernel_message, serialize_from_kernel_message,
+    serialize_to_kernel_message,
 };
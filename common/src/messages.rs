@@ -1,6 +1,8 @@
-use crate::scopes::{SerializedGlobals, SerializedGlobalsUpdate};
+use crate::scopes::{ScopeId, SerializedGlobals, SerializedGlobalsUpdate};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::time::SystemTime;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,11 +41,70 @@ pub struct ComputeMsg {
     pub code: CodeGroup,
 }
 
+/// Limits of serialization of objects into jobjects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JObjectLimits {
+    /// Children of objects nested deeper in a variable are not serialized
+    pub max_depth: usize,
+    /// Children of a container after this count are not serialized
+    pub max_children: usize,
+    /// Number of objects serialized at once
+    pub max_objects: usize,
+}
+
+impl Default for JObjectLimits {
+    fn default() -> Self {
+        JObjectLimits {
+            max_depth: 16,
+            max_children: 10_000,
+            max_objects: 100_000,
+        }
+    }
+}
+
+/// Request for a page of children of an object in globals
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InspectMsg {
+    pub request_id: Uuid,
+    /// Ids of scopes from the root scope to the scope of the variable
+    pub scope: Vec<ScopeId>,
+    pub variable: String,
+    /// Indices of children from the variable to the inspected object
+    pub path: Vec<usize>,
+    pub offset: usize,
+    pub limit: usize,
+    pub limits: JObjectLimits,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ToKernelMessage {
     Compute(ComputeMsg),
     SaveState(PathBuf),
     LoadState(PathBuf),
+    /// Interrupts the running computation, if `cell_id` is set, only when that cell is running
+    Interrupt {
+        cell_id: Option<Uuid>,
+    },
+    Shutdown,
+    /// Kernel answers by `FromKernelMessage::HeartbeatResponse` with the same value
+    Heartbeat(u64),
+    /// Answer to `FromKernelMessage::InputRequest`
+    InputReply {
+        request_id: Uuid,
+        value: InputValue,
+    },
+    /// Kernel answers by `FromKernelMessage::InspectResponse` when no cell is computed
+    Inspect(InspectMsg),
+}
+
+/// Text entered by the user, it is never printed into logs as it may be a password
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InputValue(pub String);
+
+impl std::fmt::Debug for InputValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("InputValue(..)")
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -67,12 +128,52 @@ impl OutputFlag {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Exception {
     pub message: String,
     pub traceback: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamName {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LogKind {
+    /// Produced by `warnings.warn`
+    Warning,
+    /// Record of the `logging` module
+    Logging,
+}
+
+/// Place in code where a warning or a log record was produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub filename: String,
+    pub lineno: u32,
+    /// Editor cell when the code comes from the notebook
+    pub cell_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogRecord {
+    pub kind: LogKind,
+    /// Category of a warning (e.g. `DeprecationWarning`) or name of a logging level
+    pub category: String,
+    /// Level as in the `logging` module, warnings have `logging.WARNING`
+    pub level: u32,
+    /// Name of the logger, `None` for warnings
+    pub logger: Option<String>,
+    pub message: String,
+    pub source: Option<SourceLocation>,
+}
+
+/// Representations of a value keyed by MIME type, binary data are encoded by base64
+pub type MimeBundle = BTreeMap<String, String>;
+
 /*
    We are using different output value for in kernel and from kernel communication
    because bincode breaks when serde(tag = ...) is used on this enum,
@@ -80,22 +181,55 @@ pub struct Exception {
 */
 #[derive(Debug, Serialize, Deserialize)]
 pub enum KernelOutputValue {
-    Text { value: String },
-    Html { value: String },
-    Exception { value: Exception },
+    /// Text written into a standard stream
+    Stream {
+        name: StreamName,
+        value: String,
+    },
+    Text {
+        value: String,
+    },
+    Html {
+        value: String,
+    },
+    MimeBundle {
+        value: MimeBundle,
+    },
+    Exception {
+        value: Exception,
+    },
+    /// Warning or log record emitted during the computation
+    Log {
+        value: LogRecord,
+    },
     None,
 }
 
+/// Prefix of `FromKernelMessage::Login` that is stable between protocol versions
+#[derive(Debug, Deserialize)]
+pub struct LoginHeader {
+    pub protocol_version: u32,
+    pub kernel_id: Uuid,
+    pub secret: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum FromKernelMessage {
+    /// The first message sent by kernel. Fields of `LoginHeader` have to stay at the beginning
+    /// in all protocol versions, so the server can always read them.
     Login {
+        protocol_version: u32,
         kernel_id: Uuid,
+        secret: String,
+        capabilities: Vec<String>,
     },
     Output {
         value: KernelOutputValue,
         cell_id: Uuid,
         flag: OutputFlag,
         update: Option<SerializedGlobalsUpdate>,
+        /// Time when the computation ended, it is set only for the final output of the cell
+        finished: Option<SystemTime>,
     },
     SaveStateResponse {
         path: PathBuf,
@@ -105,4 +239,30 @@ pub enum FromKernelMessage {
         path: PathBuf,
         result: Result<SerializedGlobals, String>,
     },
+    HeartbeatResponse(u64),
+    /// Executor has started the computation of the cell
+    ComputeStarted {
+        cell_id: Uuid,
+        time: SystemTime,
+    },
+    /// Rich output displayed during the computation of the cell,
+    /// when `update` is set, it replaces earlier outputs with the same `display_id`
+    Display {
+        cell_id: Uuid,
+        value: MimeBundle,
+        display_id: Option<String>,
+        update: bool,
+    },
+    /// Executor waits for a line of text from the user (`input()`, `getpass()`)
+    InputRequest {
+        cell_id: Uuid,
+        request_id: Uuid,
+        prompt: String,
+        password: bool,
+    },
+    /// Serialized inspected object (jobject dump) or an error
+    InspectResponse {
+        request_id: Uuid,
+        result: Result<String, String>,
+    },
 }
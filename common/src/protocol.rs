@@ -1,7 +1,26 @@
-use crate::messages::{FromKernelMessage, ToKernelMessage};
+use crate::messages::{FromKernelMessage, LoginHeader, ToKernelMessage};
+use anyhow::bail;
 use tokio_util::codec::LengthDelimitedCodec;
 use tokio_util::codec::length_delimited::Builder;
 
+/// Version of the protocol between server and kernel,
+/// it has to be increased on every incompatible change of the messages
+pub const PROTOCOL_VERSION: u32 = 11;
+
+/// Features supported by kernels built from this version
+pub const KERNEL_CAPABILITIES: &[&str] = &[
+    "interrupt",
+    "shutdown",
+    "save_state",
+    "load_state",
+    "heartbeat",
+    "input",
+    "inspect",
+];
+
+/// Variant index of `FromKernelMessage::Login` in the serialized message
+const LOGIN_VARIANT: u32 = 0;
+
 pub fn make_protocol_builder() -> Builder {
     *LengthDelimitedCodec::builder()
         .little_endian()
@@ -19,11 +38,20 @@ pub fn parse_to_kernel_message(data: &[u8]) -> bincode::Result<ToKernelMessage>
 }
 
 #[inline]
-pub fn serialize_from_kernel_message(message: FromKernelMessage) -> bincode::Result<Vec<u8>> {
-    bincode::serialize(&message)
+pub fn serialize_from_kernel_message(message: &FromKernelMessage) -> bincode::Result<Vec<u8>> {
+    bincode::serialize(message)
 }
 
 #[inline]
 pub fn serialize_to_kernel_message(message: ToKernelMessage) -> bincode::Result<Vec<u8>> {
     bincode::serialize(&message)
 }
+
+/// Reads the stable prefix of login message, it works for messages from all protocol versions
+pub fn parse_login_header(data: &[u8]) -> anyhow::Result<LoginHeader> {
+    let (variant, header): (u32, LoginHeader) = bincode::deserialize(data)?;
+    if variant != LOGIN_VARIANT {
+        bail!("Invalid first message");
+    }
+    Ok(header)
+}
@@ -0,0 +1,182 @@
+import base64
+import contextlib
+import importlib.util
+import json
+import sys
+import types
+import uuid
+
+from twinsong.driver.display import mime_bundle
+from twinsong.driver.hooks import on_import
+
+__all__ = [
+    "display",
+    "update_display",
+    "DisplayHandle",
+    "DisplayObject",
+    "HTML",
+    "Markdown",
+    "Latex",
+    "SVG",
+    "JSON",
+    "Image",
+]
+
+# Output of the cell that is currently computed by the executor
+_cell_output = None
+
+
+@contextlib.contextmanager
+def cell_output(output):
+    global _cell_output
+    old = _cell_output
+    _cell_output = output
+    try:
+        yield
+    finally:
+        _cell_output = old
+
+
+def _send(bundle, display_id, update):
+    if _cell_output is None:
+        # Outside of a computation (e.g. in a background thread after the cell ended)
+        print(bundle.get("text/plain", ""))
+        return
+    _cell_output.display(bundle, display_id, update)
+
+
+def _create_bundle(obj, raw, include, exclude):
+    bundle = dict(obj) if raw else mime_bundle(obj)
+    if include is not None:
+        bundle = {k: v for k, v in bundle.items() if k in include}
+    if exclude is not None:
+        bundle = {k: v for k, v in bundle.items() if k not in exclude}
+    return bundle
+
+
+def display(*objs, include=None, exclude=None, raw=False, display_id=None, **kwargs):
+    """Shows objects in the output of the current cell.
+
+    If `display_id` is given (or True for a generated one), the output can be later
+    replaced by `update_display` and a `DisplayHandle` is returned.
+    """
+    if display_id is True:
+        display_id = uuid.uuid4().hex
+    for obj in objs:
+        _send(_create_bundle(obj, raw, include, exclude), display_id, False)
+    if display_id is not None:
+        return DisplayHandle(display_id)
+
+
+def update_display(obj, *, display_id, include=None, exclude=None, raw=False, **kwargs):
+    """Replaces all outputs that were displayed with `display_id`"""
+    _send(_create_bundle(obj, raw, include, exclude), display_id, True)
+
+
+class DisplayHandle:
+    def __init__(self, display_id=None):
+        if display_id is None:
+            display_id = uuid.uuid4().hex
+        self.display_id = display_id
+
+    def __repr__(self):
+        return f"<DisplayHandle display_id={self.display_id}>"
+
+    def display(self, obj, **kwargs):
+        display(obj, display_id=self.display_id, **kwargs)
+
+    def update(self, obj, **kwargs):
+        update_display(obj, display_id=self.display_id, **kwargs)
+
+
+class DisplayObject:
+    _binary = False
+
+    def __init__(self, data=None, url=None, filename=None):
+        if filename is not None:
+            with open(filename, "rb" if self._binary else "r") as f:
+                data = f.read()
+        self.data = data
+        self.url = url
+
+    def __repr__(self):
+        return f"<{self.__class__.__module__}.{self.__class__.__name__} object>"
+
+
+class HTML(DisplayObject):
+    def _repr_html_(self):
+        return self.data
+
+
+class Markdown(DisplayObject):
+    def _repr_markdown_(self):
+        return self.data
+
+
+class Latex(DisplayObject):
+    def _repr_latex_(self):
+        return self.data
+
+
+class SVG(DisplayObject):
+    def _repr_svg_(self):
+        return self.data
+
+
+class JSON(DisplayObject):
+    def _repr_json_(self):
+        if isinstance(self.data, str):
+            return json.loads(self.data)
+        return self.data
+
+
+class Image(DisplayObject):
+    _binary = True
+
+    def __init__(self, data=None, url=None, filename=None, format=None, **kwargs):
+        if format is None:
+            name = filename or url or ""
+            format = "jpeg" if name.lower().endswith((".jpg", ".jpeg")) else "png"
+        self.format = "jpeg" if format.lower() == "jpg" else format.lower()
+        super().__init__(data, url, filename)
+
+    def _repr_mimebundle_(self, include=None, exclude=None):
+        if self.data is None:
+            return {"text/html": f'<img src="{self.url}"/>'}
+        data = self.data
+        if isinstance(data, (bytes, bytearray)):
+            data = base64.b64encode(data).decode("ascii")
+        return {f"image/{self.format}": data}
+
+
+# Modules of IPython that provide `display` and `update_display`
+IPYTHON_DISPLAY_MODULES = [
+    "IPython.core.display_functions",
+    "IPython.core.display",
+    "IPython.display",
+]
+
+
+def _patch_ipython_display(module):
+    # IPython publishes outputs only inside its own shell, otherwise it just prints them
+    if hasattr(module, "display"):
+        module.display = display
+    if hasattr(module, "update_display"):
+        module.update_display = update_display
+
+
+def install_ipython_shim():
+    """Makes `IPython.display` show objects in cell outputs.
+
+    Functions of an installed IPython are replaced once it is imported,
+    a module with the API of `IPython.display` is provided when IPython is not installed.
+    """
+    if importlib.util.find_spec("IPython") is not None:
+        for name in IPYTHON_DISPLAY_MODULES:
+            on_import(name, _patch_ipython_display)
+        return
+    ipython = types.ModuleType("IPython")
+    ipython.display = sys.modules[__name__]
+    ipython.get_ipython = lambda: None
+    sys.modules["IPython"] = ipython
+    sys.modules["IPython.display"] = sys.modules[__name__]
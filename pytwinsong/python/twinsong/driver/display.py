@@ -0,0 +1,80 @@
+import base64
+import inspect
+import json
+import sys
+
+from twinsong.driver.hooks import on_import
+
+MATPLOTLIB_BACKEND = "module://twinsong.driver.mpl_backend"
+
+# Rich representations of objects in the order of their preference
+REPR_METHODS = [
+    ("text/html", "_repr_html_"),
+    ("text/markdown", "_repr_markdown_"),
+    ("text/latex", "_repr_latex_"),
+    ("image/svg+xml", "_repr_svg_"),
+    ("image/png", "_repr_png_"),
+    ("image/jpeg", "_repr_jpeg_"),
+    ("application/json", "_repr_json_"),
+]
+
+
+def _encode(mime, value):
+    # Binary data are base64 encoded, structured data are serialized as JSON text
+    if isinstance(value, (bytes, bytearray)):
+        return base64.b64encode(value).decode("ascii")
+    if mime == "application/json" or not isinstance(value, str):
+        return json.dumps(value)
+    return value
+
+
+def _strip_metadata(value):
+    # Repr methods may return a pair (data, metadata)
+    if isinstance(value, tuple) and len(value) == 2:
+        return value[0]
+    return value
+
+
+def mime_bundle(obj):
+    bundle = {}
+    # Methods accessed on classes are unbound, so only instances are asked
+    if not inspect.isclass(obj):
+        repr_mimebundle = getattr(obj, "_repr_mimebundle_", None)
+        if callable(repr_mimebundle):
+            data = _strip_metadata(repr_mimebundle(include=None, exclude=None))
+            for mime, value in (data or {}).items():
+                if value is not None:
+                    bundle[mime] = _encode(mime, value)
+        for mime, name in REPR_METHODS:
+            method = getattr(obj, name, None)
+            if mime in bundle or not callable(method):
+                continue
+            value = _strip_metadata(method())
+            if value is not None:
+                bundle[mime] = _encode(mime, value)
+    if "text/plain" not in bundle:
+        bundle["text/plain"] = repr(obj)
+    return bundle
+
+
+def _set_matplotlib_backend(matplotlib):
+    # Backend chosen by the user (MPLBACKEND or matplotlibrc) replaces the sentinel
+    sentinel = getattr(matplotlib.rcsetup, "_auto_backend_sentinel", None)
+    if dict.__getitem__(matplotlib.rcParams, "backend") is sentinel:
+        matplotlib.rcParams["backend"] = MATPLOTLIB_BACKEND
+
+
+def install_matplotlib_backend():
+    """Makes matplotlib show figures in outputs, unless the user chose a backend.
+
+    The backend is set when matplotlib is imported, the environment is not changed,
+    so subprocesses started by the user do not get a backend they cannot import.
+    """
+    on_import("matplotlib", _set_matplotlib_backend)
+
+
+def flush_figures():
+    # The backend is imported by matplotlib only when pyplot is used
+    backend = sys.modules.get("twinsong.driver.mpl_backend")
+    if backend is not None:
+        backend.flush_figures()
@@ -0,0 +1,55 @@
+import importlib.abc
+import sys
+
+# Callbacks that are called with the module once it is imported
+_callbacks = {}
+
+
+class _HookedLoader(importlib.abc.Loader):
+    def __init__(self, loader):
+        self._loader = loader
+
+    def __getattr__(self, name):
+        return getattr(self._loader, name)
+
+    def create_module(self, spec):
+        return self._loader.create_module(spec)
+
+    def exec_module(self, module):
+        self._loader.exec_module(module)
+        for callback in _callbacks.pop(module.__name__, ()):
+            callback(module)
+
+
+class _ImportHookFinder(importlib.abc.MetaPathFinder):
+    """Lets the other finders find watched modules and wraps their loaders"""
+
+    def find_spec(self, fullname, path, target=None):
+        if fullname not in _callbacks:
+            return None
+        for finder in sys.meta_path:
+            find_spec = getattr(finder, "find_spec", None)
+            if finder is self or find_spec is None:
+                continue
+            spec = find_spec(fullname, path, target)
+            if spec is not None:
+                break
+        else:
+            return None
+        if spec.loader is not None and hasattr(spec.loader, "exec_module"):
+            spec.loader = _HookedLoader(spec.loader)
+        return spec
+
+
+def on_import(name, callback):
+    """Calls `callback(module)` when the module is imported, immediately when it already is.
+
+    Nothing is imported here, so hooks for optional packages cost nothing until used.
+    """
+    module = sys.modules.get(name)
+    if module is not None:
+        callback(module)
+        return
+    _callbacks.setdefault(name, []).append(callback)
+    if not any(isinstance(finder, _ImportHookFinder) for finder in sys.meta_path):
+        sys.meta_path.insert(0, _ImportHookFinder())
@@ -0,0 +1,83 @@
+import contextlib
+import logging
+import re
+import warnings
+
+# Code of notebook cells is compiled with this filename, so sources can be mapped to cells
+_CELL_FILENAME = re.compile(r"<cell ([0-9a-f-]{36})>")
+
+# Output of the cell that is currently computed
+_cell_output = None
+_original_showwarning = warnings.showwarning
+_original_last_resort = logging.lastResort
+
+
+def cell_filename(cell_id):
+    return f"<cell {cell_id}>"
+
+
+def _cell_id(filename):
+    match = _CELL_FILENAME.fullmatch(filename or "")
+    return match.group(1) if match else None
+
+
+@contextlib.contextmanager
+def cell_logs(output):
+    global _cell_output
+    old = _cell_output
+    _cell_output = output
+    try:
+        yield
+    finally:
+        _cell_output = old
+
+
+def _showwarning(message, category, filename, lineno, file=None, line=None):
+    # Warnings explicitly written into a file are kept as they are
+    if _cell_output is None or file is not None:
+        _original_showwarning(message, category, filename, lineno, file, line)
+        return
+    _cell_output.log(
+        True,
+        category.__name__,
+        logging.WARNING,
+        None,
+        str(message),
+        filename,
+        lineno,
+        _cell_id(filename),
+    )
+
+
+class CellLogHandler(logging.Handler):
+    """Sends log records into the output of the computed cell.
+
+    It replaces `logging.lastResort`, so it gets records only when logging was not
+    configured by the user (e.g. by `logging.basicConfig`). Unlike the original
+    handler it has no level, so only the level of the logger decides.
+    """
+
+    def emit(self, record):
+        if _cell_output is None:
+            last_resort = _original_last_resort
+            if last_resort is not None and record.levelno >= last_resort.level:
+                last_resort.handle(record)
+            return
+        try:
+            _cell_output.log(
+                False,
+                record.levelname,
+                record.levelno,
+                record.name,
+                self.format(record),
+                record.pathname,
+                record.lineno,
+                _cell_id(record.pathname),
+            )
+        except Exception:
+            self.handleError(record)
+
+
+def install_log_hooks():
+    warnings.showwarning = _showwarning
+    logging.lastResort = CellLogHandler()
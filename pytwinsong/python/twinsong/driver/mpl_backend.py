@@ -0,0 +1,52 @@
+# Matplotlib backend of twinsong kernels, it is imported by matplotlib itself
+# when pyplot is used for the first time (it is set in rcParams on import)
+import base64
+import io
+import os
+
+from matplotlib._pylab_helpers import Gcf
+from matplotlib.backend_bases import _Backend
+from matplotlib.backends.backend_agg import FigureCanvasAgg, _BackendAgg
+
+# Format of shown figures: "png" or "svg"
+FIGURE_FORMAT = os.environ.get("TWINSONG_FIGURE_FORMAT", "png")
+
+
+class FigureCanvasTwinsong(FigureCanvasAgg):
+    # Own class, so figures of other backends (after `plt.switch_backend`)
+    # are recognized and left untouched
+    pass
+
+
+def _figure_bundle(figure):
+    buffer = io.BytesIO()
+    figure.savefig(buffer, format=FIGURE_FORMAT, bbox_inches="tight")
+    data = buffer.getvalue()
+    if FIGURE_FORMAT == "svg":
+        bundle = {"image/svg+xml": data.decode("utf-8")}
+    else:
+        bundle = {"image/png": base64.b64encode(data).decode("ascii")}
+    bundle["text/plain"] = repr(figure)
+    return bundle
+
+
+def flush_figures():
+    """Shows all open figures in the output of the current cell and closes them"""
+    from twinsong.display import display
+
+    for manager in Gcf.get_all_fig_managers():
+        if not isinstance(manager.canvas, FigureCanvasTwinsong):
+            continue
+        try:
+            display(_figure_bundle(manager.canvas.figure), raw=True)
+        finally:
+            Gcf.destroy(manager)
+
+
+@_Backend.export
+class _BackendTwinsong(_BackendAgg):
+    FigureCanvas = FigureCanvasTwinsong
+
+    @staticmethod
+    def show(*args, **kwargs):
+        flush_figures()
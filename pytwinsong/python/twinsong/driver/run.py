@@ -1,7 +1,14 @@
 import ast
+import builtins
 import contextlib
+import getpass
+import sys
 from typing import Any
 
+from twinsong.display import cell_output
+from twinsong.driver.display import flush_figures
+from twinsong.driver.logs import cell_filename, cell_logs
+
 
 class ScopeWrapper:
     def __init__(self, scope_dict: dict, globals_dict: dict):
@@ -22,35 +29,104 @@ class ScopeWrapper:
             return self.__scope_dict[name]
 
 
-def run_code(code, globals_dict, parent_dict, locals_dict, stdout, return_last):
+# Stdin of the cell that is currently computed
+_cell_stdin = None
+_original_input = builtins.input
+_original_getpass = getpass.getpass
+
+
+def _input(prompt=""):
+    if _cell_stdin is None:
+        return _original_input(prompt)
+    prompt = str(prompt)
+    value = _cell_stdin.input(prompt, False)
+    sys.stdout.write(f"{prompt}{value}\n")
+    return value
+
+
+def _getpass(prompt="Password: ", stream=None):
+    if _cell_stdin is None:
+        return _original_getpass(prompt, stream)
+    value = _cell_stdin.input(prompt, True)
+    # Password is not echoed, so it never gets into outputs
+    sys.stdout.write(f"{prompt}\n")
+    return value
+
+
+# Patched once, so also functions imported by user before the computation are redirected
+builtins.input = _input
+getpass.getpass = _getpass
+
+
+@contextlib.contextmanager
+def redirect_input(stdin):
+    global _cell_stdin
+    old = _cell_stdin
+    _cell_stdin = stdin
+    try:
+        yield
+    finally:
+        _cell_stdin = old
+
+
+@contextlib.contextmanager
+def redirect_streams(stdin, stdout, stderr):
+    with (
+        contextlib.redirect_stdout(stdout),
+        contextlib.redirect_stderr(stderr),
+        redirect_input(stdin),
+        cell_output(stdout),
+        cell_logs(stdout),
+    ):
+        try:
+            yield
+        finally:
+            # Figures not shown by `plt.show()` are shown at the end of the cell
+            flush_figures()
+
+
+def run_code(
+    code,
+    cell_id,
+    globals_dict,
+    parent_dict,
+    locals_dict,
+    stdin,
+    stdout,
+    stderr,
+    return_last,
+):
+    filename = cell_filename(cell_id)
     if not return_last:
-        with contextlib.redirect_stdout(stdout):
-            return exec(code, globals_dict, locals_dict)
-    tree = ast.parse(code)
+        with redirect_streams(stdin, stdout, stderr):
+            return exec(
+                compile(code, filename=filename, mode="exec"), globals_dict, locals_dict
+            )
+    tree = ast.parse(code, filename=filename)
     if parent_dict is not None and "parent_scope" not in locals_dict:
         parent_scope = ScopeWrapper(parent_dict, globals_dict)
         locals_dict["parent_scope"] = parent_scope
     else:
         parent_scope = None
     try:
-        with contextlib.redirect_stdout(stdout):
+        with redirect_streams(stdin, stdout, stderr):
             if tree.body:
                 if isinstance(tree.body[-1], ast.Expr):
                     last_expr = tree.body.pop().value
                     exec(
-                        compile(tree, filename="<cell>", mode="exec"),
+                        compile(tree, filename=filename, mode="exec"),
                         globals_dict,
                         locals_dict,
                     )
                     return eval(
                         compile(
-                            ast.Expression(last_expr), filename="<cell>", mode="eval"
+                            ast.Expression(last_expr), filename=filename, mode="eval"
                         ),
                         globals_dict,
                         locals_dict,
                     )
                 exec(
-                    compile(tree, filename="<cell>", mode="exec"),
+                    compile(tree, filename=filename, mode="exec"),
                     globals_dict,
                     locals_dict,
                 )
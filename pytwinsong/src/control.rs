@@ -1,39 +1,91 @@
-use crate::executor::{FromExecutorMessage, ToExecutorMessage};
+use crate::executor::{FromExecutorMessage, RunningCell, ToExecutorMessage};
+use crate::stdio::InputReply;
 use anyhow::anyhow;
 use comm::messages::{FromKernelMessage, ToKernelMessage};
 use comm::scopes::SerializedGlobals;
-use comm::{Codec, make_protocol_builder, parse_to_kernel_message, serialize_from_kernel_message};
+use comm::{
+    Codec, KERNEL_CAPABILITIES, PROTOCOL_VERSION, make_protocol_builder, parse_to_kernel_message,
+    serialize_from_kernel_message,
+};
 use futures_util::SinkExt;
 use futures_util::stream::{SplitSink, SplitStream, StreamExt};
+use pyo3::Python;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
 use tokio::runtime::Builder;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::sync::oneshot;
 use tokio_util::bytes::Bytes;
 use uuid::Uuid;
 
-pub fn start_control_process() -> (
+/// Delay between attempts to connect to a restarted server
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn start_control_process(
+    running_cell: RunningCell,
+    shutdown_sender: oneshot::Sender<()>,
+    secret: String,
+) -> (
     UnboundedSender<FromExecutorMessage>,
     UnboundedReceiver<ToExecutorMessage>,
+    std::sync::mpsc::Receiver<InputReply>,
+    JoinHandle<()>,
 ) {
     let (c_sender, c_receiver) = unbounded_channel();
     let (o_sender, o_receiver) = unbounded_channel();
-    std::thread::spawn(|| {
+    // Replies are received by the executor while it is blocked inside Python code
+    let (i_sender, i_receiver) = std::sync::mpsc::channel();
+    let handle = std::thread::spawn(|| {
         Builder::new_current_thread()
             .enable_all()
             .build()
             .unwrap()
             .block_on(async {
-                if let Err(e) = controller_main(c_sender, o_receiver).await {
+                if let Err(e) = controller_main(
+                    c_sender,
+                    o_receiver,
+                    i_sender,
+                    running_cell,
+                    shutdown_sender,
+                    secret,
+                )
+                .await
+                {
                     panic!("Error: {e:?}");
                 }
             });
     });
-    (o_sender, c_receiver)
+    (o_sender, c_receiver, i_receiver, handle)
+}
+
+/// Kernel keeps trying to connect for this time when the connection to server is lost.
+/// It is set only for detached kernels, others exit together with the connection.
+fn reconnect_timeout() -> anyhow::Result<Option<Duration>> {
+    std::env::var("KERNEL_RECONNECT_TIMEOUT")
+        .ok()
+        .map(|value| Ok(Duration::from_secs(value.parse()?)))
+        .transpose()
+}
+
+async fn reconnect(addr: &str, deadline: Instant) -> anyhow::Result<TcpStream> {
+    loop {
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+        match TcpStream::connect(addr).await {
+            Ok(socket) => return Ok(socket),
+            Err(e) if Instant::now() >= deadline => return Err(e.into()),
+            Err(_) => {}
+        }
+    }
 }
 
 async fn controller_main(
     c_sender: UnboundedSender<ToExecutorMessage>,
-    o_receiver: UnboundedReceiver<FromExecutorMessage>,
+    mut o_receiver: UnboundedReceiver<FromExecutorMessage>,
+    i_sender: std::sync::mpsc::Sender<InputReply>,
+    running_cell: RunningCell,
+    shutdown_sender: oneshot::Sender<()>,
+    secret: String,
 ) -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
     let addr = std::env::var("KERNEL_CONNECT")
@@ -41,38 +93,117 @@ async fn controller_main(
     let id_str =
         std::env::var("KERNEL_ID").map_err(|_| anyhow!("Variable KERNEL_ID not defined"))?;
     let kernel_id = Uuid::parse_str(&id_str)?;
-    let socket = TcpStream::connect(&addr).await?;
-    let (mut sender, receiver) = make_protocol_builder().new_framed(socket).split();
-    sender
-        .send(serialize_from_kernel_message(FromKernelMessage::Login { kernel_id })?.into())
-        .await?;
+    let reconnect_timeout = reconnect_timeout()?;
 
-    tokio::select! {
-        r = async {
-            forward_sender(sender, o_receiver).await
-        } => r,
-        r = async {
-            handle_recv(receiver, c_sender).await
-        } => r
+    // Messages answered directly by the control thread, independently of the executor
+    let (r_sender, mut r_receiver) = unbounded_channel();
+    let mut shutdown_sender = Some(shutdown_sender);
+    let mut deadline = None;
+    // Message that was not written before the connection was lost and globals sent last,
+    // they outlive the connection, so no output of a computation is lost
+    let mut unsent = None;
+    let mut last_globals = SerializedGlobals::default();
+    let mut socket = TcpStream::connect(&addr).await?;
+    loop {
+        let (mut sender, receiver) = make_protocol_builder().new_framed(socket).split();
+        sender
+            .send(
+                serialize_from_kernel_message(&FromKernelMessage::Login {
+                    protocol_version: PROTOCOL_VERSION,
+                    kernel_id,
+                    secret: secret.clone(),
+                    capabilities: KERNEL_CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+                })?
+                .into(),
+            )
+            .await?;
+
+        let mut accepted = false;
+        let result = tokio::select! {
+            r = forward_sender(
+                &mut sender,
+                &mut o_receiver,
+                &mut r_receiver,
+                &mut unsent,
+                &mut last_globals,
+            ) => {
+                if r.is_ok() {
+                    // Executor has finished
+                    return Ok(());
+                }
+                r
+            }
+            r = handle_recv(
+                receiver,
+                &c_sender,
+                &i_sender,
+                &r_sender,
+                &running_cell,
+                &mut shutdown_sender,
+                &mut accepted,
+            ) => r
+        };
+        // Shutdown was requested, so the kernel does not wait for the server
+        let Some(timeout) = reconnect_timeout.filter(|_| shutdown_sender.is_some()) else {
+            return result;
+        };
+        tracing::debug!("Connection to server lost ({result:?}), reconnecting");
+        if accepted {
+            deadline = None;
+        }
+        // Deadline is not prolonged by connections where the login was rejected
+        let deadline = *deadline.get_or_insert_with(|| Instant::now() + timeout);
+        socket = reconnect(&addr, deadline).await?;
     }
 }
 
+/// Forwards messages to the server, it returns Ok when the executor has finished.
+/// A message is kept in `unsent` until it is written, so it is sent again after reconnecting
+/// when the connection is lost (or this future is dropped) in the middle of sending.
 async fn forward_sender(
-    mut sender: SplitSink<Codec, Bytes>,
-    mut o_receiver: UnboundedReceiver<FromExecutorMessage>,
+    sender: &mut SplitSink<Codec, Bytes>,
+    o_receiver: &mut UnboundedReceiver<FromExecutorMessage>,
+    r_receiver: &mut UnboundedReceiver<FromKernelMessage>,
+    unsent: &mut Option<FromKernelMessage>,
+    last_globals: &mut SerializedGlobals,
 ) -> anyhow::Result<()> {
-    let mut last_globals = SerializedGlobals::default();
-    while let Some(msg) = o_receiver.recv().await {
-        let out_msg = match msg {
+    // A new connection may be handled by a restarted server, so the first update is complete
+    match unsent {
+        Some(FromKernelMessage::Output {
+            update: Some(update),
+            ..
+        }) => *update = last_globals.create_update(None),
+        _ => *last_globals = SerializedGlobals::default(),
+    }
+    loop {
+        if let Some(msg) = unsent.as_ref() {
+            sender
+                .send(serialize_from_kernel_message(msg)?.into())
+                .await?;
+            *unsent = None;
+        }
+        let msg = tokio::select! {
+            msg = o_receiver.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            // Replies of the control thread are not worth sending to a new connection
+            Some(msg) = r_receiver.recv() => {
+                sender.send(serialize_from_kernel_message(&msg)?.into()).await?;
+                continue;
+            }
+        };
+        *unsent = Some(match msg {
             FromExecutorMessage::Output {
                 value,
                 cell_id,
                 flag,
                 update: globals,
+                finished,
             } => {
                 let update = globals.map(|g| {
-                    let update = g.create_update(Some(&last_globals));
-                    last_globals = g;
+                    let update = g.create_update(Some(last_globals));
+                    *last_globals = g;
                     update
                 });
                 FromKernelMessage::Output {
@@ -80,27 +211,92 @@ async fn forward_sender(
                     cell_id,
                     flag,
                     update,
+                    finished,
                 }
             }
+            FromExecutorMessage::ComputeStarted { cell_id, time } => {
+                FromKernelMessage::ComputeStarted { cell_id, time }
+            }
+            FromExecutorMessage::Display {
+                cell_id,
+                value,
+                display_id,
+                update,
+            } => FromKernelMessage::Display {
+                cell_id,
+                value,
+                display_id,
+                update,
+            },
+            FromExecutorMessage::InputRequest {
+                cell_id,
+                request_id,
+                prompt,
+                password,
+            } => FromKernelMessage::InputRequest {
+                cell_id,
+                request_id,
+                prompt,
+                password,
+            },
             FromExecutorMessage::SaveStateResponse { path, result } => {
                 FromKernelMessage::SaveStateResponse { path, result }
             }
             FromExecutorMessage::LoadStateResponse { path, result } => {
                 FromKernelMessage::LoadStateResponse { path, result }
             }
-        };
-        let msg = serialize_from_kernel_message(out_msg)?;
-        sender.send(msg.into()).await?
+            FromExecutorMessage::InspectResponse { request_id, result } => {
+                FromKernelMessage::InspectResponse { request_id, result }
+            }
+            FromExecutorMessage::Exit => break,
+        });
     }
     Ok(())
 }
 
+fn interrupt_executor(running_cell: &RunningCell, target: Option<Uuid>) {
+    {
+        // Lock is held during raising the interrupt, so executor cannot leave the cell meanwhile
+        let running_cell = running_cell.lock().unwrap();
+        let Some(cell_id) = *running_cell else {
+            tracing::debug!("Nothing to interrupt");
+            return;
+        };
+        if target.is_some_and(|target| target != cell_id) {
+            tracing::debug!("Cell {} is not running", target.unwrap());
+            return;
+        }
+        tracing::debug!("Interrupting cell {cell_id}");
+        unsafe {
+            /* SAFETY
+               PyErr_SetInterrupt is async-signal-safe and can be called without GIL.
+               It raises KeyboardInterrupt in the main thread where the executor runs.
+            */
+            pyo3::ffi::PyErr_SetInterrupt();
+        }
+    }
+    /*
+       Signal set from a non-main thread does not wake up the eval loop of the main thread,
+       so we briefly ask for GIL; it forces the executor to check pending signals.
+       It is done in blocking thread, as the executor may hold GIL for a long time.
+    */
+    tokio::task::spawn_blocking(|| Python::with_gil(|_| {}));
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_recv(
     mut receiver: SplitStream<Codec>,
-    c_sender: UnboundedSender<ToExecutorMessage>,
+    c_sender: &UnboundedSender<ToExecutorMessage>,
+    i_sender: &std::sync::mpsc::Sender<InputReply>,
+    r_sender: &UnboundedSender<FromKernelMessage>,
+    running_cell: &RunningCell,
+    shutdown_sender: &mut Option<oneshot::Sender<()>>,
+    accepted: &mut bool,
 ) -> anyhow::Result<()> {
     while let Some(message) = receiver.next().await {
         let message = message?;
+        // Server sends nothing to a kernel with rejected login
+        *accepted = true;
         match parse_to_kernel_message(&message)? {
             ToKernelMessage::Compute(msg) => {
                 c_sender.send(ToExecutorMessage::Compute(msg)).unwrap();
@@ -111,6 +307,22 @@ async fn handle_recv(
             ToKernelMessage::LoadState(path) => {
                 c_sender.send(ToExecutorMessage::LoadState(path)).unwrap();
             }
+            ToKernelMessage::Inspect(msg) => {
+                c_sender.send(ToExecutorMessage::Inspect(msg)).unwrap();
+            }
+            ToKernelMessage::Interrupt { cell_id } => interrupt_executor(running_cell, cell_id),
+            ToKernelMessage::Shutdown => {
+                if let Some(sender) = shutdown_sender.take() {
+                    let _ = sender.send(());
+                }
+                interrupt_executor(running_cell, None);
+            }
+            ToKernelMessage::Heartbeat(value) => {
+                let _ = r_sender.send(FromKernelMessage::HeartbeatResponse(value));
+            }
+            ToKernelMessage::InputReply { request_id, value } => {
+                let _ = i_sender.send(InputReply { request_id, value });
+            }
         }
     }
     Ok(())
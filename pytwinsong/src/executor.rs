@@ -1,17 +1,21 @@
 use crate::control::start_control_process;
+use crate::jobject::inspect_jobject_string;
 use crate::scopes::ScopedPyGlobals;
-use crate::stdio::RedirectedStdio;
+use crate::stdio::{InputReplies, RedirectedStdin, RedirectedStdio};
 use comm::messages::{
-    CodeGroup, CodeLeaf, CodeNode, CodeScope, ComputeMsg, Exception, KernelOutputValue, OutputFlag,
-    OwnCodeScope,
+    CodeGroup, CodeLeaf, CodeNode, CodeScope, ComputeMsg, Exception, InspectMsg, KernelOutputValue,
+    MimeBundle, OutputFlag, OwnCodeScope, StreamName,
 };
 use comm::scopes::SerializedGlobals;
+use pyo3::types::PyNone;
 use pyo3::types::{PyAnyMethods, PyDict, PyTracebackMethods};
-use pyo3::types::{PyNone, PyStringMethods};
 use pyo3::{Bound, IntoPyObjectExt, PyAny, PyErr, PyResult, Python, intern};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use tokio::runtime::Builder;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -21,6 +25,23 @@ pub enum FromExecutorMessage {
         cell_id: Uuid,
         flag: OutputFlag,
         update: Option<SerializedGlobals>,
+        finished: Option<SystemTime>,
+    },
+    ComputeStarted {
+        cell_id: Uuid,
+        time: SystemTime,
+    },
+    Display {
+        cell_id: Uuid,
+        value: MimeBundle,
+        display_id: Option<String>,
+        update: bool,
+    },
+    InputRequest {
+        cell_id: Uuid,
+        request_id: Uuid,
+        prompt: String,
+        password: bool,
     },
     SaveStateResponse {
         path: PathBuf,
@@ -30,6 +51,12 @@ pub enum FromExecutorMessage {
         path: PathBuf,
         result: Result<SerializedGlobals, String>,
     },
+    InspectResponse {
+        request_id: Uuid,
+        result: Result<String, String>,
+    },
+    /// The last message from executor, control thread terminates when it is forwarded
+    Exit,
 }
 
 #[derive(Debug)]
@@ -37,38 +64,112 @@ pub enum ToExecutorMessage {
     Compute(ComputeMsg),
     SaveState(PathBuf),
     LoadState(PathBuf),
+    Inspect(InspectMsg),
 }
 
-pub fn start_executor() {
-    let (o_sender, c_receiver) = start_control_process();
+const TEXT_PLAIN: &str = "text/plain";
+const TEXT_HTML: &str = "text/html";
+
+/// Id of the cell that is currently evaluated by the executor.
+/// It is shared with the control thread, so it knows when an interrupt can be raised.
+pub type RunningCell = Arc<Mutex<Option<Uuid>>>;
+
+pub fn start_executor(secret: String) {
+    let running_cell = RunningCell::default();
+    let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+    let (o_sender, c_receiver, i_receiver, control_thread) =
+        start_control_process(running_cell.clone(), shutdown_sender, secret);
+    let input_replies = Arc::new(Mutex::new(i_receiver));
     Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap()
         .block_on(async {
-            if let Err(e) = executor_main(o_sender, c_receiver).await {
+            if let Err(e) = executor_main(
+                o_sender,
+                c_receiver,
+                input_replies,
+                running_cell,
+                shutdown_receiver,
+            )
+            .await
+            {
                 panic!("Error: {e:?}");
             }
         });
+    // Wait until all outputs are sent to the server,
+    // GIL is released as the control thread may need it for finishing an interrupt
+    Python::with_gil(|py| py.allow_threads(|| control_thread.join()))
+        .unwrap_or_else(|_| tracing::debug!("Control thread failed"));
 }
 
-fn try_repr_html(py: Python, obj: &Bound<PyAny>) -> PyResult<Option<String>> {
-    if let Ok(repr_html) = obj.getattr(intern!(py, "_repr_html_")) {
-        let html_repr = repr_html.call0()?;
-        let str: String = html_repr.extract()?;
-        Ok(Some(str))
-    } else {
-        Ok(None)
+fn create_output_value(py: Python, obj: &Bound<PyAny>) -> PyResult<KernelOutputValue> {
+    let display_module = py.import(intern!(py, "twinsong.driver.display"))?;
+    let mut bundle: MimeBundle = display_module
+        .getattr(intern!(py, "mime_bundle"))?
+        .call1((obj,))?
+        .extract()?;
+    // Plain text and HTML keep their own variants, bundle is used only for richer outputs
+    Ok(match bundle.len() {
+        1 => KernelOutputValue::Text {
+            value: bundle.remove(TEXT_PLAIN).unwrap_or_default(),
+        },
+        2 if bundle.contains_key(TEXT_HTML) => KernelOutputValue::Html {
+            value: bundle.remove(TEXT_HTML).unwrap(),
+        },
+        _ => KernelOutputValue::MimeBundle { value: bundle },
+    })
+}
+
+fn install_hooks(py: Python) -> PyResult<()> {
+    py.import(intern!(py, "twinsong.display"))?
+        .getattr(intern!(py, "install_ipython_shim"))?
+        .call0()?;
+    py.import(intern!(py, "twinsong.driver.display"))?
+        .getattr(intern!(py, "install_matplotlib_backend"))?
+        .call0()?;
+    py.import(intern!(py, "twinsong.driver.logs"))?
+        .getattr(intern!(py, "install_log_hooks"))?
+        .call0()?;
+    Ok(())
+}
+
+/// Redirected standard streams of the computed cell
+struct CellStreams<'py> {
+    stdin: Bound<'py, PyAny>,
+    stdout: Bound<'py, PyAny>,
+    stderr: Bound<'py, PyAny>,
+}
+
+impl<'py> CellStreams<'py> {
+    fn new(
+        py: Python<'py>,
+        sender: &UnboundedSender<FromExecutorMessage>,
+        input_replies: &InputReplies,
+        cell_id: Uuid,
+    ) -> Self {
+        let create = |stream| {
+            RedirectedStdio::new(sender.clone(), cell_id, stream)
+                .into_bound_py_any(py)
+                .unwrap()
+        };
+        CellStreams {
+            stdin: RedirectedStdin::new(sender.clone(), input_replies.clone(), cell_id)
+                .into_bound_py_any(py)
+                .unwrap(),
+            stdout: create(StreamName::Stdout),
+            stderr: create(StreamName::Stderr),
+        }
     }
 }
 
 fn eval_code<'a>(
     py: Python<'a>,
-    code: &str,
+    leaf: &CodeLeaf,
     globals: &Bound<'a, PyDict>,
     parent: Option<&Bound<'a, PyDict>>,
     locals: &Bound<'a, PyDict>,
-    stdout: &'a Bound<PyAny>,
+    streams: &CellStreams<'a>,
     return_last: bool,
 ) -> PyResult<Bound<'a, PyAny>> {
     let run_module = py.import(intern!(py, "twinsong.driver.run"))?;
@@ -76,11 +177,14 @@ fn eval_code<'a>(
         .map(|x| x.clone().into_any())
         .unwrap_or_else(|| PyNone::get(py).into_bound_py_any(py).unwrap());
     run_module.getattr(intern!(py, "run_code"))?.call1((
-        code,
+        &leaf.code,
+        leaf.id.to_string(),
         globals,
         parent,
         locals,
-        stdout,
+        &streams.stdin,
+        &streams.stdout,
+        &streams.stderr,
         return_last,
     ))
 }
@@ -135,7 +239,7 @@ fn run_code(
     py: Python<'_>,
     py_scopes: &mut ScopedPyGlobals,
     code: &CodeGroup,
-    stdout: Bound<PyAny>,
+    streams: &CellStreams,
 ) -> PyResult<KernelOutputValue> {
     // let s = CString::new(code.as_bytes())?;
     // let result = py.eval(&s, None, None)?;
@@ -149,35 +253,27 @@ fn run_code(
     for code in codes {
         eval_code(
             py,
-            &code.leaf.code,
+            code.leaf,
             &code.globals,
             code.parent.as_ref(),
             &code.locals,
-            &stdout,
+            streams,
             false,
         )?;
     }
     let result = eval_code(
         py,
-        &last.leaf.code,
+        last.leaf,
         &last.globals,
         last.parent.as_ref(),
         &last.locals,
-        &stdout,
+        streams,
         true,
     )?;
     if result.is_none() {
         return Ok(KernelOutputValue::None);
     }
-    Ok(if let Some(value) = try_repr_html(py, &result)? {
-        KernelOutputValue::Html { value }
-    } else {
-        let repr = result.repr()?;
-        let repr = repr.to_str()?;
-        KernelOutputValue::Text {
-            value: repr.to_owned(),
-        }
-    })
+    create_output_value(py, &result)
 }
 
 fn create_traceback(py: &Python, e: PyErr) -> PyResult<Exception> {
@@ -187,11 +283,36 @@ fn create_traceback(py: &Python, e: PyErr) -> PyResult<Exception> {
         .unwrap_or_default();
 
     Ok(Exception {
-        message: e.to_string(),
-        traceback,
+        message: strip_cell_ids(&e.to_string()),
+        traceback: strip_cell_ids(&traceback),
     })
 }
 
+/// Cells are compiled with filename `<cell {uuid}>` (see `cell_filename` in the driver),
+/// so warnings and logs can be mapped to cells. Ids are not shown to the user.
+fn strip_cell_ids(text: &str) -> String {
+    const PREFIX: &str = "<cell ";
+    const ID_LEN: usize = 36;
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(PREFIX) {
+        let after = &rest[start + PREFIX.len()..];
+        let is_id = after.len() > ID_LEN
+            && after.as_bytes()[ID_LEN] == b'>'
+            && Uuid::try_parse(&after[..ID_LEN]).is_ok();
+        if is_id {
+            result.push_str(&rest[..start]);
+            result.push_str("<cell>");
+            rest = &after[ID_LEN + 1..];
+        } else {
+            result.push_str(&rest[..start + PREFIX.len()]);
+            rest = after;
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
 /*fn get_globals(py: Python, scope_storage: &ScopeStorage) -> ScopeObjects {
     variables
         .into_iter()
@@ -205,24 +326,70 @@ fn create_traceback(py: &Python, e: PyErr) -> PyResult<Exception> {
         .collect()
 }*/
 
+fn run_interruptible_code(
+    py: Python<'_>,
+    py_scopes: &mut ScopedPyGlobals,
+    msg: &ComputeMsg,
+    streams: &CellStreams,
+    running_cell: &RunningCell,
+) -> PyResult<KernelOutputValue> {
+    *running_cell.lock().unwrap() = Some(msg.cell_id);
+    let result = run_code(py, py_scopes, &msg.code, streams);
+    *running_cell.lock().unwrap() = None;
+    // Interrupt may arrive after the code is finished but before the cell is unset,
+    // we consume it here, so it does not hit the next computation
+    if let Err(e) = py.check_signals() {
+        tracing::debug!("Dropping late interrupt: {e}");
+    }
+    result
+}
+
 async fn executor_main(
     o_sender: UnboundedSender<FromExecutorMessage>,
     mut c_receiver: UnboundedReceiver<ToExecutorMessage>,
+    input_replies: InputReplies,
+    running_cell: RunningCell,
+    mut shutdown_receiver: oneshot::Receiver<()>,
 ) -> anyhow::Result<()> {
     let mut py_scopes = Python::with_gil(ScopedPyGlobals::new);
-    while let Some(msg) = c_receiver.recv().await {
+    if let Err(e) = Python::with_gil(install_hooks) {
+        tracing::debug!("Installing hooks of the driver failed: {e}");
+    }
+    loop {
+        // Shutdown is checked first, so queued computations are not started
+        let msg = tokio::select! {
+            biased;
+            _ = &mut shutdown_receiver => {
+                tracing::debug!("Executor shutdown");
+                break;
+            }
+            msg = c_receiver.recv() => {
+                let Some(msg) = msg else { break };
+                msg
+            }
+        };
         tracing::debug!("New command: {:?}", msg);
         match msg {
             ToExecutorMessage::Compute(msg) => {
-                let stdout = RedirectedStdio::new(o_sender.clone(), msg.cell_id);
+                o_sender
+                    .send(FromExecutorMessage::ComputeStarted {
+                        cell_id: msg.cell_id,
+                        time: SystemTime::now(),
+                    })
+                    .unwrap();
                 let out_msg = Python::with_gil(|py| {
-                    let stdout = stdout.into_bound_py_any(py).unwrap();
-                    match run_code(py, &mut py_scopes, &msg.code, stdout) {
+                    let streams = CellStreams::new(py, &o_sender, &input_replies, msg.cell_id);
+                    let result =
+                        run_interruptible_code(py, &mut py_scopes, &msg, &streams, &running_cell);
+                    // Serialization of globals is not counted into the computation
+                    let finished = Some(SystemTime::now());
+                    match result {
                         Ok(output) => FromExecutorMessage::Output {
                             value: output,
                             cell_id: msg.cell_id,
                             flag: OutputFlag::Success,
                             update: Some(py_scopes.serialize(py)),
+                            finished,
                         },
                         Err(e) => FromExecutorMessage::Output {
                             value: KernelOutputValue::Exception {
@@ -231,6 +398,7 @@ async fn executor_main(
                             cell_id: msg.cell_id,
                             flag: OutputFlag::Fail,
                             update: Some(py_scopes.serialize(py)),
+                            finished,
                         },
                     }
                 });
@@ -267,11 +435,32 @@ async fn executor_main(
                 tracing::debug!("Send output: {:?}", out_msg);
                 o_sender.send(out_msg).unwrap();
             }
+            ToExecutorMessage::Inspect(msg) => {
+                let result = Python::with_gil(|py| inspect(py, &py_scopes, &msg));
+                o_sender
+                    .send(FromExecutorMessage::InspectResponse {
+                        request_id: msg.request_id,
+                        result,
+                    })
+                    .unwrap();
+            }
         }
     }
+    let _ = o_sender.send(FromExecutorMessage::Exit);
     Ok(())
 }
 
+fn inspect(py: Python, py_scopes: &ScopedPyGlobals, msg: &InspectMsg) -> Result<String, String> {
+    let variable = py_scopes
+        .find_variable(py, &msg.scope, &msg.variable)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Variable '{}' not found", msg.variable))?;
+    let page = msg.offset..msg.offset.saturating_add(msg.limit);
+    inspect_jobject_string(py, &variable, &msg.path, page, msg.limits)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Object not found in variable '{}'", msg.variable))
+}
+
 fn write_data(py: Python, path: &Path, py_scopes: &ScopedPyGlobals) -> PyResult<()> {
     let scopes_dict = py_scopes.as_py_dict(py)?;
     let run_module = py.import(intern!(py, "twinsong.driver.storage"))?;
@@ -1,3 +1,4 @@
+use comm::messages::JObjectLimits;
 use pyo3::types::{
     PyAnyMethods, PyDict, PyDictMethods, PyFloat, PyInt, PyList, PyListMethods, PyModule,
     PyModuleMethods, PyString, PyStringMethods, PyTuple, PyTupleMethods, PyType, PyTypeMethods,
@@ -5,6 +6,7 @@ use pyo3::types::{
 use pyo3::{Bound, PyAny, PyResult, Python, intern};
 use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 
 use serde::Serialize;
 
@@ -29,6 +31,14 @@ pub struct JsonObject {
 
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub children: Vec<(String, JsonObjectId)>,
+
+    /// Number of all children of a container when only some of them are serialized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub len: Option<usize>,
+
+    /// Number of children after the serialized ones that were cut off by limits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -41,17 +51,78 @@ enum TypeCollection<'a> {
 struct BuildCtx {
     serialized: HashSet<JsonObjectId>,
     objects: HashMap<JsonObjectId, JsonObject>,
+    /// Depth of the object that is currently created, the root has depth 0
+    depth: usize,
+    /// Depth of the root in the variable from which it was inspected
+    root_depth: usize,
+    /// Children of objects in this depth are not serialized, they are fetched by inspection
+    lazy_depth: usize,
+    /// Children of the root that are serialized
+    page: Option<Range<usize>>,
+    limits: JObjectLimits,
 }
 
 impl BuildCtx {
-    fn new() -> Self {
+    fn new(limits: JObjectLimits) -> Self {
         BuildCtx {
             serialized: Default::default(),
             objects: Default::default(),
+            depth: 0,
+            root_depth: 0,
+            lazy_depth: usize::MAX,
+            page: None,
+            limits,
+        }
+    }
+
+    /// Children of a container with `len` children that are allowed by limits
+    fn limit_end(&self, len: usize) -> usize {
+        if self.root_depth + self.depth >= self.limits.max_depth {
+            0
+        } else {
+            len.min(self.limits.max_children)
+        }
+    }
+
+    /// Children of a container with `len` children that are serialized
+    fn children_range(&self, len: usize) -> Range<usize> {
+        if self.depth >= self.lazy_depth {
+            return 0..0;
+        }
+        let end = self.limit_end(len);
+        match &self.page {
+            Some(page) if self.depth == 0 => page.start.min(end)..page.end.min(end),
+            _ => 0..end,
+        }
+    }
+
+    /// Another object may be serialized
+    fn has_budget(&self) -> bool {
+        self.serialized.len() < self.limits.max_objects
+    }
+
+    /// Number of children that were not serialized because of limits,
+    /// when children in `range` were planned but only those before `end` were serialized
+    fn truncated(&self, range: &Range<usize>, end: usize, len: usize) -> Option<usize> {
+        if self.depth >= self.lazy_depth || end >= len {
+            return None;
+        }
+        (end < range.end || end >= self.limit_end(len)).then_some(len - end)
+    }
+
+    fn into_dump(self, root: JsonObjectId) -> JsonObjectDump {
+        JsonObjectDump {
+            objects: self.objects.into_values().collect(),
+            root,
         }
     }
 }
 
+/// Number of all children, when only a part of them is serialized
+fn omitted_len(start: usize, end: usize, len: usize) -> Option<usize> {
+    (start > 0 || end < len).then_some(len)
+}
+
 impl<'a> TypeCollection<'a> {
     #[allow(clippy::ptr_arg)]
     pub fn add<'b>(&mut self, name: &'b Cow<'a, str>) {
@@ -83,16 +154,80 @@ fn create_name_2<'b>(tc1: TypeCollection, tc2: TypeCollection, name: &'b str) ->
     }
 }
 
-pub fn create_jobject_string(py: Python, obj: &Bound<PyAny>) -> serde_json::Result<String> {
-    serde_json::to_string(&create_jobject_dump(py, obj))
+pub fn create_jobject_string(
+    py: Python,
+    obj: &Bound<PyAny>,
+    limits: JObjectLimits,
+) -> serde_json::Result<String> {
+    serde_json::to_string(&create_jobject_dump(py, obj, limits))
+}
+
+pub fn create_jobject_dump(
+    py: Python,
+    obj: &Bound<PyAny>,
+    limits: JObjectLimits,
+) -> JsonObjectDump {
+    let mut ctx = BuildCtx::new(limits);
+    let root = create_jobject_helper(py, &mut ctx, obj);
+    ctx.into_dump(root)
 }
 
-pub fn create_jobject_dump(py: Python, obj: &Bound<PyAny>) -> JsonObjectDump {
-    let mut ctx = BuildCtx::new();
+/// Serializes only the object itself, children of containers are fetched by `inspect_jobject`
+pub fn create_jobject_summary_string(py: Python, obj: &Bound<PyAny>) -> serde_json::Result<String> {
+    let mut ctx = BuildCtx::new(JObjectLimits::default());
+    ctx.lazy_depth = 0;
     let root = create_jobject_helper(py, &mut ctx, obj);
-    JsonObjectDump {
-        objects: ctx.objects.into_values().collect(),
-        root,
+    serde_json::to_string(&ctx.into_dump(root))
+}
+
+/// Serializes the object at `path` (indices of children) inside of `obj` together with
+/// summaries of its children in `page`. Returns None when the path does not exist.
+pub fn inspect_jobject_string(
+    py: Python,
+    obj: &Bound<PyAny>,
+    path: &[usize],
+    page: Range<usize>,
+    limits: JObjectLimits,
+) -> PyResult<Option<String>> {
+    let mut obj = obj.clone();
+    for index in path {
+        let Some(child) = child_at(py, &obj, *index)? else {
+            return Ok(None);
+        };
+        obj = child;
+    }
+    let mut ctx = BuildCtx::new(limits);
+    ctx.root_depth = path.len();
+    ctx.lazy_depth = 1;
+    ctx.page = Some(page);
+    let root = create_jobject_helper(py, &mut ctx, &obj);
+    Ok(Some(serde_json::to_string(&ctx.into_dump(root)).unwrap()))
+}
+
+/// Child at `index` in the order in which children are serialized
+fn child_at<'py>(
+    py: Python<'py>,
+    obj: &Bound<'py, PyAny>,
+    index: usize,
+) -> PyResult<Option<Bound<'py, PyAny>>> {
+    if let Ok(obj) = obj.downcast_exact::<PyTuple>() {
+        Ok(obj.get_item(index).ok())
+    } else if let Ok(obj) = obj.downcast_exact::<PyList>() {
+        Ok(obj.get_item(index).ok())
+    } else if let Ok(obj) = obj.downcast_exact::<PyDict>() {
+        Ok(obj.iter().nth(index).map(|(_, value)| value))
+    } else if obj.downcast_exact::<PyType>().is_ok() {
+        Ok(None)
+    } else if let Some(fields) = dataclass_fields(py, obj)? {
+        match fields.get_item(index) {
+            Ok(field) => {
+                let name = field.getattr(intern!(py, "name"))?.to_string();
+                Ok(Some(obj.getattr(&name)?))
+            }
+            Err(_) => Ok(None),
+        }
+    } else {
+        Ok(None)
     }
 }
 
@@ -103,6 +238,8 @@ fn simple_value(repr: String, value_type: Cow<'static, str>, kind: &'static str)
         value_type,
         kind,
         children: Vec::new(),
+        len: None,
+        truncated: None,
     }
 }
 
@@ -139,12 +276,12 @@ fn find_children_element_type<'a>(
 const MAX_CONTAINER_REPR: usize = 24;
 
 fn container_repr(obj: &Bound<PyAny>, len: usize) -> String {
-    if len <= 10 {
-        if let Ok(r) = obj.repr() {
-            let cow = PyStringMethods::to_string_lossy(&r);
-            if cow.as_ref().len() <= MAX_CONTAINER_REPR {
-                return cow.to_string();
-            }
+    if len <= 10
+        && let Ok(r) = obj.repr()
+    {
+        let cow = PyStringMethods::to_string_lossy(&r);
+        if cow.as_ref().len() <= MAX_CONTAINER_REPR {
+            return cow.to_string();
         }
     }
     format!("{len} items")
@@ -172,18 +309,33 @@ fn short_type(obj: &Bound<PyAny>) -> Cow<'static, str> {
     }
 }
 
+fn create_child(py: Python, ctx: &mut BuildCtx, obj: &Bound<PyAny>) -> JsonObjectId {
+    ctx.depth += 1;
+    let id = create_jobject_helper(py, ctx, obj);
+    ctx.depth -= 1;
+    id
+}
+
 fn create_dict(py: Python, ctx: &mut BuildCtx, obj: &Bound<PyDict>) -> JsonObject {
+    let len = PyDictMethods::len(obj);
+    let range = ctx.children_range(len);
     let mut tc1 = TypeCollection::Unknown;
     let children: Vec<_> = obj
         .into_iter()
-        .map(|(slot, child)| {
+        .skip(range.start)
+        .take(range.len())
+        .map_while(|(slot, child)| {
+            if !ctx.has_budget() {
+                return None;
+            }
             if !tc1.is_many() {
                 tc1.add(&short_type(&slot));
             }
-            (slot.to_string(), create_jobject_helper(py, ctx, &child))
+            Some((slot.to_string(), create_child(py, ctx, &child)))
         })
         .collect();
-    let repr = container_repr(obj, PyDictMethods::len(obj));
+    let end = range.start + children.len();
+    let repr = container_repr(obj, len);
     let tc2 = find_children_element_type(ctx, &children);
     JsonObject {
         id: 0,
@@ -191,16 +343,25 @@ fn create_dict(py: Python, ctx: &mut BuildCtx, obj: &Bound<PyDict>) -> JsonObjec
         value_type: create_name_2(tc1, tc2, "dict"),
         kind: "dict",
         children,
+        len: omitted_len(range.start, end, len),
+        truncated: ctx.truncated(&range, end, len),
     }
 }
 
 fn create_list<'a>(py: Python, ctx: &'a mut BuildCtx, obj: &'a Bound<PyList>) -> JsonObject {
+    let len = PyListMethods::len(obj);
+    let range = ctx.children_range(len);
     let children: Vec<_> = obj
+        .get_slice(range.start, range.end)
         .into_iter()
-        .enumerate()
-        .map(|(idx, child)| (idx.to_string(), create_jobject_helper(py, ctx, &child)))
+        .zip(range.clone())
+        .map_while(|(child, idx)| {
+            ctx.has_budget()
+                .then(|| (idx.to_string(), create_child(py, ctx, &child)))
+        })
         .collect();
-    let repr = container_repr(obj, PyListMethods::len(obj));
+    let end = range.start + children.len();
+    let repr = container_repr(obj, len);
     let tc = find_collection_element_type(
         children
             .iter()
@@ -212,16 +373,25 @@ fn create_list<'a>(py: Python, ctx: &'a mut BuildCtx, obj: &'a Bound<PyList>) ->
         value_type: create_name_1(tc, "list"),
         kind: "list",
         children,
+        len: omitted_len(range.start, end, len),
+        truncated: ctx.truncated(&range, end, len),
     }
 }
 
 fn create_tuple(py: Python, ctx: &mut BuildCtx, obj: &Bound<PyTuple>) -> JsonObject {
+    let len = PyTupleMethods::len(obj);
+    let range = ctx.children_range(len);
     let children: Vec<_> = obj
+        .get_slice(range.start, range.end)
         .into_iter()
-        .enumerate()
-        .map(|(idx, child)| (idx.to_string(), create_jobject_helper(py, ctx, &child)))
+        .zip(range.clone())
+        .map_while(|(child, idx)| {
+            ctx.has_budget()
+                .then(|| (idx.to_string(), create_child(py, ctx, &child)))
+        })
         .collect();
-    let repr = container_repr(obj, PyTupleMethods::len(obj));
+    let end = range.start + children.len();
+    let repr = container_repr(obj, len);
     let tc = find_collection_element_type(
         children
             .iter()
@@ -233,6 +403,8 @@ fn create_tuple(py: Python, ctx: &mut BuildCtx, obj: &Bound<PyTuple>) -> JsonObj
         value_type: create_name_1(tc, "tuple"),
         kind: "tuple",
         children,
+        len: omitted_len(range.start, end, len),
+        truncated: ctx.truncated(&range, end, len),
     }
 }
 
@@ -255,6 +427,8 @@ fn create_module(_py: Python, _ctx: &mut BuildCtx, obj: &Bound<PyModule>) -> Jso
         value_type: "".into(),
         kind: "module",
         children,
+        len: None,
+        truncated: None,
     }
 }
 
@@ -272,39 +446,53 @@ fn create_class(_py: Python, _ctx: &mut BuildCtx, obj: &Bound<PyType>) -> JsonOb
         value_type: "".into(),
         kind: "class",
         children,
+        len: None,
+        truncated: None,
     }
 }
 
 // TODO: Cache import and getattr
+fn dataclass_fields<'py>(
+    py: Python<'py>,
+    obj: &Bound<'py, PyAny>,
+) -> PyResult<Option<Bound<'py, PyTuple>>> {
+    let m = py.import(intern!(py, "dataclasses"))?;
+    let f = m.getattr(intern!(py, "is_dataclass"))?;
+    if !(f.call1((obj,))?.is_truthy()?) {
+        return Ok(None);
+    }
+    let f = m.getattr(intern!(py, "fields"))?;
+    Ok(Some(f.call1((obj,))?.downcast_into::<PyTuple>()?))
+}
+
 fn try_create_dataclass(
     py: Python,
     ctx: &mut BuildCtx,
     obj: &Bound<PyAny>,
 ) -> PyResult<Option<JsonObject>> {
-    let m = py.import(intern!(py, "dataclasses"))?;
-    let f = m.getattr(intern!(py, "is_dataclass"))?;
-    if !(f.call1((obj,))?.is_truthy()?) {
+    let Some(fields) = dataclass_fields(py, obj)? else {
         return Ok(None);
+    };
+    let len = fields.len();
+    let range = ctx.children_range(len);
+    let mut children = Vec::new();
+    for field in fields.iter().skip(range.start).take(range.len()) {
+        if !ctx.has_budget() {
+            break;
+        }
+        let name = field.getattr(intern!(py, "name"))?.to_string();
+        let child = obj.getattr(&name)?;
+        children.push((name, create_child(py, ctx, &child)));
     }
-    let f = m.getattr(intern!(py, "fields"))?;
-    let r = f.call1((obj,))?;
-    let fields = r.downcast_exact::<PyTuple>()?;
-    let mut len = 0;
-    let children = fields
-        .iter()
-        .map(|field| {
-            len += 1;
-            let name = field.getattr(intern!(py, "name"))?.to_string();
-            let child = obj.getattr(&name)?;
-            Ok((name, create_jobject_helper(py, ctx, &child)))
-        })
-        .collect::<PyResult<Vec<_>>>()?;
+    let end = range.start + children.len();
     Ok(Some(JsonObject {
         id: 0,
         repr: container_repr(obj, len),
         value_type: string_value(obj.get_type().name()).into(),
         kind: "dataclass",
         children,
+        len: omitted_len(range.start, end, len),
+        truncated: ctx.truncated(&range, end, len),
     }))
 }
 
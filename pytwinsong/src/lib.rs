@@ -6,6 +6,7 @@ mod stdio;
 
 use crate::executor::start_executor;
 use crate::jobject::create_jobject_string;
+use comm::messages::JObjectLimits;
 use pyo3::intern;
 use pyo3::prelude::*;
 use std::ffi::OsString;
@@ -13,7 +14,10 @@ use tokio::runtime::Builder;
 
 #[pyfunction]
 fn start_kernel() -> PyResult<()> {
-    start_executor();
+    // Secret is sent by the server as the first line of stdin, it is read only once at start
+    let mut secret = String::new();
+    std::io::stdin().read_line(&mut secret)?;
+    start_executor(secret.trim_end().to_string());
     Ok(())
 }
 
@@ -52,12 +56,30 @@ fn start_server() -> PyResult<()> {
         .block_on(async {
             ::twinsong::server_cli(Some(args)).await;
         });
+    // Ctrl-C stopping the server was also recorded by Python's handler,
+    // we drop the pending KeyboardInterrupt as the server already terminated gracefully
+    Python::with_gil(|py| {
+        let _ = py.check_signals();
+    });
     Ok(())
 }
 
 #[pyfunction]
-fn create_jobject(py: Python, obj: Bound<PyAny>) -> PyResult<String> {
-    Ok(create_jobject_string(py, &obj).unwrap())
+#[pyo3(signature = (obj, max_depth=None, max_children=None, max_objects=None))]
+fn create_jobject(
+    py: Python,
+    obj: Bound<PyAny>,
+    max_depth: Option<usize>,
+    max_children: Option<usize>,
+    max_objects: Option<usize>,
+) -> PyResult<String> {
+    let default = JObjectLimits::default();
+    let limits = JObjectLimits {
+        max_depth: max_depth.unwrap_or(default.max_depth),
+        max_children: max_children.unwrap_or(default.max_children),
+        max_objects: max_objects.unwrap_or(default.max_objects),
+    };
+    Ok(create_jobject_string(py, &obj, limits).unwrap())
 }
 
 /// A Python module implemented in Rust.
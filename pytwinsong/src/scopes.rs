@@ -1,9 +1,9 @@
-use crate::jobject::create_jobject_string;
+use crate::jobject::create_jobject_summary_string;
 use comm::messages::OwnCodeScope;
 use comm::scopes::{ScopeId, SerializedGlobals};
 use pyo3::exceptions::PyValueError;
 use pyo3::types::{PyAnyMethods, PyDict, PyDictMethods};
-use pyo3::{Bound, BoundObject, Py, PyResult, Python, intern};
+use pyo3::{Bound, BoundObject, Py, PyAny, PyResult, Python, intern};
 use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -101,7 +101,7 @@ impl ScopedPyGlobals {
             .map(|(k, v)| {
                 (
                     k.to_string(),
-                    Arc::new(create_jobject_string(py, &v).unwrap()),
+                    Arc::new(create_jobject_summary_string(py, &v).unwrap()),
                 )
             })
             .collect();
@@ -113,6 +113,22 @@ impl ScopedPyGlobals {
         SerializedGlobals::new(self.name.clone(), variables, children)
     }
 
+    /// Finds a variable in the scope given by ids of scopes nested in this one
+    pub fn find_variable<'py>(
+        &self,
+        py: Python<'py>,
+        scope: &[ScopeId],
+        name: &str,
+    ) -> PyResult<Option<Bound<'py, PyAny>>> {
+        match scope.split_first() {
+            Some((id, rest)) => match self.children.get(id) {
+                Some(child) => child.find_variable(py, rest, name),
+                None => Ok(None),
+            },
+            None => self.variables.bind(py).get_item(name),
+        }
+    }
+
     pub fn as_py_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
         let result = PyDict::new(py);
         result.set_item(intern!(py, "name"), self.name.clone())?;
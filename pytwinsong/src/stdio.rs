@@ -1,31 +1,182 @@
 use crate::executor::FromExecutorMessage;
-use comm::messages::{KernelOutputValue, OutputFlag};
-use pyo3::{PyResult, pyclass, pymethods};
+use comm::messages::{
+    InputValue, KernelOutputValue, LogKind, LogRecord, MimeBundle, OutputFlag, SourceLocation,
+    StreamName,
+};
+use pyo3::exceptions::PyEOFError;
+use pyo3::{PyResult, Python, pyclass, pymethods};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc::UnboundedSender;
 use uuid::Uuid;
 
+/// How often pending signals (interrupt) are checked while waiting for an input from the user
+const INPUT_SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
 // A "tuple" struct
 #[pyclass]
 pub struct RedirectedStdio {
     sender: UnboundedSender<FromExecutorMessage>,
     cell_id: Uuid,
+    stream: StreamName,
 }
 
 impl RedirectedStdio {
-    pub fn new(sender: UnboundedSender<FromExecutorMessage>, cell_id: Uuid) -> Self {
-        RedirectedStdio { sender, cell_id }
+    pub fn new(
+        sender: UnboundedSender<FromExecutorMessage>,
+        cell_id: Uuid,
+        stream: StreamName,
+    ) -> Self {
+        RedirectedStdio {
+            sender,
+            cell_id,
+            stream,
+        }
     }
 }
 
 #[pymethods]
 impl RedirectedStdio {
     pub fn write(&self, text: String) -> PyResult<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let _ = self.sender.send(FromExecutorMessage::Output {
+            value: KernelOutputValue::Stream {
+                name: self.stream,
+                value: text,
+            },
+            cell_id: self.cell_id,
+            flag: OutputFlag::Running,
+            update: None,
+            finished: None,
+        });
+        Ok(())
+    }
+
+    /// Text is sent immediately, flushing is accepted for compatibility with file objects
+    pub fn flush(&self) -> PyResult<()> {
+        Ok(())
+    }
+
+    #[pyo3(signature = (bundle, display_id=None, update=false))]
+    pub fn display(
+        &self,
+        bundle: MimeBundle,
+        display_id: Option<String>,
+        update: bool,
+    ) -> PyResult<()> {
+        let _ = self.sender.send(FromExecutorMessage::Display {
+            cell_id: self.cell_id,
+            value: bundle,
+            display_id,
+            update,
+        });
+        Ok(())
+    }
+
+    /// Sends a warning or a log record as a structured output;
+    /// `cell_id` is set when `filename` belongs to a cell of the notebook
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (warning, category, level, logger, message, filename, lineno, cell_id))]
+    pub fn log(
+        &self,
+        warning: bool,
+        category: String,
+        level: u32,
+        logger: Option<String>,
+        message: String,
+        filename: Option<String>,
+        lineno: u32,
+        cell_id: Option<String>,
+    ) -> PyResult<()> {
         let _ = self.sender.send(FromExecutorMessage::Output {
-            value: KernelOutputValue::Text { value: text },
+            value: KernelOutputValue::Log {
+                value: LogRecord {
+                    kind: if warning {
+                        LogKind::Warning
+                    } else {
+                        LogKind::Logging
+                    },
+                    category,
+                    level,
+                    logger,
+                    message,
+                    source: filename.map(|filename| SourceLocation {
+                        filename,
+                        lineno,
+                        cell_id: cell_id.and_then(|id| Uuid::parse_str(&id).ok()),
+                    }),
+                },
+            },
             cell_id: self.cell_id,
             flag: OutputFlag::Running,
             update: None,
+            finished: None,
         });
         Ok(())
     }
 }
+
+pub struct InputReply {
+    pub request_id: Uuid,
+    pub value: InputValue,
+}
+
+/// Replies forwarded by the control thread, they are shared by all cells of the executor
+pub type InputReplies = Arc<Mutex<Receiver<InputReply>>>;
+
+#[pyclass]
+pub struct RedirectedStdin {
+    sender: UnboundedSender<FromExecutorMessage>,
+    replies: InputReplies,
+    cell_id: Uuid,
+}
+
+impl RedirectedStdin {
+    pub fn new(
+        sender: UnboundedSender<FromExecutorMessage>,
+        replies: InputReplies,
+        cell_id: Uuid,
+    ) -> Self {
+        RedirectedStdin {
+            sender,
+            replies,
+            cell_id,
+        }
+    }
+}
+
+#[pymethods]
+impl RedirectedStdin {
+    /// Asks the user for a line of text, it blocks until the answer arrives
+    #[pyo3(signature = (prompt, password=false))]
+    pub fn input(&self, py: Python, prompt: String, password: bool) -> PyResult<String> {
+        let request_id = Uuid::new_v4();
+        let _ = self.sender.send(FromExecutorMessage::InputRequest {
+            cell_id: self.cell_id,
+            request_id,
+            prompt,
+            password,
+        });
+        let replies = &self.replies;
+        loop {
+            py.check_signals()?;
+            let reply = py.allow_threads(|| {
+                replies
+                    .lock()
+                    .unwrap()
+                    .recv_timeout(INPUT_SIGNAL_CHECK_INTERVAL)
+            });
+            match reply {
+                Ok(reply) if reply.request_id == request_id => return Ok(reply.value.0),
+                // Answer to a request that was abandoned by an interrupt
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(PyEOFError::new_err("Connection to server is closed"));
+                }
+            }
+        }
+    }
+}
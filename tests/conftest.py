@@ -35,8 +35,35 @@ def key():
     return "".join(random.choice(chars) for _ in range(10))
 
 
+class HttpService:
+    def __init__(self, url, args, env, log):
+        self.url = url
+        self.args = args
+        self.env = env
+        self.log = log
+        self.process = None
+        self.start()
+
+    def start(self):
+        self.process = subprocess.Popen(
+            self.args,
+            stdout=self.log,
+            stderr=subprocess.STDOUT,
+            env=self.env,
+        )
+        time.sleep(0.15)
+
+    def restart(self, kill=False):
+        if kill:
+            self.process.kill()
+        else:
+            self.process.terminate()
+        self.process.wait(timeout=10)
+        self.start()
+
+
 @pytest.fixture
-def http_service(tmp_path, key):
+def http_service(tmp_path, key, request):
     global PORT
     PORT += 1
     with work_dir(tmp_path):
@@ -44,25 +71,27 @@ def http_service(tmp_path, key):
         log = open(log_path, "w")
         env = os.environ.copy()
         env["RUST_LOG"] = "DEBUG"
-        p = subprocess.Popen(
+        # Extra environment variables may be passed by indirect parametrization
+        env.update(getattr(request, "param", {}))
+        service = HttpService(
+            f"ws://127.0.0.1:{PORT}/ws",
             [BIN_DIR, "--port", str(PORT), "--key", key],
-            stdout=log,
-            stderr=subprocess.STDOUT,
-            env=env,
+            env,
+            log,
         )
-        time.sleep(0.15)
-        yield f"ws://127.0.0.1:{PORT}/ws"
+        yield service
         print("Shutting down http service")
+        p = service.process
         if p.poll() is None:
             p.kill()
             time.sleep(0.1)
-        else:
+        elif p.returncode != 0:
             raise Exception("HTTP service failed")
 
 
 @pytest.fixture
 def ws(http_service):
-    with connect(http_service) as ws:
+    with connect(http_service.url) as ws:
         yield ws
 
 
@@ -75,9 +104,10 @@ class Kernel:
         self.last_editor_node = None
         self.last_called_id = None
         self.last_update = None
+        self.last_timing = None
         self.editor_root_id = str(uuid.uuid4())
 
-    def run_code(self, code, called_id=None):
+    def send_code(self, code, called_id=None, timeout=None):
         cell_id = str(uuid.uuid4())
         if isinstance(code, str):
             called_id = str(uuid.uuid4())
@@ -98,7 +128,6 @@ class Kernel:
         }
         self.last_editor_node = editor_node
         self.last_called_id = called_id
-        outputs = []
         self.client.send_message(
             {
                 "type": "RunCode",
@@ -108,19 +137,139 @@ class Kernel:
                 "cell_id": cell_id,
                 "editor_node": editor_node,
                 "called_id": called_id,
+                "timeout": timeout,
             }
         )
+        return cell_id
+
+    def wait_for_outputs(self):
+        outputs = []
         while True:
             r = self.client.receive_message()
             print(">>>", r)
             if r["update"]:
                 self.last_update = dict(r["update"])
             self.last_cell_id = r["cell_id"]
+            self.last_timing = r["timing"]
             assert r["type"] == "Output"
             outputs.append(r["value"])
             if r["flag"] != "Running":
                 return outputs
 
+    def run_code(self, code, called_id=None):
+        self.send_code(code, called_id)
+        return self.wait_for_outputs()
+
+    def interrupt(self):
+        self.client.send_message(
+            {
+                "type": "InterruptRun",
+                "notebook_id": self.notebook_id,
+                "run_id": self.run_id,
+            }
+        )
+
+    def compute_queue(self):
+        self.client.send_message(
+            {
+                "type": "ComputeQueue",
+                "notebook_id": self.notebook_id,
+                "run_id": self.run_id,
+            }
+        )
+        r = self.client.receive_message()
+        assert r["type"] == "ComputeQueue"
+        return r["cells"]
+
+    def cancel_compute(self, cell_id):
+        self.client.send_message(
+            {
+                "type": "CancelCompute",
+                "notebook_id": self.notebook_id,
+                "run_id": self.run_id,
+                "cell_id": cell_id,
+            }
+        )
+
+    def fetch_output(self, cell_id, index, offset, size):
+        self.client.send_message(
+            {
+                "type": "FetchOutput",
+                "notebook_id": self.notebook_id,
+                "run_id": self.run_id,
+                "cell_id": cell_id,
+                "index": index,
+                "offset": offset,
+                "size": size,
+            }
+        )
+        r = self.client.receive_message()
+        assert r["type"] == "OutputPage"
+        return r
+
+    def inspect(self, variable, path=(), offset=0, limit=100, scope=()):
+        self.client.send_message(
+            {
+                "type": "Inspect",
+                "notebook_id": self.notebook_id,
+                "run_id": self.run_id,
+                "scope": list(scope),
+                "variable": variable,
+                "path": list(path),
+                "offset": offset,
+                "limit": limit,
+            }
+        )
+        r = self.client.receive_message()
+        assert r["type"] == "InspectResult"
+        return r
+
+    def set_jobject_limits(self, limits):
+        self.client.send_message(
+            {
+                "type": "SetJObjectLimits",
+                "notebook_id": self.notebook_id,
+                "run_id": self.run_id,
+                "limits": limits,
+            }
+        )
+
+    def wait_for_input_request(self):
+        r = self.client.receive_message()
+        assert r["type"] == "InputRequest"
+        return r["request"]
+
+    def input_reply(self, request_id, value):
+        self.client.send_message(
+            {
+                "type": "InputReply",
+                "notebook_id": self.notebook_id,
+                "run_id": self.run_id,
+                "request_id": request_id,
+                "value": value,
+            }
+        )
+        r = self.client.receive_message()
+        assert r["type"] == "InputAnswered"
+
+    def set_notebook_timeout(self, timeout):
+        self.client.send_message(
+            {
+                "type": "SetNotebookTimeout",
+                "notebook_id": self.notebook_id,
+                "timeout": timeout,
+            }
+        )
+
+    def restart(self):
+        self.client.send_message(
+            {
+                "type": "RestartKernel",
+                "notebook_id": self.notebook_id,
+                "run_id": self.run_id,
+            }
+        )
+
     def run_code_simple(self, code):
         r = self.run_code(code)
         if r[-1]["type"] == "None":
@@ -140,7 +289,7 @@ class Client:
     def receive_message(self, skip_async=True):
         while True:
             r = json.loads(self.ws.recv())
-            if skip_async and r["type"] == "DirList":
+            if skip_async and r["type"] in ("DirList", "KernelsUpdate"):
                 continue
             return r
 
@@ -160,6 +309,29 @@ class Client:
         assert r["type"] == "Kernels"
         return r["kernels"]
 
+    def wait_for_kernel_pool(self, size, exclude=(), timeout=10):
+        end = time.time() + timeout
+        while True:
+            self.send_message({"type": "KernelList"})
+            r = self.receive_message()
+            assert r["type"] == "Kernels"
+            pool = set(r["pool"])
+            if len(pool) == size and not pool.intersection(exclude):
+                return pool
+            assert time.time() < end, "Kernel pool was not filled"
+            time.sleep(0.05)
+
+    def kernel_log(self, kernel_id):
+        self.send_message({"type": "KernelLog", "kernel_id": kernel_id})
+        r = self.receive_message()
+        assert r["type"] == "KernelLogTail"
+        return r
+
+    def stop_kernel(self, kernel_id, force=False):
+        self.send_message(
+            {"type": "KillKernel" if force else "StopKernel", "kernel_id": kernel_id}
+        )
+
     def create_new_kernel(self, notebook_id) -> Kernel:
         run_id = str(uuid.uuid4())
         self.send_message(
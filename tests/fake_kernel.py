@@ -0,0 +1,37 @@
+#!/usr/bin/env python3
+"""
+Kernel that logs in with an unsupported protocol version.
+It is used in tests as TWINSONG_PYTHON, so all arguments are ignored.
+"""
+
+import os
+import socket
+import struct
+import sys
+import uuid
+
+PROTOCOL_VERSION = 999
+
+
+def bincode_bytes(data: bytes) -> bytes:
+    return struct.pack("<Q", len(data)) + data
+
+
+def main():
+    host, port = os.environ["KERNEL_CONNECT"].split(":")
+    kernel_id = uuid.UUID(os.environ["KERNEL_ID"])
+    secret = sys.stdin.readline().strip().encode()
+    # Stable header of FromKernelMessage::Login
+    msg = (
+        struct.pack("<II", 0, PROTOCOL_VERSION)
+        + bincode_bytes(kernel_id.bytes)
+        + bincode_bytes(secret)
+    )
+    with socket.create_connection((host, int(port))) as s:
+        s.sendall(struct.pack("<I", len(msg)) + msg)
+        # Wait until the server closes the connection
+        s.recv(1)
+
+
+if __name__ == "__main__":
+    main()
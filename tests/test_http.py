@@ -1,27 +1,560 @@
+import importlib.util
+import json
+import os
 import shutil
+import signal
+import subprocess
+import sys
 import uuid
 
+import pytest
 import toml
 import psutil
 import time
 
-from conftest import Kernel
+from conftest import Client, Kernel
+from websockets.sync.client import connect
 from utils import build_jobject_from_text
 
+FAKE_KERNEL = os.path.join(os.path.dirname(os.path.abspath(__file__)), "fake_kernel.py")
+
 
 def test_execute_command(client):
     r = client.create_new_notebook()
     k = client.create_new_kernel(r["notebook"]["id"])
     assert "3" == k.run_code_simple("1 + 2")
     assert [
-        {"type": "Text", "value": "Hello"},
-        {"type": "Text", "value": "\n"},
-        {"type": "Text", "value": "World"},
-        {"type": "Text", "value": "\n"},
+        {"type": "Stream", "name": "stdout", "value": "Hello"},
+        {"type": "Stream", "name": "stdout", "value": "\n"},
+        {"type": "Stream", "name": "stdout", "value": "World"},
+        {"type": "Stream", "name": "stdout", "value": "\n"},
         {"type": "None"},
     ] == k.run_code("print('Hello')\nprint('World')")
 
 
+def test_mime_bundle(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code(
+        "class A:\n"
+        "    def _repr_html_(self): return '<b>A</b>'\n"
+        "    def __repr__(self): return 'A()'\n"
+        "class B:\n"
+        "    def _repr_png_(self): return b'PNG'\n"
+        "    def _repr_markdown_(self): return '# B'\n"
+        "    def _repr_json_(self): return {'b': [1, 2]}\n"
+        "    def _repr_svg_(self): return None\n"
+        "    def __repr__(self): return 'B()'\n"
+        "class C:\n"
+        "    def _repr_mimebundle_(self, include=None, exclude=None):\n"
+        "        return {'image/png': 'Q0NDQw==', 'text/plain': 'C!'}, {}\n"
+    )
+    assert k.run_code("A()") == [{"type": "Html", "value": "<b>A</b>"}]
+    assert k.run_code("B()") == [
+        {
+            "type": "MimeBundle",
+            "value": {
+                "application/json": '{"b": [1, 2]}',
+                "image/png": "UE5H",
+                "text/markdown": "# B",
+                "text/plain": "B()",
+            },
+        }
+    ]
+    assert k.run_code("C()") == [
+        {
+            "type": "MimeBundle",
+            "value": {"image/png": "Q0NDQw==", "text/plain": "C!"},
+        }
+    ]
+    # Classes are not asked for representations of their instances
+    assert k.run_code("B") == [{"type": "Text", "value": "<class 'B'>"}]
+
+    client.send_message(
+        {
+            "type": "SaveNotebook",
+            "notebook_id": k.notebook_id,
+            "editor_root": k.last_editor_node,
+        }
+    )
+    assert client.receive_message()["type"] == "SaveCompleted"
+    shutil.copy(path, "copy.tsnb")
+    shutil.copytree(path + ".runs", "copy.tsnb.runs")
+    r = client.load_notebook("copy.tsnb")
+    cells = r["notebook"]["runs"][0]["output_cells"]
+    assert cells[2]["values"] == [
+        {
+            "type": "MimeBundle",
+            "value": {
+                "application/json": '{"b": [1, 2]}',
+                "image/png": "UE5H",
+                "text/markdown": "# B",
+                "text/plain": "B()",
+            },
+        }
+    ]
+
+
+def test_display(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    cell_id = k.send_code(
+        "from twinsong.display import display, update_display, HTML\n"
+        "print('a')\n"
+        "h = display(HTML('<b>1</b>'), display_id='p')\n"
+        "display('text')\n"
+        "update_display(HTML('<b>2</b>'), display_id='p')\n"
+        "3"
+    )
+    messages = [client.receive_message()]
+    while messages[-1]["type"] != "Output" or messages[-1]["flag"] == "Running":
+        messages.append(client.receive_message())
+    html1 = {"text/html": "<b>1</b>", "text/plain": "<twinsong.display.HTML object>"}
+    html2 = {"text/html": "<b>2</b>", "text/plain": "<twinsong.display.HTML object>"}
+    assert [(m["type"], m["value"]) for m in messages] == [
+        ("Output", {"type": "Stream", "name": "stdout", "value": "a"}),
+        ("Output", {"type": "Stream", "name": "stdout", "value": "\n"}),
+        ("Output", {"type": "MimeBundle", "value": html1}),
+        ("Output", {"type": "MimeBundle", "value": {"text/plain": "'text'"}}),
+        ("OutputReplaced", {"type": "MimeBundle", "value": html2}),
+        ("Output", {"type": "Text", "value": "3"}),
+    ]
+    assert messages[4]["cell_id"] == cell_id
+    assert messages[4]["index"] == 1
+
+    # Display of a finished cell is updated from a later cell
+    k.send_code("h.update(HTML('<b>3</b>')); print(type(h).__name__)")
+    r = client.receive_message()
+    assert r["type"] == "OutputReplaced"
+    assert r["cell_id"] == cell_id
+    assert r["value"]["value"]["text/html"] == "<b>3</b>"
+    assert k.wait_for_outputs()[0] == {
+        "type": "Stream",
+        "name": "stdout",
+        "value": "DisplayHandle",
+    }
+
+    r = client.load_notebook(path)
+    values = r["notebook"]["runs"][0]["output_cells"][0]["values"]
+    assert values[1]["value"]["text/html"] == "<b>3</b>"
+    assert values[2] == {"type": "MimeBundle", "value": {"text/plain": "'text'"}}
+
+
+@pytest.mark.skipif(
+    importlib.util.find_spec("IPython") is not None, reason="IPython is installed"
+)
+def test_ipython_shim(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.send_code(
+        "import IPython\n"
+        "from IPython.display import display, Markdown\n"
+        "display(Markdown('# Title'))\n"
+        "IPython.get_ipython()"
+    )
+    assert k.wait_for_outputs() == [
+        {
+            "type": "MimeBundle",
+            "value": {
+                "text/markdown": "# Title",
+                "text/plain": "<twinsong.display.Markdown object>",
+            },
+        },
+        {"type": "None"},
+    ]
+
+
+@pytest.mark.skipif(
+    importlib.util.find_spec("IPython") is None, reason="IPython is not installed"
+)
+def test_ipython_display(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.send_code(
+        "from IPython.display import display, update_display, Markdown\n"
+        "from IPython.core.display import display as core_display\n"
+        "h = display(Markdown('# Title'), display_id='t')\n"
+        "core_display(Markdown('# Other'))\n"
+        "update_display(Markdown('# New'), display_id='t')"
+    )
+    messages = [client.receive_message()]
+    while messages[-1]["type"] != "Output" or messages[-1]["flag"] == "Running":
+        messages.append(client.receive_message())
+    def markdown(text):
+        return {
+            "type": "MimeBundle",
+            "value": {
+                "text/markdown": text,
+                "text/plain": "<IPython.core.display.Markdown object>",
+            },
+        }
+
+    assert [(m["type"], m["value"]) for m in messages] == [
+        ("Output", markdown("# Title")),
+        ("Output", markdown("# Other")),
+        ("OutputReplaced", markdown("# New")),
+        ("Output", {"type": "None"}),
+    ]
+
+
+def test_matplotlib(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    # Matplotlib is not imported by the kernel and subprocesses do not inherit the backend
+    assert k.run_code_simple(
+        "import os, sys\n('MPLBACKEND' in os.environ, 'matplotlib' in sys.modules)"
+    ) == "(False, False)"
+
+    pytest.importorskip("matplotlib")
+    assert (
+        k.run_code_simple("import matplotlib; matplotlib.get_backend()")
+        == "'module://twinsong.driver.mpl_backend'"
+    )
+    r = k.run_code(
+        "import matplotlib.pyplot as plt\n"
+        "plt.plot([1, 2, 3])\n"
+        "plt.show()\n"
+        "print('between')\n"
+        "plt.figure()\n"
+        "plt.plot([3, 2, 1])\n"
+        "None"
+    )
+    assert [v["type"] for v in r] == ["MimeBundle", "Stream", "MimeBundle", "None"]
+    for v in (r[0], r[2]):
+        assert set(v["value"]) == {"image/png", "text/plain"}
+        assert v["value"]["text/plain"].startswith("Figure(")
+    # Shown figures are closed
+    assert k.run_code_simple("len(plt.get_fignums())") == "0"
+
+
+def test_stderr_output(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    assert k.run_code(
+        "import sys\n"
+        "print('out1', end='')\n"
+        "print('err1', end='', file=sys.stderr)\n"
+        "sys.stderr.write('err2')\n"
+        "sys.stderr.flush()\n"
+        "print('out2', end='')\n"
+    ) == [
+        {"type": "Stream", "name": "stdout", "value": "out1"},
+        {"type": "Stream", "name": "stderr", "value": "err1"},
+        {"type": "Stream", "name": "stderr", "value": "err2"},
+        {"type": "Stream", "name": "stdout", "value": "out2"},
+        {"type": "None"},
+    ]
+    # Warnings are not written into stderr, but sent as log records
+    r = k.run_code("import warnings; warnings.warn('Careful')")
+    assert r[0]["type"] == "Log"
+    assert r[0]["value"]["category"] == "UserWarning"
+    assert r[0]["value"]["message"] == "Careful"
+
+    # Chunks are merged only within the same stream
+    r = client.load_notebook(path)
+    values = r["notebook"]["runs"][0]["output_cells"][0]["values"]
+    assert values == [
+        {"type": "Stream", "name": "stdout", "value": "out1"},
+        {"type": "Stream", "name": "stderr", "value": "err1err2"},
+        {"type": "Stream", "name": "stdout", "value": "out2"},
+        {"type": "None"},
+    ]
+
+
+def test_terminal_output(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.send_code(
+        "import sys\n"
+        "for i in range(0, 101, 50):\n"
+        "    bar = ('#' * (i // 25)).ljust(4)\n"
+        "    sys.stderr.write(f'\\r{i:3}%|{bar}|')\n"
+        "sys.stderr.write('\\n')\n"
+        "print('abcd\\b\\bXY\\rZ')\n"
+        "print('long line\\r\\x1b[Kshort')\n"
+        "print('\\x1b[1;31mred\\x1b[0m plain '\n"
+        "      '\\x1b[38;5;208mor\\x1b[38;2;1;2;3mgb\\x1b[m')\n"
+    )
+    messages = []
+    while True:
+        r = client.receive_message()
+        assert r["type"] == "Output"
+        messages.append(r)
+        if r["flag"] != "Running":
+            break
+    # Redrawn line is sent again, a new line is only appended
+    assert [(m["value"]["value"], m["replace_from"]) for m in messages[:4]] == [
+        ("  0%|    |", None),
+        (" 50%|##  |", 0),
+        ("100%|####|", 0),
+        ("\n", 10),
+    ]
+
+    r = client.load_notebook(path)
+    values = r["notebook"]["runs"][0]["output_cells"][0]["values"]
+    assert values == [
+        {"type": "Stream", "name": "stderr", "value": "100%|####|\n"},
+        {
+            "type": "Stream",
+            "name": "stdout",
+            "value": "ZbXY\nshort\nred plain orgb\n",
+            "spans": [
+                {"start": 11, "end": 14, "fg": 1, "bold": True},
+                {"start": 21, "end": 23, "fg": 208},
+                {"start": 23, "end": 25, "fg": [1, 2, 3]},
+            ],
+        },
+        {"type": "None"},
+    ]
+
+
+@pytest.mark.parametrize(
+    "http_service",
+    [{"TWINSONG_OUTPUT_LIMIT": "2000", "TWINSONG_CELL_OUTPUT_LIMIT": "3500"}],
+    indirect=True,
+)
+def test_output_limits(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    cell_id = k.send_code(
+        "for i in range(1000):\n    print(f'line {i:04}', flush=True)\n'x' * 10000"
+    )
+    outputs = k.wait_for_outputs()
+    assert len(json.dumps(outputs[-1])) < 3000
+
+    r = client.load_notebook(path)
+    stream, result = r["notebook"]["runs"][0]["output_cells"][0]["values"]
+    full_stream = "".join(f"line {i:04}\n" for i in range(1000))
+    full_result = repr("x" * 10000)
+    value = stream["value"]
+    assert len(value) <= 2000
+    assert stream["truncated"]["offset"] == 1000
+    assert stream["truncated"]["omitted"] == len(full_stream) - len(value)
+    assert value.startswith(full_stream[:1000])
+    assert full_stream.endswith(value[1000:])
+    # Value is shortened to the rest of the limit of the cell
+    assert result["type"] == "Text"
+    limit = 3500 - len(value)
+    assert len(result["value"]) == limit
+    assert result["truncated"]["offset"] == limit // 2
+    assert os.path.isdir(path + ".spool")
+
+    for index, value, full in ((0, stream, full_stream), (1, result, full_result)):
+        truncated = value["truncated"]
+        pages = []
+        offset = 0
+        while offset < truncated["omitted"]:
+            r = k.fetch_output(cell_id, index, offset, 3000)
+            assert r["offset"] == offset
+            assert r["omitted"] == truncated["omitted"]
+            pages.append(r["text"])
+            offset = r["next_offset"]
+        head = value["value"][: truncated["offset"]]
+        tail = value["value"][truncated["offset"] :]
+        assert head + "".join(pages) + tail == full
+
+    # A single unfinished line is cut too, only its tail may be rewritten
+    cell_id = k.send_code(
+        "for i in range(1000):\n"
+        "    print(f'{i:04}', end=' ', flush=True)\n"
+        "print('\\rend', end='')"
+    )
+    k.wait_for_outputs()
+    r = client.load_notebook(path)
+    stream, _ = r["notebook"]["runs"][0]["output_cells"][1]["values"]
+    full_line = "".join(f"{i:04} " for i in range(1000))
+    value = stream["value"]
+    truncated = stream["truncated"]
+    assert len(value) <= 2000
+    assert truncated["offset"] == 1000
+    assert value.startswith(full_line[:1000])
+    head = value[:1000]
+    tail = value[1000:]
+    assert tail.startswith("end")
+    assert full_line.endswith(tail[3:])
+    r = k.fetch_output(cell_id, 0, 0, truncated["omitted"])
+    assert head + r["text"] + full_line[-len(tail) :] == full_line
+
+    # Displayed values count into the limit of the cell, rich representations
+    # over the limit are dropped and listed in the value
+    k.send_code(
+        "from twinsong.display import display, HTML\n"
+        "import sys\n"
+        "print('s' * 3000)\n"
+        "print('e' * 3000, file=sys.stderr)\n"
+        "display('z' * 1600)\n"
+        "display(HTML('<b>' + 'y' * 3000 + '</b>'))"
+    )
+    k.wait_for_outputs()
+    r = client.load_notebook(path)
+    out, err, text, html = r["notebook"]["runs"][0]["output_cells"][2]["values"][:4]
+    assert len(out["value"]) + len(err["value"]) > 2500
+    assert text["type"] == "Text"
+    assert len(text["value"]) == 1024
+    assert text["truncated"]["omitted"] == 1602 - len(text["value"])
+    assert "dropped" not in text
+    assert html == {
+        "type": "Text",
+        "value": "<twinsong.display.HTML object>",
+        "dropped": ["text/html"],
+    }
+
+    # Values that are not truncated cannot be fetched
+    k.client.send_message(
+        {
+            "type": "FetchOutput",
+            "notebook_id": k.notebook_id,
+            "run_id": k.run_id,
+            "cell_id": cell_id,
+            "index": 5,
+            "offset": 0,
+            "size": 100,
+        }
+    )
+    assert k.client.receive_message()["type"] == "Error"
+
+    # Omitted texts are removed together with the run
+    assert os.listdir(path + ".spool")
+    client.send_message(
+        {"type": "CloseRun", "notebook_id": k.notebook_id, "run_id": k.run_id}
+    )
+    end = time.time() + 5
+    while os.path.exists(path + ".spool"):
+        assert time.time() < end, "Spool was not removed"
+        time.sleep(0.05)
+
+
+def test_logs(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code(
+        "import logging, warnings\n"
+        "def f():\n"
+        "    import warnings\n"
+        "    warnings.warn('old api')"
+    )
+    called_id = str(uuid.uuid4())
+    k.send_code(
+        {
+            "type": "Cell",
+            "id": called_id,
+            "code": "f()\n"
+            "warnings.warn('bad value', UserWarning)\n"
+            "logging.getLogger('app').info('hidden')\n"
+            "logging.getLogger('app').warning('disk %s', 'full')\n"
+            "logging.getLogger('app').setLevel(logging.DEBUG)\n"
+            "logging.getLogger('app').debug('details')\n"
+            "print('done')",
+        }
+    )
+    values = k.wait_for_outputs()
+    logs = [v["value"] for v in values if v["type"] == "Log"]
+    assert [(v["kind"], v["category"], v["level"], v["message"]) for v in logs] == [
+        ("Warning", "UserWarning", 30, "old api"),
+        ("Warning", "UserWarning", 30, "bad value"),
+        ("Logging", "WARNING", 30, "disk full"),
+        ("Logging", "DEBUG", 10, "details"),
+    ]
+    assert [v["logger"] for v in logs] == [None, None, "app", "app"]
+    # Code from the previous computation is mapped to its own cell
+    assert logs[0]["source"]["cell_id"] != called_id
+    assert logs[0]["source"]["lineno"] == 4
+    assert [v["source"]["cell_id"] for v in logs[1:]] == [called_id] * 3
+    assert [v["source"]["lineno"] for v in logs[1:]] == [2, 4, 6]
+    assert [v["value"] for v in values[4:-1]] == ["done", "\n"]
+
+    # Configured logging is not changed
+    values = k.run_code(
+        "import sys\n"
+        "logging.basicConfig(stream=sys.stdout, format='%(levelname)s %(message)s')\n"
+        "logging.getLogger('app').warning('configured')"
+    )
+    assert values[0] == {
+        "type": "Stream",
+        "name": "stdout",
+        "value": "WARNING configured\n",
+    }
+
+    r = client.load_notebook(path)
+    values = r["notebook"]["runs"][0]["output_cells"][1]["values"]
+    assert [v["type"] for v in values] == ["Log"] * 4 + ["Stream", "None"]
+    assert values[2]["value"]["message"] == "disk full"
+
+
+def test_exception_traceback(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code("def f():\n    raise ValueError('bad')")
+    e = k.run_code("x = 1\nf()")[-1]
+    assert e["type"] == "Exception"
+    assert e["value"]["message"] == "ValueError: bad"
+    traceback = e["value"]["traceback"]
+    assert 'File "<cell>", line 2, in <module>' in traceback
+    assert 'File "<cell>", line 2, in f' in traceback
+    assert "<cell " not in traceback
+
+    e = k.run_code("x = (")[-1]
+    assert e["type"] == "Exception"
+    assert "<cell>, line 1" in e["value"]["message"]
+
+
+def test_input(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.send_code("name = input('Name: ')\nname.upper()")
+    request = k.wait_for_input_request()
+    assert request["prompt"] == "Name: "
+    assert not request["password"]
+    k.input_reply(request["request_id"], "alice")
+    assert k.wait_for_outputs() == [
+        {"type": "Stream", "name": "stdout", "value": "Name: alice\n"},
+        {"type": "Text", "value": "'ALICE'"},
+    ]
+
+    k.send_code("import getpass\nlen(getpass.getpass())")
+    request = k.wait_for_input_request()
+    assert request["prompt"] == "Password: "
+    assert request["password"]
+    # Pending request is a part of the run, so a reloaded notebook can answer it
+    r = client.load_notebook(path)
+    assert r["notebook"]["runs"][0]["input_request"] == request
+    k.input_reply(request["request_id"], "s3cr3t")
+    assert k.wait_for_outputs() == [
+        {"type": "Stream", "name": "stdout", "value": "Password: \n"},
+        {"type": "Text", "value": "6"},
+    ]
+    r = client.load_notebook(path)
+    assert r["notebook"]["runs"][0]["input_request"] is None
+    assert "s3cr3t" not in json.dumps(r)
+
+    # Reply to a request that is not pending is refused
+    k.client.send_message(
+        {
+            "type": "InputReply",
+            "notebook_id": k.notebook_id,
+            "run_id": k.run_id,
+            "request_id": request["request_id"],
+            "value": "x",
+        }
+    )
+    assert k.client.receive_message()["type"] == "Error"
+
+    # Waiting for input can be interrupted
+    k.send_code("input()")
+    k.wait_for_input_request()
+    k.interrupt()
+    r = k.wait_for_outputs()
+    assert "KeyboardInterrupt" in r[-1]["value"]["message"]
+    assert k.run_code_simple("1 + 1") == "2"
+
+
 def test_globals_update_without_scopes(client):
     r = client.create_new_notebook()
     k = client.create_new_kernel(r["notebook"]["id"])
@@ -85,6 +618,115 @@ def test_globals_update_scopes(client):
     assert k.last_update["children"][group_id1]["variables"]["x"] is None
 
 
+def test_globals_inspect(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code("big = list(range(1_000_000))\nd = {'a': [1, 2], 'b': 'x'}")
+    # Only summaries of variables are sent after a computation
+    big = build_jobject_from_text(k.last_update["variables"]["big"])
+    assert big == {
+        "repr": "1000000 items",
+        "value_type": "list",
+        "kind": "list",
+        "len": 1000000,
+    }
+
+    r = k.inspect("big", offset=10, limit=3)
+    assert r["error"] is None
+    assert (r["variable"], r["path"], r["offset"]) == ("big", [], 10)
+    assert build_jobject_from_text(r["object"]) == {
+        "repr": "1000000 items",
+        "value_type": "list[int]",
+        "kind": "list",
+        "len": 1000000,
+        "children": [
+            (str(i), {"repr": str(i), "value_type": "int", "kind": "number"})
+            for i in range(10, 13)
+        ],
+    }
+
+    # Children are sent as summaries and they are inspected by their index
+    d = build_jobject_from_text(k.inspect("d")["object"])
+    assert d["children"] == [
+        ("a", {"repr": "[1, 2]", "value_type": "list", "kind": "list", "len": 2}),
+        ("b", {"repr": '"x"', "value_type": "str", "kind": "string"}),
+    ]
+    a = build_jobject_from_text(k.inspect("d", path=[0])["object"])
+    assert a == {
+        "repr": "[1, 2]",
+        "value_type": "list[int]",
+        "kind": "list",
+        "children": [
+            ("0", {"repr": "1", "value_type": "int", "kind": "number"}),
+            ("1", {"repr": "2", "value_type": "int", "kind": "number"}),
+        ],
+    }
+
+    group_id = str(uuid.uuid4())
+    k.run_code(
+        {
+            "type": "Group",
+            "id": group_id,
+            "name": "G1",
+            "scope": "Own",
+            "children": [
+                {"type": "Cell", "id": str(uuid.uuid4()), "code": "z = (7,)"},
+            ],
+        }
+    )
+    z = build_jobject_from_text(k.inspect("z", scope=[group_id])["object"])
+    assert z["children"] == [
+        ("0", {"repr": "7", "value_type": "int", "kind": "number"}),
+    ]
+
+    r = k.inspect("z")
+    assert r["object"] is None
+    assert r["error"] == "Variable 'z' not found"
+    r = k.inspect("d", path=[0, 5])
+    assert r["object"] is None
+    assert r["error"] == "Object not found in variable 'd'"
+
+
+def test_globals_inspect_limits(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code("x = [list(range(5)), [[1]]]")
+    limits = {"max_depth": 2, "max_children": 3, "max_objects": 100}
+    k.set_jobject_limits(limits)
+
+    r = build_jobject_from_text(k.inspect("x", path=[0])["object"])
+    assert [name for name, _ in r["children"]] == ["0", "1", "2"]
+    assert (r["len"], r["truncated"]) == (5, 2)
+    # Pages do not go behind the limit
+    r = build_jobject_from_text(k.inspect("x", path=[0], offset=3)["object"])
+    assert "children" not in r
+    assert (r["len"], r["truncated"]) == (5, 2)
+    # Depth is counted from the variable
+    r = build_jobject_from_text(k.inspect("x", path=[1, 0])["object"])
+    assert r == {
+        "repr": "[1]",
+        "value_type": "list",
+        "kind": "list",
+        "len": 1,
+        "truncated": 1,
+    }
+
+    # Limits are stored with the run
+    client.send_message(
+        {
+            "type": "SaveNotebook",
+            "notebook_id": k.notebook_id,
+            "editor_root": k.last_editor_node,
+        }
+    )
+    assert client.receive_message()["type"] == "SaveCompleted"
+    shutil.copy(path, "copy.tsnb")
+    shutil.copytree(path + ".runs", "copy.tsnb.runs")
+    r = client.load_notebook("copy.tsnb")
+    assert r["notebook"]["runs"][0]["jobject_limits"] == limits
+
+
 def test_parent_scope(client):
     r = client.create_new_notebook()
     k = client.create_new_kernel(r["notebook"]["id"])
@@ -154,6 +796,7 @@ def test_save_notebook_plain(client):
         {
             "id": k.run_id,
             "kernel_state": {"type": "Closed"},
+            "kernel_restarts": [],
             "output_cells": [
                 {
                     "editor_node": k.last_editor_node,
@@ -162,7 +805,8 @@ def test_save_notebook_plain(client):
                     "id": cell_id,
                     "values": [
                         {
-                            "type": "Text",
+                            "type": "Stream",
+                            "name": "stdout",
                             "value": "Hello\nworld!\n",
                         },
                         {
@@ -172,6 +816,12 @@ def test_save_notebook_plain(client):
                 },
             ],
             "title": "Run Test",
+            "input_request": None,
+            "jobject_limits": {
+                "max_children": 10000,
+                "max_depth": 16,
+                "max_objects": 100000,
+            },
         }
     ]
     client.send_message(
@@ -205,6 +855,8 @@ def test_save_notebook_plain(client):
     r = client.load_notebook("copy.tsnb")
     for run in r["notebook"]["runs"]:
         del run["globals"]
+        for cell in run["output_cells"]:
+            assert cell.pop("timing") == k.last_timing
     assert r == {
         "type": "NewNotebook",
         "notebook": {
@@ -212,12 +864,15 @@ def test_save_notebook_plain(client):
             "runs": runs,
             "id": notebook_id + 1,
             "path": "copy.tsnb",
+            "default_timeout": None,
             "editor_open_nodes": ["a0ff2759-edf5-44ac-a367-6d86c6bc4bcf"],
         },
     }
     r2 = client.load_notebook("copy.tsnb")
     for run in r2["notebook"]["runs"]:
         del run["globals"]
+        for cell in run["output_cells"]:
+            del cell["timing"]
     assert r == r2
     with open("copy.tsnb") as f:
         data2 = toml.loads(f.read())
@@ -328,10 +983,10 @@ def test_execute_tree(client):
     }
 
     assert [
-        {"type": "Text", "value": "One"},
-        {"type": "Text", "value": "\n"},
-        {"type": "Text", "value": "Two"},
-        {"type": "Text", "value": "\n"},
+        {"type": "Stream", "name": "stdout", "value": "One"},
+        {"type": "Stream", "name": "stdout", "value": "\n"},
+        {"type": "Stream", "name": "stdout", "value": "Two"},
+        {"type": "Stream", "name": "stdout", "value": "\n"},
         {"type": "Text", "value": "10"},
     ] == k.run_code(code)
 
@@ -398,3 +1053,576 @@ def test_fork(client):
         }
     )
     assert r == [{"type": "Text", "value": "4"}]
+
+
+def test_interrupt(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code("x = 5")
+    k.send_code("while True: pass")
+    k.send_code("x + 1")
+    time.sleep(0.5)
+    k.interrupt()
+    r = k.wait_for_outputs()
+    assert r[-1]["type"] == "Exception"
+    assert r[-1]["value"]["message"].startswith("KeyboardInterrupt")
+    assert [{"type": "Text", "value": "6"}] == k.wait_for_outputs()
+
+    # Interrupting idle kernel does nothing
+    k.interrupt()
+    time.sleep(0.3)
+    assert "10" == k.run_code_simple("import time; time.sleep(0.2); x * 2")
+
+
+def test_cancel_compute(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    cell1 = k.send_code("import time; time.sleep(1); 1")
+    cell2 = k.send_code("2")
+    cell3 = k.send_code("3")
+    assert k.compute_queue() == [cell2, cell3]
+
+    k.cancel_compute(cell2)
+    r = client.receive_message()
+    assert r["type"] == "ComputeCancelled"
+    assert r["cell_id"] == cell2
+    assert k.compute_queue() == [cell3]
+
+    # Running cell cannot be cancelled
+    k.cancel_compute(cell1)
+    r = client.receive_message()
+    assert r["type"] == "Error"
+
+    assert [{"type": "Text", "value": "1"}] == k.wait_for_outputs()
+    assert k.last_cell_id == cell1
+    assert [{"type": "Text", "value": "3"}] == k.wait_for_outputs()
+    assert k.last_cell_id == cell3
+    assert k.compute_queue() == []
+
+    r = client.load_notebook(path)
+    run = r["notebook"]["runs"][0]
+    flags = [c["flag"] for c in run["output_cells"]]
+    assert flags == ["Success", "Cancelled", "Success"]
+
+
+def test_cell_timing(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.send_code("import time; time.sleep(1)")
+    k.send_code("print('Hello'); time.sleep(0.5)")
+    k.wait_for_outputs()
+    timing1 = k.last_timing
+    assert timing1["duration_ms"] >= 1000
+    r = client.receive_message()
+    assert r["type"] == "Output"
+    assert r["flag"] == "Running"
+    assert r["timing"]["finished"] is None
+    assert r["timing"]["duration_ms"] is None
+    k.wait_for_outputs()
+    timing2 = k.last_timing
+    assert 500 <= timing2["duration_ms"] < 1000
+    # The second cell waited in the queue until the first one was finished
+    assert timing2["queued"] < timing1["finished"]
+    assert timing1["finished"] <= timing2["started"]
+    assert timing2["started"] < timing2["finished"]
+
+    # Times are taken by the kernel, so serialization of globals is not included
+    start = time.time()
+    k.run_code(
+        "class Slow:\n"
+        "    def __repr__(self):\n"
+        "        import time\n"
+        "        time.sleep(1)\n"
+        "        return 'slow'\n"
+        "x = Slow()"
+    )
+    assert time.time() - start >= 1
+    assert k.last_timing["duration_ms"] < 500
+
+
+def test_restart_kernel(client):
+    r = client.create_new_notebook()
+    notebook_id = r["notebook"]["id"]
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(notebook_id)
+    k.run_code("x = 1")
+    old_pid = client.kernel_list()[0]["pid"]
+
+    k.restart()
+    r = client.receive_message()
+    assert r["type"] == "KernelRestarted"
+    assert r["restart"]["position"] == 1
+    r = client.receive_message()
+    assert r["type"] == "KernelReady"
+
+    r = k.run_code("x")
+    assert r[-1]["type"] == "Exception"
+    assert "NameError" in r[-1]["value"]["message"]
+    assert "2" == k.run_code_simple("y = 2; y")
+
+    time.sleep(0.5)
+    assert not psutil.pid_exists(old_pid)
+    klist = client.kernel_list()
+    assert len(klist) == 1
+    assert klist[0]["pid"] != old_pid
+
+    r = client.load_notebook(path)
+    run = r["notebook"]["runs"][0]
+    assert len(run["output_cells"]) == 3
+    assert [x["position"] for x in run["kernel_restarts"]] == [1]
+    assert list(run["globals"]["variables"].keys()) == ["y"]
+
+
+def test_stop_kernel(client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code("x = 42")
+    [kernel] = client.kernel_list()
+
+    client.stop_kernel(kernel["kernel_id"])
+    r = client.receive_message()
+    assert r["type"] == "KernelClosed"
+    assert r["run_id"] == k.run_id
+    time.sleep(1)
+    assert not psutil.pid_exists(kernel["pid"])
+    assert client.kernel_list() == []
+
+    r = client.load_notebook(path)
+    [run] = r["notebook"]["runs"]
+    assert run["kernel_state"] == {"type": "Closed"}
+    assert len(run["output_cells"]) == 1
+    assert list(run["globals"]["variables"].keys()) == ["x"]
+
+
+def test_kill_kernel(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code("x = 1")
+    [kernel] = client.kernel_list()
+    k.send_code(
+        "import time\n"
+        "while True:\n"
+        "    try:\n"
+        "        time.sleep(100)\n"
+        "    except KeyboardInterrupt:\n"
+        "        pass"
+    )
+    time.sleep(0.5)
+
+    # Kernel ignores interruption so it does not react to a graceful stop
+    client.stop_kernel(kernel["kernel_id"])
+    r = client.receive_message()
+    assert r["type"] == "KernelClosed"
+    # Kernel is reported as stopping right away, not only when its process exits
+    r = client.receive_message(skip_async=False)
+    assert r["type"] == "KernelsUpdate"
+    [info] = r["kernels"]
+    assert info["kernel_id"] == kernel["kernel_id"]
+    assert info["stopping"]
+    assert not kernel["stopping"]
+    time.sleep(0.5)
+    assert psutil.pid_exists(kernel["pid"])
+    # Repeated graceful stop is ignored
+    client.stop_kernel(kernel["kernel_id"])
+    [info] = client.kernel_list()
+    assert info["stopping"]
+
+    client.stop_kernel(kernel["kernel_id"], force=True)
+    while True:
+        r = client.receive_message(skip_async=False)
+        assert r["type"] == "KernelsUpdate"
+        if r["kernels"] == []:
+            break
+    assert not psutil.pid_exists(kernel["pid"])
+    assert client.kernel_list() == []
+
+
+@pytest.mark.parametrize("http_service", [{"TWINSONG_KERNEL_POOL": "2"}], indirect=True)
+def test_kernel_pool(client):
+    pooled = client.wait_for_kernel_pool(2)
+    r = client.create_new_notebook()
+    notebook_id = r["notebook"]["id"]
+    k1 = client.create_new_kernel(notebook_id)
+    k2 = client.create_new_kernel(notebook_id)
+    klist = client.kernel_list()
+    assert {k["run_id"] for k in klist} == {k1.run_id, k2.run_id}
+    # Kernels were started before the runs were created
+    assert {k["pid"] for k in klist} == pooled
+    assert "3" == k1.run_code_simple("x = 3; x")
+    assert "4" == k2.run_code_simple("x = 4; x")
+
+    # Pool is refilled in the background
+    pooled = client.wait_for_kernel_pool(2)
+    k1.restart()
+    r = client.receive_message()
+    assert r["type"] == "KernelRestarted"
+    r = client.receive_message()
+    assert r["type"] == "KernelReady"
+    k3 = client.create_new_kernel(notebook_id)
+    klist = client.kernel_list()
+    assert {k["run_id"] for k in klist} == {k1.run_id, k2.run_id, k3.run_id}
+    assert pooled < {k["pid"] for k in klist}
+    assert "1" == k1.run_code_simple("x = 1; x")
+    assert "4" == k2.run_code_simple("x")
+
+    # Idle kernel that dies is replaced
+    pid = client.wait_for_kernel_pool(2).pop()
+    os.kill(pid, signal.SIGKILL)
+    client.wait_for_kernel_pool(2, exclude=[pid])
+
+
+def test_cell_timeout(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code("x = 5")
+    k.send_code("while True: pass", timeout=1)
+    k.send_code("x + 1")
+    r = k.wait_for_outputs()
+    assert r[-1]["type"] == "Exception"
+    assert r[-1]["value"]["message"].startswith("TimeoutError")
+    r = client.receive_message()
+    assert r["type"] == "NewGlobals"
+    assert [{"type": "Text", "value": "6"}] == k.wait_for_outputs()
+
+    k.set_notebook_timeout(1)
+    r = k.run_code("while True: pass")
+    assert r[-1]["value"]["message"].startswith("TimeoutError")
+    assert client.receive_message()["type"] == "NewGlobals"
+    # Explicit timeout of the cell overrides notebook's default
+    k.send_code("import time; time.sleep(1.5); 2", timeout=2)
+    assert [{"type": "Text", "value": "2"}] == k.wait_for_outputs()
+
+    # Timeout of the next cell starts when the kernel finishes the interrupted cell
+    k.send_code(
+        "import time\n"
+        "try:\n"
+        "    while True:\n"
+        "        time.sleep(0.01)\n"
+        "except KeyboardInterrupt:\n"
+        "    time.sleep(1.5)\n"
+    )
+    k.send_code("3")
+    r = k.wait_for_outputs()
+    assert r[-1]["value"]["message"].startswith("TimeoutError")
+    assert client.receive_message()["type"] == "NewGlobals"
+    assert [{"type": "Text", "value": "3"}] == k.wait_for_outputs()
+
+
+def test_kernel_logs(client):
+    r = client.create_new_notebook()
+    k1 = client.create_new_kernel(r["notebook"]["id"])
+    k2 = client.create_new_kernel(r["notebook"]["id"])
+    # Process-level stderr goes to the log, sys.stderr is captured into outputs
+    k1.run_code("import os; os.write(2, b'Message1')")
+    k2.run_code("import os; os.write(2, b'Message2')")
+    kernels = {k["run_id"]: k["kernel_id"] for k in client.kernel_list()}
+
+    log = client.kernel_log(kernels[k1.run_id])
+    assert log["stderr"].endswith("Message1")
+    log = client.kernel_log(kernels[k2.run_id])
+    assert log["stderr"].endswith("Message2")
+
+    k1.send_code("import os; os.write(2, b'Fatal error'); os._exit(1)")
+    r = client.receive_message()
+    assert r["type"] == "KernelCrashed"
+    assert r["run_id"] == k1.run_id
+    assert r["stderr"].endswith("Fatal error")
+
+    # Log is available after the kernel is gone
+    log = client.kernel_log(kernels[k1.run_id])
+    assert log["stderr"].endswith("Fatal error")
+
+
+@pytest.mark.skipif(sys.platform != "linux", reason="Resource usage is read from /proc")
+def test_kernel_usage(client):
+    r = client.create_new_notebook()
+    notebook_id = r["notebook"]["id"]
+    k = client.create_new_kernel(notebook_id)
+    k.run_code(
+        "import sys, time\n"
+        "sys.test_data = bytearray(200 * 1024 * 1024)\n"
+        "start = time.process_time()\n"
+        "while time.process_time() - start < 1: pass"
+    )
+
+    def wait_for_update():
+        while True:
+            r = client.receive_message(skip_async=False)
+            if r["type"] == "KernelsUpdate":
+                return r
+
+    r = wait_for_update()
+    # The first update may be sampled before the code was finished
+    r = wait_for_update()
+    assert r["notebook_id"] == notebook_id
+    [kernel] = r["kernels"]
+    assert kernel["run_id"] == k.run_id
+    assert kernel["rss"] > 200 * 1024 * 1024
+    assert kernel["cpu_time"] >= 1.0
+    assert kernel["uptime"] >= 1.0
+    assert kernel["queue"] == 0
+    assert kernel["state"] == {"type": "Ready"}
+
+    [kernel] = client.kernel_list()
+    assert kernel["rss"] > 200 * 1024 * 1024
+
+
+def test_kernel_crash_exit_code(client):
+    r = client.create_new_notebook()
+    notebook_id = r["notebook"]["id"]
+    path = r["notebook"]["path"]
+    editor_root = r["notebook"]["editor_root"]
+    k = client.create_new_kernel(notebook_id)
+    k.run_code("import time; x = 1")
+    cell_id = k.send_code("import os; time.sleep(0.3); os._exit(3)")
+    queued_id = k.send_code("x + 1")
+    r = client.receive_message()
+    while r["type"] == "ComputeQueue":
+        r = client.receive_message()
+    assert r["type"] == "KernelCrashed"
+    # The crashed cell fails and the queued one is cancelled
+    assert r["cell_flags"] == {cell_id: "Fail", queued_id: "Cancelled"}
+    crash = r["crash"]
+    assert crash["exit_code"] == 3
+    assert crash["signal"] is None
+    assert not crash["oom"]
+    assert crash["cell_id"] == cell_id
+    assert crash["time"] is not None
+    assert "code 3" in crash["message"]
+
+    client.send_message(
+        {"type": "SaveNotebook", "notebook_id": notebook_id, "editor_root": editor_root}
+    )
+    assert client.receive_message()["type"] == "SaveCompleted"
+    [run] = os.listdir(path + ".runs")
+    with open(os.path.join(path + ".runs", run)) as f:
+        data = toml.loads(f.read())
+    # TOML has no null, unset values are missing
+    stored = {k: v for k, v in crash.items() if v is not None}
+    assert data["kernel_state"] == {"type": "Crashed", **stored}
+    flags = [cell["flag"] for cell in data["output_cells"]]
+    assert flags == ["Success", "Fail", "Cancelled"]
+
+
+@pytest.mark.skipif(sys.platform == "win32", reason="Signals are not supported")
+def test_kernel_crash_signal(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.send_code("import os, signal; os.kill(os.getpid(), signal.SIGSEGV)")
+    r = client.receive_message()
+    assert r["type"] == "KernelCrashed"
+    assert r["crash"]["exit_code"] is None
+    assert r["crash"]["signal"] == "SIGSEGV"
+    assert not r["crash"]["oom"]
+
+    k = client.create_new_kernel(r["notebook_id"])
+    k.send_code("import os, signal; os.kill(os.getpid(), signal.SIGKILL)")
+    r = client.receive_message()
+    assert r["type"] == "KernelCrashed"
+    assert r["crash"]["signal"] == "SIGKILL"
+    # SIGKILL that was not sent by the OOM killer
+    assert not r["crash"]["oom"]
+    assert "out of memory" not in r["crash"]["message"]
+
+
+@pytest.mark.skipif(sys.platform == "win32", reason="Uses SIGSTOP")
+def test_kernel_unresponsive(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    assert "3" == k.run_code_simple("1 + 2")
+    pid = client.kernel_list()[0]["pid"]
+
+    os.kill(pid, signal.SIGSTOP)
+    try:
+        r = client.receive_message()
+        assert r["type"] == "KernelStateChanged"
+        assert r["run_id"] == k.run_id
+        assert r["kernel_state"] == {"type": "Unresponsive"}
+        assert client.kernel_list()[0]["state"] == {"type": "Unresponsive"}
+    finally:
+        os.kill(pid, signal.SIGCONT)
+
+    r = client.receive_message()
+    assert r["type"] == "KernelStateChanged"
+    assert r["kernel_state"] == {"type": "Ready"}
+    assert "7" == k.run_code_simple("3 + 4")
+
+
+@pytest.mark.skipif(sys.platform == "win32", reason="Detached kernels need Unix")
+@pytest.mark.parametrize(
+    "http_service", [{"TWINSONG_DETACHED_KERNELS": "true"}], indirect=True
+)
+def test_detached_kernels(http_service, client, key):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    pid = k.run_code_simple("import os; x = 42; os.getpid()")
+    try:
+        # Killed server has no chance to save the run, it is recreated from runtime state
+        for kill in (True, False):
+            http_service.restart(kill=kill)
+            with connect(http_service.url) as ws:
+                client = Client(ws, key)
+                r = client.load_notebook(path)
+                assert r["type"] == "NewNotebook"
+                [run] = r["notebook"]["runs"]
+                assert run["id"] == k.run_id
+                assert run["title"] == "Run Test"
+                if run["kernel_state"]["type"] == "Init":
+                    assert client.receive_message()["type"] == "KernelReady"
+                else:
+                    assert run["kernel_state"]["type"] == "Ready"
+                k = Kernel(client, r["notebook"]["id"], k.run_id)
+                assert "42" == k.run_code_simple("x")
+                assert pid == k.run_code_simple("os.getpid()")
+                [kernel] = client.kernel_list()
+                assert str(kernel["pid"]) == pid
+    finally:
+        os.kill(int(pid), signal.SIGKILL)
+
+
+@pytest.mark.skipif(sys.platform == "win32", reason="Detached kernels need Unix")
+@pytest.mark.parametrize(
+    "http_service", [{"TWINSONG_DETACHED_KERNELS": "true"}], indirect=True
+)
+def test_detached_kernel_output_of_unknown_cell(http_service, client, key):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    pid = k.run_code_simple("import os, time; os.getpid()")
+    try:
+        # The cell is not in the run recreated after the kill of the server
+        k.send_code("time.sleep(1.5); x = 42")
+        time.sleep(0.3)
+        http_service.restart(kill=True)
+        with connect(http_service.url) as ws:
+            client = Client(ws, key)
+            r = client.load_notebook(path)
+            [run] = r["notebook"]["runs"]
+            assert run["output_cells"] == []
+            if run["kernel_state"]["type"] == "Init":
+                assert client.receive_message()["type"] == "KernelReady"
+            # Only globals of the finished cell are kept
+            r = client.receive_message()
+            assert r["type"] == "NewGlobals"
+            assert "x" in r["globals"]["variables"]
+            k = Kernel(client, r["notebook_id"], k.run_id)
+            assert "42" == k.run_code_simple("x")
+            assert http_service.process.poll() is None
+    finally:
+        os.kill(int(pid), signal.SIGKILL)
+
+
+@pytest.mark.skipif(sys.platform != "linux", reason="Process identity needs /proc")
+@pytest.mark.parametrize(
+    "http_service", [{"TWINSONG_DETACHED_KERNELS": "true"}], indirect=True
+)
+def test_detached_kernel_pid_reused(http_service, client, key):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    pid = int(k.run_code_simple("import os; os.getpid()"))
+    http_service.process.kill()
+    http_service.process.wait(timeout=10)
+    os.kill(pid, signal.SIGKILL)
+
+    # Pid of the ended kernel now belongs to an unrelated process
+    other = subprocess.Popen(["sleep", "60"])
+    try:
+        with open(".twinsong/runtime.json") as f:
+            runtime = json.load(f)
+        [record] = runtime["kernels"]
+        record["pid"] = other.pid
+        with open(".twinsong/runtime.json", "w") as f:
+            json.dump(runtime, f)
+        http_service.start()
+        with connect(http_service.url) as ws:
+            client = Client(ws, key)
+            assert client.load_notebook(path)["type"] == "NewNotebook"
+            assert client.kernel_list() == []
+        http_service.restart()
+        assert other.poll() is None
+    finally:
+        other.kill()
+
+
+def test_kernel_login_with_invalid_secret(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    process = psutil.Process(client.kernel_list()[0]["pid"])
+
+    # Pretend to be the running kernel, i.e. the same id and address
+    subprocess.run(
+        process.cmdline(),
+        env=process.environ(),
+        input=b"invalid\n",
+        timeout=10,
+        capture_output=True,
+    )
+    with open("server.out.log") as f:
+        assert "Rejected login of kernel" in f.read()
+    assert "3" == k.run_code_simple("1 + 2")
+
+
+@pytest.mark.skipif(sys.platform != "linux", reason="Requires /proc")
+def test_kernel_secret_not_in_environ(client):
+    r = client.create_new_notebook()
+    k = client.create_new_kernel(r["notebook"]["id"])
+    pid = client.kernel_list()[0]["pid"]
+    with open(f"/proc/{pid}/environ", "rb") as f:
+        environ = f.read()
+    assert b"KERNEL_ID=" in environ
+    assert b"KERNEL_SECRET" not in environ
+    assert "False" == k.run_code_simple("import os; 'KERNEL_SECRET' in os.environ")
+
+
+@pytest.mark.skipif(sys.platform == "win32", reason="Fake kernel is not executable")
+@pytest.mark.parametrize(
+    "http_service", [{"TWINSONG_PYTHON": FAKE_KERNEL}], indirect=True
+)
+def test_kernel_protocol_mismatch(client):
+    r = client.create_new_notebook()
+    run_id = str(uuid.uuid4())
+    client.send_message(
+        {
+            "type": "CreateNewKernel",
+            "notebook_id": r["notebook"]["id"],
+            "run_id": run_id,
+            "run_title": "Run Test",
+        }
+    )
+    r = client.receive_message()
+    assert r["type"] == "KernelCrashed"
+    assert r["run_id"] == run_id
+    assert "Kernel protocol version 999" in r["crash"]["message"]
+    assert "server protocol version" in r["crash"]["message"]
+    assert client.kernel_list() == []
+
+
+@pytest.mark.skipif(sys.platform == "win32", reason="Sending SIGINT is not supported")
+def test_server_shutdown(http_service, client):
+    r = client.create_new_notebook()
+    path = r["notebook"]["path"]
+    k = client.create_new_kernel(r["notebook"]["id"])
+    k.run_code("x = 5")
+    k.send_code("while True: pass")
+    k.send_code("x + 1")
+    pid = client.kernel_list()[0]["pid"]
+    time.sleep(0.5)
+
+    http_service.process.send_signal(signal.SIGINT)
+    assert client.receive_message()["type"] == "ServerShutdown"
+    assert http_service.process.wait(5) == 0
+    assert not psutil.pid_exists(pid)
+
+    runs = os.listdir(path + ".runs")
+    assert len(runs) == 1
+    with open(os.path.join(path + ".runs", runs[0])) as f:
+        data = toml.loads(f.read())
+    assert data["kernel_state"] == {"type": "Closed"}
+    assert [c["flag"] for c in data["output_cells"]] == ["Success", "Fail", "Fail"]
+    assert data["output_cells"][1]["values"][0]["value"]["message"].startswith(
+        "KeyboardInterrupt"
+    )
@@ -162,3 +162,49 @@ def test_jobject_dataclass():
             ),
         ],
     }
+
+
+def test_jobject_limits():
+    one = {"repr": "1", "value_type": "int", "kind": "number"}
+    two = {"repr": "2", "value_type": "int", "kind": "number"}
+    assert build_obj([1, 2, 3], max_children=2) == {
+        "repr": "[1, 2, 3]",
+        "value_type": "list[int]",
+        "kind": "list",
+        "children": [("0", one), ("1", two)],
+        "len": 3,
+        "truncated": 1,
+    }
+    assert build_obj([[1], 2], max_depth=1) == {
+        "repr": "[[1], 2]",
+        "value_type": "list",
+        "kind": "list",
+        "children": [
+            (
+                "0",
+                {
+                    "repr": "[1]",
+                    "value_type": "list",
+                    "kind": "list",
+                    "len": 1,
+                    "truncated": 1,
+                },
+            ),
+            ("1", two),
+        ],
+    }
+    # The container itself takes one object of the budget
+    assert build_obj({"a": 1, "b": 2, "c": 3}, max_objects=3) == {
+        "repr": "{'a': 1, 'b': 2, 'c': 3}",
+        "value_type": "dict[str, int]",
+        "kind": "dict",
+        "children": [("a", one), ("b", two)],
+        "len": 3,
+        "truncated": 1,
+    }
+    p = Person("John", 25, ["Foo", "Bar"])
+    r = build_obj(p, max_objects=2)
+    assert r["children"] == [
+        ("name", {"repr": '"John"', "value_type": "str", "kind": "string"}),
+    ]
+    assert (r["len"], r["truncated"]) == (3, 2)
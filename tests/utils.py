@@ -21,5 +21,5 @@ def build_raw_obj(obj):
     return json.loads(create_jobject(obj))
 
 
-def build_obj(obj):
-    return build_jobject_from_text(create_jobject(obj))
+def build_obj(obj, **limits):
+    return build_jobject_from_text(create_jobject(obj, **limits))
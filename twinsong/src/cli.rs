@@ -1,8 +1,11 @@
 use crate::http::http_server_main;
 use crate::kernel::init_kernel_manager;
+use crate::reactor::shutdown_server;
+use crate::spool::OutputLimits;
 use crate::state::AppState;
 use clap::Parser;
 use std::sync::{Arc, Mutex};
+use tokio::task::spawn_local;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -12,15 +15,45 @@ struct Args {
 
     #[arg(long)]
     key: Option<String>,
+
+    /// Number of idle kernels kept ready for new runs
+    #[arg(long, env = "TWINSONG_KERNEL_POOL", default_value = "0")]
+    kernel_pool: usize,
+
+    /// Port where kernels connect to the server, a free port is used by default
+    #[arg(long, env = "TWINSONG_KERNEL_PORT", default_value = "0")]
+    kernel_port: u16,
+
+    /// Kernels keep running when the server stops and they are re-attached after its restart.
+    /// Runtime state is kept in `.twinsong` and the kernel port of the previous server is reused.
+    #[arg(long, env = "TWINSONG_DETACHED_KERNELS")]
+    detached_kernels: bool,
+
+    /// Size limit (in bytes) of a single output value, the middle of a longer output
+    /// is moved into `<notebook>.spool` and can be paged from the browser
+    #[arg(long, env = "TWINSONG_OUTPUT_LIMIT", default_value = "1000000")]
+    output_limit: usize,
+
+    /// Size limit (in bytes) of all outputs of a cell
+    #[arg(long, env = "TWINSONG_CELL_OUTPUT_LIMIT", default_value = "10000000")]
+    cell_output_limit: usize,
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = signal(SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.unwrap();
 }
 
 pub async fn server_cli(args: Option<Vec<String>>) {
-    /*
-       TODO: Implement graceful termination of kernels
-       We are not explicitly setting handler when server is called
-       from Python
-    */
-    ctrlc::set_handler(|| std::process::exit(2)).unwrap();
     let args = if let Some(args) = args {
         Args::parse_from(args)
     } else {
@@ -30,9 +63,30 @@ pub async fn server_cli(args: Option<Vec<String>>) {
     local
         .run_until(async move {
             tracing_subscriber::fmt::init();
-            let state = Arc::new(Mutex::new(AppState::new(args.port, args.key)));
+            let state = Arc::new(Mutex::new(AppState::new(
+                args.port,
+                args.key,
+                args.kernel_port,
+                args.kernel_pool,
+                args.detached_kernels,
+                OutputLimits {
+                    output: args.output_limit,
+                    cell: args.cell_output_limit,
+                },
+            )));
             init_kernel_manager(&state).await.unwrap();
-            http_server_main(state, args.port).await.unwrap();
+            tokio::select! {
+                r = http_server_main(state.clone(), args.port) => r.unwrap(),
+                _ = shutdown_signal() => {
+                    tracing::info!("Shutting down");
+                    spawn_local(async {
+                        shutdown_signal().await;
+                        tracing::info!("Shutdown interrupted");
+                        std::process::exit(2);
+                    });
+                    shutdown_server(&state).await;
+                }
+            }
         })
         .await;
 }
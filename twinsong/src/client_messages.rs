@@ -1,10 +1,13 @@
 use crate::notebook::{
-    EditorGroup, EditorId, KernelId, NotebookId, OutputCell, OutputCellId, OutputValue, RunId,
+    CellTiming, EditorGroup, EditorId, InputRequest, KernelCrash, KernelId, KernelRestart,
+    NotebookId, OutputCell, OutputCellFlag, OutputCellId, OutputValue, RunId,
 };
 use axum::extract::ws::Message;
-use comm::messages::OutputFlag;
-use comm::scopes::{SerializedGlobals, SerializedGlobalsUpdate};
+use comm::messages::{InputValue, JObjectLimits, OutputFlag};
+use comm::scopes::{ScopeId, SerializedGlobals, SerializedGlobalsUpdate};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
@@ -16,8 +19,20 @@ pub(crate) enum FromClientMessage {
     LoadNotebook(LoadNotebookMsg),
     QueryDir,
     CloseRun(NotebookRunMsg),
+    InterruptRun(NotebookRunMsg),
+    CancelCompute(RunCellMsg),
+    ComputeQueue(NotebookRunMsg),
+    InputReply(InputReplyMsg),
+    Inspect(InspectVariableMsg),
+    FetchOutput(FetchOutputMsg),
+    RestartKernel(NotebookRunMsg),
     KernelList,
+    KernelLog(KernelMsg),
+    StopKernel(KernelMsg),
+    KillKernel(KernelMsg),
     Fork(ForkMsg),
+    SetNotebookTimeout(SetNotebookTimeoutMsg),
+    SetJObjectLimits(SetJObjectLimitsMsg),
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,6 +41,53 @@ pub(crate) struct NotebookRunMsg {
     pub run_id: RunId,
 }
 
+#[derive(Debug, Deserialize)]
+pub(crate) struct RunCellMsg {
+    pub notebook_id: NotebookId,
+    pub run_id: RunId,
+    pub cell_id: OutputCellId,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct InputReplyMsg {
+    pub notebook_id: NotebookId,
+    pub run_id: RunId,
+    pub request_id: Uuid,
+    pub value: InputValue,
+}
+
+/// Request of a page of the text omitted from a truncated output
+#[derive(Debug, Deserialize)]
+pub(crate) struct FetchOutputMsg {
+    pub notebook_id: NotebookId,
+    pub run_id: RunId,
+    pub cell_id: OutputCellId,
+    /// Index of the value in the output cell
+    pub index: usize,
+    /// Offset (in bytes) in the omitted text
+    pub offset: usize,
+    pub size: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct InspectVariableMsg {
+    pub notebook_id: NotebookId,
+    pub run_id: RunId,
+    /// Ids of scopes from the root scope to the scope of the variable
+    pub scope: Vec<ScopeId>,
+    pub variable: String,
+    /// Indices of children from the variable to the inspected object
+    pub path: Vec<usize>,
+    /// Range of children of the inspected object that are sent
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct KernelMsg {
+    pub kernel_id: KernelId,
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct CreateNewNotebookMsg {
     pub filename: String,
@@ -45,6 +107,21 @@ pub(crate) struct RunCodeMsg {
     pub cell_id: OutputCellId,
     pub editor_node: EditorGroup,
     pub called_id: EditorId,
+    /// Timeout in seconds, notebook's default timeout is used when not set
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SetNotebookTimeoutMsg {
+    pub notebook_id: NotebookId,
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SetJObjectLimitsMsg {
+    pub notebook_id: NotebookId,
+    pub run_id: RunId,
+    pub limits: JObjectLimits,
 }
 
 #[derive(Debug, Deserialize)]
@@ -72,7 +149,9 @@ pub(crate) enum KernelStateDesc {
     Init,
     Ready,
     Running,
-    Crashed { message: String },
+    /// Kernel stopped answering heartbeats
+    Unresponsive,
+    Crashed(KernelCrash),
     Closed,
 }
 
@@ -81,14 +160,18 @@ pub(crate) struct RunDesc<'a> {
     pub id: RunId,
     pub title: &'a str,
     pub output_cells: &'a [OutputCell],
+    pub kernel_restarts: &'a [KernelRestart],
     pub kernel_state: KernelStateDesc,
     pub globals: &'a SerializedGlobals,
+    pub jobject_limits: &'a JObjectLimits,
+    pub input_request: Option<&'a InputRequest>,
 }
 
 #[derive(Debug, Serialize)]
 pub(crate) struct NotebookDesc<'a> {
     pub id: NotebookId,
     pub path: &'a str,
+    pub default_timeout: Option<u64>,
     pub editor_root: &'a EditorGroup,
     pub editor_open_nodes: &'a [EditorId],
     pub runs: Vec<RunDesc<'a>>,
@@ -114,6 +197,17 @@ pub(crate) struct KernelInfo {
     pub notebook_id: NotebookId,
     pub run_id: RunId,
     pub pid: u32,
+    pub capabilities: Vec<String>,
+    /// Seconds since the kernel was spawned
+    pub uptime: f64,
+    /// Resident set size in bytes, None if not sampled yet or not supported on the platform
+    pub rss: Option<u64>,
+    /// User and system CPU time in seconds
+    pub cpu_time: Option<f64>,
+    pub queue: usize,
+    pub state: KernelStateDesc,
+    /// Kernel was asked to stop and waits for its process to exit
+    pub stopping: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -132,21 +226,96 @@ pub(crate) enum ToClientMessage<'a> {
     KernelCrashed {
         notebook_id: NotebookId,
         run_id: RunId,
-        message: String,
+        crash: KernelCrash,
+        /// The last lines of kernel's stderr
+        stderr: String,
+        /// New flags of cells that were unfinished when the kernel crashed
+        cell_flags: HashMap<OutputCellId, OutputCellFlag>,
+    },
+    KernelStateChanged {
+        notebook_id: NotebookId,
+        run_id: RunId,
+        kernel_state: KernelStateDesc,
+    },
+    KernelClosed {
+        notebook_id: NotebookId,
+        run_id: RunId,
+    },
+    KernelRestarted {
+        notebook_id: NotebookId,
+        run_id: RunId,
+        restart: &'a KernelRestart,
     },
     Output {
         notebook_id: NotebookId,
         run_id: RunId,
         cell_id: OutputCellId,
         value: &'a OutputValue,
+        /// Stream chunk replaces the last value of the cell from this offset (in characters)
+        replace_from: Option<usize>,
         flag: OutputFlag,
+        timing: CellTiming,
         update: Option<&'a SerializedGlobalsUpdate>,
         kernel_state: KernelStateDesc,
     },
+    /// Output at `index` of the cell was replaced by an update of its display
+    OutputReplaced {
+        notebook_id: NotebookId,
+        run_id: RunId,
+        cell_id: OutputCellId,
+        index: usize,
+        value: &'a OutputValue,
+    },
+    ComputeCancelled {
+        notebook_id: NotebookId,
+        run_id: RunId,
+        cell_id: OutputCellId,
+        kernel_state: KernelStateDesc,
+    },
+    ComputeQueue {
+        notebook_id: NotebookId,
+        run_id: RunId,
+        /// Cells waiting for the kernel in the order of computation
+        cells: Vec<OutputCellId>,
+    },
+    OutputPage {
+        notebook_id: NotebookId,
+        run_id: RunId,
+        cell_id: OutputCellId,
+        index: usize,
+        offset: usize,
+        /// Offset of the following page, pages are aligned to whole characters
+        next_offset: usize,
+        /// Size of the whole omitted text
+        omitted: usize,
+        text: String,
+    },
+    /// Answer to `FromClientMessage::Inspect`, `object` is a serialized jobject dump
+    InspectResult {
+        notebook_id: NotebookId,
+        run_id: RunId,
+        scope: Vec<ScopeId>,
+        variable: String,
+        path: Vec<usize>,
+        offset: usize,
+        object: Option<String>,
+        error: Option<String>,
+    },
+    InputRequest {
+        notebook_id: NotebookId,
+        run_id: RunId,
+        request: &'a InputRequest,
+    },
+    /// Pending input request was answered (possibly by another client)
+    InputAnswered {
+        notebook_id: NotebookId,
+        run_id: RunId,
+        request_id: Uuid,
+    },
     NewGlobals {
         notebook_id: NotebookId,
         run_id: RunId,
-        globals: SerializedGlobals,
+        globals: &'a SerializedGlobals,
     },
     SaveCompleted {
         notebook_id: NotebookId,
@@ -157,7 +326,19 @@ pub(crate) enum ToClientMessage<'a> {
     },
     Kernels {
         kernels: Vec<KernelInfo>,
+        /// Pids of idle kernels in the pool that are ready to be bound to a run
+        pool: Vec<u32>,
+    },
+    KernelsUpdate {
+        notebook_id: NotebookId,
+        kernels: Vec<KernelInfo>,
+    },
+    KernelLogTail {
+        kernel_id: KernelId,
+        stdout: String,
+        stderr: String,
     },
+    ServerShutdown,
 }
 
 pub(crate) fn parse_client_message(message: Message) -> anyhow::Result<FromClientMessage> {
@@ -2,8 +2,10 @@ use crate::client_messages::{
     FromClientMessage, ToClientMessage, parse_client_message, serialize_client_message,
 };
 use crate::reactor::{
-    close_run, fork_run, load_notebook, new_notebook, query_dir, run_code, save_notebook,
-    start_kernel,
+    cancel_compute, close_run, compute_queue, fetch_output, fork_run, input_reply,
+    inspect_variable, interrupt_run, kernel_log, kill_kernel, load_notebook, new_notebook,
+    query_dir, restart_kernel, run_code, save_notebook, set_jobject_limits, set_notebook_timeout,
+    start_kernel, stop_kernel,
 };
 use crate::state::{AppState, AppStateRef};
 use anyhow::bail;
@@ -155,7 +157,7 @@ fn process_client_message(
             start_kernel(state, state_ref, msg.notebook_id, msg.run_id, msg.run_title)?;
         }
         FromClientMessage::RunCode(msg) => {
-            run_code(state, msg)?;
+            run_code(state, state_ref, msg)?;
         }
         FromClientMessage::Fork(msg) => {
             fork_run(state, state_ref, msg)?;
@@ -172,9 +174,46 @@ fn process_client_message(
         FromClientMessage::CloseRun(msg) => {
             close_run(state, msg.notebook_id, msg.run_id)?;
         }
+        FromClientMessage::InterruptRun(msg) => {
+            interrupt_run(state, msg.notebook_id, msg.run_id)?;
+        }
+        FromClientMessage::CancelCompute(msg) => {
+            cancel_compute(state, msg)?;
+        }
+        FromClientMessage::ComputeQueue(msg) => {
+            compute_queue(state, msg, sender)?;
+        }
+        FromClientMessage::FetchOutput(msg) => {
+            fetch_output(state, msg, sender)?;
+        }
+        FromClientMessage::Inspect(msg) => {
+            inspect_variable(state, msg, sender)?;
+        }
+        FromClientMessage::InputReply(msg) => {
+            input_reply(state, msg)?;
+        }
+        FromClientMessage::RestartKernel(msg) => {
+            restart_kernel(state, state_ref, msg.notebook_id, msg.run_id)?;
+        }
+        FromClientMessage::SetNotebookTimeout(msg) => {
+            set_notebook_timeout(state, msg)?;
+        }
+        FromClientMessage::SetJObjectLimits(msg) => {
+            set_jobject_limits(state, msg)?;
+        }
+        FromClientMessage::KernelLog(msg) => {
+            kernel_log(state, msg.kernel_id, sender)?;
+        }
+        FromClientMessage::StopKernel(msg) => {
+            stop_kernel(state, state_ref, msg.kernel_id)?;
+        }
+        FromClientMessage::KillKernel(msg) => {
+            kill_kernel(state, msg.kernel_id)?;
+        }
         FromClientMessage::KernelList => {
             let _ = sender.send(serialize_client_message(ToClientMessage::Kernels {
                 kernels: state.kernel_list(),
+                pool: state.kernel_pool_ready(),
             })?);
         }
     };
@@ -193,6 +232,10 @@ async fn recv_client_messages(
         }
         let message = parse_client_message(data)?;
         let mut state = state_ref.lock().unwrap();
+        if state.is_shutting_down() {
+            tracing::debug!("Ignoring client message during shutdown");
+            continue;
+        }
         if let Err(e) = process_client_message(&mut state, state_ref, &mut sender, message) {
             tracing::error!("Client message processing failed: {e}");
             let _ = sender.send(serialize_client_message(ToClientMessage::Error {
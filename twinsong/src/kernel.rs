@@ -1,66 +1,157 @@
-use crate::client_messages::{KernelInfo, ToClientMessage};
-use crate::notebook::{KernelId, NotebookId, RunId};
-use crate::reactor::process_kernel_message;
-use crate::state::AppStateRef;
+use crate::client_messages::{InspectVariableMsg, KernelInfo, ToClientMessage};
+use crate::notebook::{KernelCrash, KernelId, KernelState, Notebook, NotebookId, Run, RunId};
+use crate::reactor::{kernel_crashed, kernel_ready, process_kernel_message, set_kernel_responsive};
+use crate::runtime::RuntimeState;
+use crate::state::{AppState, AppStateRef, RuntimeDir, generate_key};
+use crate::storage::{SerializedNotebook, deserialize_notebook};
+use crate::utils::process::{
+    OomCounter, ProcessUsage, SIGKILL, is_kernel_process, kill_process, process_exists,
+    process_usage, signal_name,
+};
 use anyhow::bail;
 use axum::body::Bytes;
-use comm::messages::{FromKernelMessage, ToKernelMessage};
+use axum::extract::ws::Message;
+use comm::messages::{FromKernelMessage, InspectMsg, JObjectLimits, ToKernelMessage};
 use comm::scopes::SerializedGlobals;
-use comm::{Codec, make_protocol_builder, parse_from_kernel_message, serialize_to_kernel_message};
+use comm::{
+    Codec, PROTOCOL_VERSION, make_protocol_builder, parse_from_kernel_message, parse_login_header,
+    serialize_to_kernel_message,
+};
 use futures_util::SinkExt;
 use futures_util::stream::{SplitSink, SplitStream, StreamExt};
-use std::env::temp_dir;
+use jiff::Timestamp;
+use std::collections::HashMap;
 use std::fs::File;
-use std::path::PathBuf;
-use std::process::Stdio;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::process::Child;
 use tokio::spawn;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
 use tokio::sync::oneshot;
 use tokio::task::spawn_local;
 use tracing::log;
+use uuid::Uuid;
 
 pub(crate) enum KernelHandleState {
     Init(Vec<ToKernelMessage>),
     Ready(UnboundedSender<ToKernelMessage>),
 }
 
+/// How long a kernel has for a clean exit after shutdown request before it is killed
+const KERNEL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Number of lines returned from the end of a kernel log
+const LOG_TAIL_LINES: usize = 40;
+/// Maximal number of bytes read from the end of a kernel log
+const LOG_TAIL_BYTES: u64 = 16 * 1024;
+
+/// How often resource usage of kernels is sampled and sent to clients
+const KERNEL_USAGE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often heartbeats are sent to logged kernels
+const KERNEL_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Kernel is considered unresponsive when no message arrives for this time
+const KERNEL_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a detached kernel waits for a restarted server
+const DETACHED_KERNEL_RECONNECT_TIMEOUT: Duration = Duration::from_secs(600);
+/// How often it is checked that a re-attached kernel is still running
+const ATTACHED_KERNEL_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Runtime directory of servers with detached kernels, relative to the working directory
+const DETACHED_RUNTIME_DIR: &str = ".twinsong";
+
+pub(crate) enum StopRequest {
+    Kill,
+    Shutdown(oneshot::Sender<()>),
+}
+
+#[derive(Clone, Copy)]
 pub(crate) struct KernelCtx {
     pub kernel_id: KernelId,
     pub notebook_id: NotebookId,
     pub run_id: RunId,
 }
 
-#[allow(dead_code)] // TODO: After kill sender is used, removed this
 pub(crate) struct KernelHandle {
     state: KernelHandleState,
-    kill_sender: oneshot::Sender<()>,
-    kernel_ctx: KernelCtx,
+    stop_sender: UnboundedSender<StopRequest>,
+    /// None while the kernel is idle in the pool
+    kernel_ctx: Option<KernelCtx>,
     pid: u32,
+    secret: String,
+    /// False for a re-attached kernel whose process could not be identified,
+    /// the process is not killed until the kernel logs in again
+    verified: bool,
+    capabilities: Vec<String>,
+    started: Instant,
+    usage: Option<ProcessUsage>,
+    last_seen: Instant,
+    unresponsive: bool,
+    /// Graceful shutdown was requested, the handle is kept until the process exits
+    stopping: bool,
+    heartbeat_counter: u64,
     on_save_sender: Vec<oneshot::Sender<Result<(), String>>>,
     on_load_sender: Vec<oneshot::Sender<Result<SerializedGlobals, String>>>,
+    /// Inspect requests waiting for the kernel with clients that asked for them
+    inspect_requests: HashMap<Uuid, (InspectVariableMsg, UnboundedSender<Message>)>,
 }
 
 impl KernelHandle {
-    pub fn new(kernel_ctx: KernelCtx, kill_sender: oneshot::Sender<()>, pid: u32) -> Self {
+    pub fn new(stop_sender: UnboundedSender<StopRequest>, pid: u32, secret: String) -> Self {
         KernelHandle {
-            kill_sender,
+            stop_sender,
             state: KernelHandleState::Init(Vec::new()),
-            kernel_ctx,
+            kernel_ctx: None,
             pid,
+            secret,
+            verified: true,
+            capabilities: Vec::new(),
+            started: Instant::now(),
+            usage: None,
+            last_seen: Instant::now(),
+            unresponsive: false,
+            stopping: false,
+            heartbeat_counter: 0,
             on_save_sender: Vec::new(),
             on_load_sender: Vec::new(),
+            inspect_requests: HashMap::new(),
         }
     }
 
-    pub fn kernel_info(&self, kernel_id: KernelId) -> KernelInfo {
-        KernelInfo {
-            kernel_id,
-            notebook_id: self.kernel_ctx.notebook_id,
-            run_id: self.kernel_ctx.run_id,
+    pub fn kernel_info(&self, run: &Run) -> Option<KernelInfo> {
+        let kernel_ctx = self.kernel_ctx.as_ref()?;
+        Some(KernelInfo {
+            kernel_id: kernel_ctx.kernel_id,
+            notebook_id: kernel_ctx.notebook_id,
+            run_id: kernel_ctx.run_id,
             pid: self.pid,
-        }
+            capabilities: self.capabilities.clone(),
+            uptime: self.started.elapsed().as_secs_f64(),
+            rss: self.usage.map(|usage| usage.rss),
+            cpu_time: self.usage.map(|usage| usage.cpu_time),
+            queue: run.queue_len(),
+            state: run.kernel_state_desc(),
+            stopping: self.stopping,
+        })
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    pub fn set_usage(&mut self, usage: Option<ProcessUsage>) {
+        self.usage = usage;
     }
 
     pub fn on_store_response(&mut self, result: Result<(), String>) {
@@ -75,19 +166,60 @@ impl KernelHandle {
         }
     }
 
-    pub fn notebook_id(&self) -> NotebookId {
-        self.kernel_ctx.notebook_id
+    /// Records that a message from the kernel arrived,
+    /// returns true when the kernel was considered unresponsive until now
+    pub fn mark_seen(&mut self) -> bool {
+        self.last_seen = Instant::now();
+        std::mem::replace(&mut self.unresponsive, false)
     }
 
-    pub fn run_id(&self) -> RunId {
-        self.kernel_ctx.run_id
+    /// Sends a heartbeat to a logged kernel,
+    /// returns true when the kernel has just become unresponsive
+    pub fn heartbeat(&mut self) -> bool {
+        if self.is_init() {
+            return false;
+        }
+        self.heartbeat_counter += 1;
+        self.send_message(ToKernelMessage::Heartbeat(self.heartbeat_counter));
+        if !self.unresponsive && self.last_seen.elapsed() > KERNEL_HEARTBEAT_TIMEOUT {
+            self.unresponsive = true;
+            return true;
+        }
+        false
+    }
+
+    pub fn kernel_ctx(&self) -> Option<&KernelCtx> {
+        self.kernel_ctx.as_ref()
+    }
+
+    pub fn bind(&mut self, kernel_ctx: KernelCtx) {
+        assert!(self.kernel_ctx.is_none());
+        self.kernel_ctx = Some(kernel_ctx);
+    }
+
+    /// Compares the secret in constant time
+    pub fn check_secret(&self, secret: &str) -> bool {
+        self.secret.len() == secret.len()
+            && self
+                .secret
+                .bytes()
+                .zip(secret.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
     }
 
     pub fn is_init(&self) -> bool {
         matches!(self.state, KernelHandleState::Init { .. })
     }
 
-    pub fn set_to_ready(&mut self, sender: UnboundedSender<ToKernelMessage>) {
+    pub fn set_to_ready(
+        &mut self,
+        sender: UnboundedSender<ToKernelMessage>,
+        capabilities: Vec<String>,
+    ) {
+        self.capabilities = capabilities;
+        self.last_seen = Instant::now();
+        self.verified = true;
         match &mut self.state {
             KernelHandleState::Init(pending_mesgs) => {
                 let msgs = std::mem::take(pending_mesgs);
@@ -121,6 +253,32 @@ impl KernelHandle {
         receiver
     }
 
+    pub fn inspect(
+        &mut self,
+        msg: InspectVariableMsg,
+        limits: JObjectLimits,
+        sender: UnboundedSender<Message>,
+    ) {
+        let request_id = Uuid::new_v4();
+        self.send_message(ToKernelMessage::Inspect(InspectMsg {
+            request_id,
+            scope: msg.scope.clone(),
+            variable: msg.variable.clone(),
+            path: msg.path.clone(),
+            offset: msg.offset,
+            limit: msg.limit,
+            limits,
+        }));
+        self.inspect_requests.insert(request_id, (msg, sender));
+    }
+
+    pub fn take_inspect_request(
+        &mut self,
+        request_id: Uuid,
+    ) -> Option<(InspectVariableMsg, UnboundedSender<Message>)> {
+        self.inspect_requests.remove(&request_id)
+    }
+
     pub fn send_message(&mut self, message: ToKernelMessage) {
         match &mut self.state {
             KernelHandleState::Init(pending_msgs) => {
@@ -133,82 +291,521 @@ impl KernelHandle {
     }
 
     pub fn stop(self) {
-        let _ = self.kill_sender.send(());
+        if !self.verified {
+            tracing::warn!(
+                "Process {} is not verified as a kernel, it is not killed",
+                self.pid
+            );
+            return;
+        }
+        let _ = self.stop_sender.send(StopRequest::Kill);
+    }
+
+    /// Asks kernel to exit; the returned receiver is resolved when the process is gone.
+    /// Kernel is killed when it does not finish in time or when `stop` is called meanwhile.
+    pub fn shutdown(&mut self) -> oneshot::Receiver<()> {
+        self.stopping = true;
+        let (sender, receiver) = oneshot::channel();
+        let request = if self.is_init() {
+            let _ = sender.send(());
+            if !self.verified {
+                return receiver;
+            }
+            StopRequest::Kill
+        } else {
+            self.send_message(ToKernelMessage::Shutdown);
+            StopRequest::Shutdown(sender)
+        };
+        let _ = self.stop_sender.send(request);
+        receiver
+    }
+}
+
+pub(crate) fn kernel_log_paths(runtime_dir: &Path, kernel_id: KernelId) -> (PathBuf, PathBuf) {
+    (
+        runtime_dir.join(format!("{kernel_id}.out")),
+        runtime_dir.join(format!("{kernel_id}.err")),
+    )
+}
+
+/// Returns the last `LOG_TAIL_LINES` lines of a log file, missing file is an empty log
+pub(crate) async fn read_log_tail(path: &Path) -> std::io::Result<String> {
+    let mut file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(String::new()),
+        Err(e) => return Err(e),
+    };
+    let start = file.metadata().await?.len().saturating_sub(LOG_TAIL_BYTES);
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).await?;
+    let data = String::from_utf8_lossy(&data);
+    let mut lines: Vec<&str> = data.lines().collect();
+    if start > 0 && !lines.is_empty() {
+        // The first line is probably cut
+        lines.remove(0);
+    }
+    let skip = lines.len().saturating_sub(LOG_TAIL_LINES);
+    Ok(lines[skip..].join("\n"))
+}
+
+/// Kernel process, either spawned by this server or re-attached after a restart of the server
+enum KernelProcess {
+    Child(tokio::process::Child),
+    /// Detached kernel of a previous server, it is not our child so its exit status is unknown
+    Attached(u32, KernelId),
+}
+
+impl KernelProcess {
+    /// Waits until the process ends, exit status is known only for children
+    async fn wait(&mut self) -> Option<std::io::Result<ExitStatus>> {
+        match self {
+            KernelProcess::Child(child) => Some(child.wait().await),
+            KernelProcess::Attached(pid, kernel_id) => {
+                // Pid of the finished kernel may be reused by another process
+                while process_exists(*pid)
+                    && is_kernel_process(*pid, &kernel_id.to_string()) != Some(false)
+                {
+                    tokio::time::sleep(ATTACHED_KERNEL_POLL_INTERVAL).await;
+                }
+                None
+            }
+        }
+    }
+
+    async fn kill(&mut self) {
+        match self {
+            KernelProcess::Child(child) => {
+                let _ = child.kill().await;
+            }
+            KernelProcess::Attached(pid, kernel_id) => {
+                if is_kernel_process(*pid, &kernel_id.to_string()) != Some(false) {
+                    kill_process(*pid)
+                }
+            }
+        }
     }
 }
 
 pub fn spawn_kernel(
     state_ref: &AppStateRef,
-    kernel_ctx: KernelCtx,
+    kernel_id: KernelId,
     kernel_port: u16,
+    runtime_dir: &Path,
+    detached: bool,
 ) -> anyhow::Result<KernelHandle> {
     let program = std::env::var("TWINSONG_PYTHON")
         .map(PathBuf::from)
         .or_else(|_| which::which("python"))
         .or_else(|_| which::which("python3"))?;
     let mut cmd = tokio::process::Command::new(program);
-    let stdout_path = temp_dir().join("kernel.out");
-    let stderr_path = temp_dir().join("kernel.err");
-    let stdout_file = File::create(&stdout_path).expect("Cannot log file");
-    let stderr_file = File::create(&stderr_path).expect("Cannot log file");
+    let (stdout_path, stderr_path) = kernel_log_paths(runtime_dir, kernel_id);
+    let stdout_file = File::create(&stdout_path)?;
+    let stderr_file = File::create(&stderr_path)?;
 
-    cmd.env("KERNEL_ID", kernel_ctx.kernel_id.to_string())
+    let secret = generate_key();
+    cmd.env("KERNEL_ID", kernel_id.to_string())
         .env("KERNEL_CONNECT", format!("127.0.0.1:{kernel_port}"))
+        .stdin(Stdio::piped())
         .stdout(Stdio::from(stdout_file))
         .stderr(Stdio::from(stderr_file))
-        .kill_on_drop(true)
+        .kill_on_drop(!detached)
         .arg("-m")
         .arg("twinsong.driver");
+    if detached {
+        cmd.env(
+            "KERNEL_RECONNECT_TIMEOUT",
+            DETACHED_KERNEL_RECONNECT_TIMEOUT.as_secs().to_string(),
+        );
+    }
+    // Kernel is not in the foreground process group, so Ctrl-C is handled only by the server
+    #[cfg(unix)]
+    cmd.process_group(0);
     tracing::debug!("Spawning new kernel command {:?}", &cmd);
-    let child = cmd.spawn()?;
+    let mut child = cmd.spawn()?;
     let pid = child.id().unwrap_or(0);
-    let (sender, receiver) = oneshot::channel();
+    // Secret is passed through stdin, so it is not visible in the environment of the process
+    if let Some(mut stdin) = child.stdin.take() {
+        let line = format!("{secret}\n");
+        spawn(async move {
+            if let Err(e) = stdin.write_all(line.as_bytes()).await {
+                tracing::debug!("Sending secret to kernel {kernel_id} failed: {e}");
+            }
+        });
+    }
+    let sender = watch_kernel_process(
+        state_ref,
+        kernel_id,
+        KernelProcess::Child(child),
+        OomCounter::new(pid),
+        stderr_path,
+    );
+    Ok(KernelHandle::new(sender, pid, secret))
+}
+
+/// Creates a handle for a detached kernel of a previous server, it is ready when the kernel logs in
+fn attach_kernel(
+    state_ref: &AppStateRef,
+    kernel_id: KernelId,
+    pid: u32,
+    secret: String,
+    verified: bool,
+    runtime_dir: &Path,
+) -> KernelHandle {
+    let (_, stderr_path) = kernel_log_paths(runtime_dir, kernel_id);
+    let sender = watch_kernel_process(
+        state_ref,
+        kernel_id,
+        KernelProcess::Attached(pid, kernel_id),
+        OomCounter::new(pid),
+        stderr_path,
+    );
+    KernelHandle {
+        verified,
+        ..KernelHandle::new(sender, pid, secret)
+    }
+}
+
+fn watch_kernel_process(
+    state_ref: &AppStateRef,
+    kernel_id: KernelId,
+    mut process: KernelProcess,
+    oom_counter: Option<OomCounter>,
+    stderr_path: PathBuf,
+) -> UnboundedSender<StopRequest> {
+    let (sender, mut receiver) = unbounded_channel();
     let state_ref = state_ref.clone();
     spawn(async move {
-        tokio::select! {
-            _ = kernel_guard(child) => {
+        let request = tokio::select! {
+            status = process.wait() => {
+                tracing::debug!("Kernel stopped: {status:?}");
+                let stderr = read_log_tail(&stderr_path).await.unwrap_or_else(|e| {
+                    tracing::debug!("Reading kernel log failed: {e}");
+                    String::new()
+                });
                 let mut state = state_ref.lock().unwrap();
-                if let Ok(kernel) = state.find_kernel_by_id_mut(kernel_ctx.kernel_id) {
+                if let Ok(kernel) = state.find_kernel_by_id_mut(kernel_id) {
+                    let Some(&KernelCtx { notebook_id, run_id, .. }) = kernel.kernel_ctx() else {
+                        tracing::warn!("Idle kernel {kernel_id} from pool unexpectedly closed");
+                        // A kernel that died before its login is not replaced,
+                        // so a broken environment does not keep spawning kernels
+                        let logged = !kernel.is_init();
+                        state.remove_kernel(kernel_id);
+                        if logged {
+                            refill_kernel_pool(&mut state, &state_ref);
+                        }
+                        return;
+                    };
                     // TODO: Remove kernel from state
-                    let notebook_id = kernel.notebook_id();
-                    let run_id = kernel.run_id();
-                    let notebook = state.find_notebook_by_id_mut(notebook_id).unwrap();
-                    let run = notebook.find_run_by_id_mut(run_id).unwrap();
-                    run.set_crashed_kernel("Process unexpectedly closed".to_string());
-                    notebook.send_message(ToClientMessage::KernelCrashed {
-                        notebook_id,
-                        run_id,
-                        message: "Process unexpectedly closed".to_string(),
-                    })
+                    let crash = match status {
+                        Some(status) => crash_from_exit_status(status, oom_counter.as_ref()),
+                        None => KernelCrash::new("Process unexpectedly closed".to_string()),
+                    };
+                    kernel_crashed(&mut state, notebook_id, run_id, crash, stderr);
                 }
+                return;
             }
-            _ = receiver => {}
+            request = receiver.recv() => request,
+        };
+        // When the handle is dropped without a request, a child is killed on its drop,
+        // detached kernels are left running
+        if let Some(StopRequest::Shutdown(done)) = request {
+            tokio::select! {
+                status = tokio::time::timeout(KERNEL_SHUTDOWN_TIMEOUT, process.wait()) => match status {
+                    Ok(status) => tracing::debug!("Kernel stopped: {status:?}"),
+                    Err(_) => {
+                        tracing::debug!("Kernel {kernel_id} did not stop in time");
+                        process.kill().await;
+                    }
+                },
+                Some(StopRequest::Kill) = receiver.recv() => {
+                    tracing::debug!("Killing kernel {kernel_id} during shutdown");
+                    process.kill().await;
+                }
+            }
+            let _ = done.send(());
+        } else if let Some(StopRequest::Kill) = request {
+            process.kill().await;
         }
     });
-    Ok(KernelHandle::new(kernel_ctx, sender, pid))
+    sender
 }
 
-async fn kernel_guard(mut child: Child) -> anyhow::Result<()> {
-    let status = child.wait().await?;
-    tracing::debug!("Kernel stopped: {status:?}");
-    if !status.success() {
-        bail!("Kernel failed with status: {}", status.code().unwrap_or(0))
+/// Describes an exit of a kernel that was not requested by the server
+fn crash_from_exit_status(
+    status: std::io::Result<ExitStatus>,
+    oom_counter: Option<&OomCounter>,
+) -> KernelCrash {
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => return KernelCrash::new(format!("Waiting for kernel process failed: {e}")),
+    };
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+    #[cfg(not(unix))]
+    let signal: Option<i32> = None;
+    // SIGKILL may be sent by anyone, it is attributed to the OOM killer only
+    // when the killer was active in the cgroup of the kernel meanwhile
+    let oom = signal == Some(SIGKILL) && oom_counter.is_some_and(OomCounter::increased);
+    let message = match (status.code(), signal) {
+        (Some(code), _) => format!("Process exited with code {code}"),
+        (None, Some(signal)) if oom => format!(
+            "Process killed by {}, possibly out of memory",
+            signal_name(signal)
+        ),
+        (None, Some(signal)) => format!("Process killed by {}", signal_name(signal)),
+        (None, None) => "Process unexpectedly closed".to_string(),
+    };
+    KernelCrash {
+        exit_code: status.code(),
+        signal: signal.map(signal_name),
+        oom,
+        ..KernelCrash::new(message)
+    }
+}
+
+/// Spawns idle kernels until the pool has its configured size
+pub(crate) fn refill_kernel_pool(state: &mut AppState, state_ref: &AppStateRef) {
+    while state.kernel_pool_len() < state.kernel_pool_size() {
+        let kernel_id = KernelId::new(Uuid::new_v4());
+        match spawn_kernel(
+            state_ref,
+            kernel_id,
+            state.kernel_port(),
+            state.runtime_dir(),
+            state.detached_kernels(),
+        ) {
+            Ok(kernel) => {
+                tracing::debug!("Kernel {kernel_id} added into pool");
+                state.add_pooled_kernel(kernel_id, kernel);
+            }
+            Err(e) => {
+                tracing::error!("Starting kernel for pool failed: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// Re-attaches detached kernels of a previous server. Notebooks of their runs are loaded,
+/// so the kernels are bound to their runs again when they log in.
+async fn reattach_kernels(state_ref: &AppStateRef, runtime_state: RuntimeState) {
+    let mut notebooks: HashMap<String, Notebook> = HashMap::new();
+    let mut records = Vec::new();
+    for record in runtime_state.kernels {
+        // Pid of the record may belong to an unrelated process when the kernel has ended
+        let verified = match is_kernel_process(record.pid, &record.kernel_id.to_string()) {
+            Some(true) => true,
+            None if process_exists(record.pid) => false,
+            _ => {
+                tracing::warn!("Detached kernel {} is not running", record.kernel_id);
+                continue;
+            }
+        };
+        if let Some(binding) = &record.binding
+            && !notebooks.contains_key(&binding.notebook)
+        {
+            match SerializedNotebook::load(Path::new(&binding.notebook))
+                .await
+                .and_then(|s| deserialize_notebook(&s))
+            {
+                Ok(mut notebook) => {
+                    notebook.path = binding.notebook.clone();
+                    notebooks.insert(binding.notebook.clone(), notebook);
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "Loading notebook {} of detached kernel {} failed: {e}",
+                        binding.notebook,
+                        record.kernel_id
+                    );
+                    if verified {
+                        kill_process(record.pid);
+                    }
+                    continue;
+                }
+            }
+        }
+        records.push((record, verified));
+    }
+
+    let mut state = state_ref.lock().unwrap();
+    let mut notebook_ids = HashMap::new();
+    for (path, notebook) in notebooks {
+        let notebook_id = state.new_notebook_id();
+        state.add_notebook(notebook_id, notebook);
+        notebook_ids.insert(path, notebook_id);
+    }
+    for (record, verified) in records {
+        let kernel_id = record.kernel_id;
+        let mut kernel = attach_kernel(
+            state_ref,
+            kernel_id,
+            record.pid,
+            record.secret,
+            verified,
+            state.runtime_dir(),
+        );
+        let Some(binding) = record.binding else {
+            tracing::info!("Detached kernel {kernel_id} re-attached to pool");
+            state.add_pooled_kernel(kernel_id, kernel);
+            continue;
+        };
+        let notebook_id = notebook_ids[&binding.notebook];
+        let notebook = state.notebook_by_id_mut(notebook_id);
+        if let Ok(run) = notebook.find_run_by_id_mut(binding.run_id) {
+            run.reattach_kernel(kernel_id);
+        } else {
+            // Run was not saved before the previous server ended
+            notebook.add_run(
+                binding.run_id,
+                Run::new(
+                    binding.run_title,
+                    Vec::new(),
+                    Vec::new(),
+                    KernelState::Init(kernel_id),
+                    SerializedGlobals::default(),
+                    Timestamp::now(),
+                ),
+            );
+        }
+        tracing::info!(
+            "Detached kernel {kernel_id} re-attached to run {}",
+            binding.run_id
+        );
+        kernel.bind(KernelCtx {
+            kernel_id,
+            notebook_id,
+            run_id: binding.run_id,
+        });
+        state.add_kernel(kernel_id, kernel);
     }
-    Ok(())
 }
 
 pub(crate) async fn init_kernel_manager(state_ref: &AppStateRef) -> anyhow::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let (detached, kernel_port) = {
+        let state = state_ref.lock().unwrap();
+        (state.detached_kernels(), state.kernel_port())
+    };
+    let (runtime_dir, runtime_state) = if detached {
+        if !cfg!(unix) {
+            bail!("Detached kernels are supported only on Unix");
+        }
+        let path = PathBuf::from(DETACHED_RUNTIME_DIR);
+        std::fs::create_dir_all(&path)?;
+        let runtime_state = RuntimeState::load(&path)?;
+        (RuntimeDir::Persistent(path), runtime_state)
+    } else {
+        let runtime_dir = tempfile::Builder::new().prefix("twinsong-").tempdir()?;
+        (RuntimeDir::Temporary(runtime_dir), None)
+    };
+    // Detached kernels of the previous server connect to its port
+    let kernel_port = runtime_state
+        .as_ref()
+        .map(|runtime_state| runtime_state.kernel_port)
+        .unwrap_or(kernel_port);
+    let listener = TcpListener::bind(("127.0.0.1", kernel_port)).await?;
     let port = listener.local_addr()?.port();
+    tracing::info!("Kernel logs are stored in {}", runtime_dir.path().display());
 
     let state_ref = state_ref.clone();
-    state_ref.lock().unwrap().set_kernel_port(port);
+    {
+        let mut state = state_ref.lock().unwrap();
+        state.set_kernel_port(port);
+        state.set_runtime_dir(runtime_dir);
+    }
+    if let Some(runtime_state) = runtime_state {
+        reattach_kernels(&state_ref, runtime_state).await;
+    }
+    {
+        let mut state = state_ref.lock().unwrap();
+        state.save_runtime_state();
+        refill_kernel_pool(&mut state, &state_ref);
+    }
 
+    let sampler_state_ref = state_ref.clone();
+    spawn_local(async move { kernel_usage_sampler(sampler_state_ref).await });
+    let heartbeat_state_ref = state_ref.clone();
+    spawn_local(async move { kernel_heartbeat(heartbeat_state_ref).await });
     spawn_local(async move { kernel_manager_main(listener, state_ref).await });
 
     Ok(())
 }
 
+/// Pushes the current kernels of a notebook to its observers
+pub(crate) fn send_kernels_update(state: &AppState, notebook_id: NotebookId) {
+    let Some(notebook) = state.get_notebook_by_id(notebook_id) else {
+        return;
+    };
+    let kernels = state
+        .kernel_list()
+        .into_iter()
+        .filter(|info| info.notebook_id == notebook_id)
+        .collect();
+    notebook.send_message(ToClientMessage::KernelsUpdate {
+        notebook_id,
+        kernels,
+    });
+}
+
+/// Periodically samples resource usage of kernels and pushes it to notebook observers
+async fn kernel_usage_sampler(state_ref: AppStateRef) {
+    let mut interval = tokio::time::interval(KERNEL_USAGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pids = state_ref.lock().unwrap().kernel_pids();
+        if pids.is_empty() {
+            continue;
+        }
+        // /proc is read without holding the state lock
+        let usages: Vec<_> = pids
+            .into_iter()
+            .map(|(kernel_id, pid)| (kernel_id, process_usage(pid)))
+            .collect();
+        let mut state = state_ref.lock().unwrap();
+        for (kernel_id, usage) in usages {
+            if let Some(kernel) = state.get_kernel_by_id_mut(kernel_id) {
+                kernel.set_usage(usage);
+            }
+        }
+        let mut kernels: HashMap<NotebookId, Vec<KernelInfo>> = HashMap::new();
+        for info in state.kernel_list() {
+            kernels.entry(info.notebook_id).or_default().push(info);
+        }
+        for (notebook_id, kernels) in kernels {
+            if let Some(notebook) = state.get_notebook_by_id(notebook_id) {
+                notebook.send_message(ToClientMessage::KernelsUpdate {
+                    notebook_id,
+                    kernels,
+                });
+            }
+        }
+    }
+}
+
+/// Periodically pings kernels and reports the ones that stopped answering
+async fn kernel_heartbeat(state_ref: AppStateRef) {
+    let mut interval = tokio::time::interval(KERNEL_HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+        let mut state = state_ref.lock().unwrap();
+        let unresponsive: Vec<KernelId> = state
+            .kernels_mut()
+            .filter_map(|(kernel_id, kernel)| kernel.heartbeat().then_some(*kernel_id))
+            .collect();
+        for kernel_id in unresponsive {
+            tracing::warn!("Kernel {kernel_id} is not responding");
+            if let Some(kernel_ctx) = state
+                .get_kernel_by_id(kernel_id)
+                .and_then(|kernel| kernel.kernel_ctx())
+                .copied()
+            {
+                set_kernel_responsive(&mut state, &kernel_ctx, false);
+            }
+        }
+    }
+}
+
 pub(crate) async fn kernel_manager_main(listener: TcpListener, state_ref: AppStateRef) {
     while let Ok((stream, _)) = listener.accept().await {
         tracing::debug!("New kernel connection");
@@ -227,56 +824,71 @@ pub(crate) async fn handle_connection(
 ) -> anyhow::Result<()> {
     let (sender, mut receiver) = make_protocol_builder().new_framed(stream).split();
 
-    let (c_receiver, kernel_ctx) = if let Some(msg) = receiver.next().await {
+    let (c_receiver, kernel_id) = if let Some(msg) = receiver.next().await {
         let msg = msg?;
-        let msg = parse_from_kernel_message(&msg)?;
-        match msg {
-            FromKernelMessage::Login { kernel_id } => {
-                let kernel_id = KernelId::new(kernel_id);
-                tracing::debug!("New kernel connection logged as {kernel_id}");
-                let mut state = state_ref.lock().unwrap();
-                let kernel = state.find_kernel_by_id_mut(kernel_id)?;
-                if !kernel.is_init() {
-                    bail!("Kernel {} is not in init state", kernel_id);
-                }
-                let (c_sender, c_receiver) = unbounded_channel();
-                kernel.set_to_ready(c_sender);
-                let notebook_id = kernel.notebook_id();
-                let run_id = kernel.run_id();
-                let notebook = state.notebook_by_id_mut(notebook_id);
-                notebook
-                    .find_run_by_id_mut(run_id)
-                    .unwrap()
-                    .set_running_kernel(kernel_id);
-                notebook.send_message(ToClientMessage::KernelReady {
-                    notebook_id,
-                    run_id,
-                });
-                (
-                    c_receiver,
-                    KernelCtx {
-                        kernel_id,
-                        notebook_id,
-                        run_id,
-                    },
-                )
+        let header = parse_login_header(&msg)?;
+        let kernel_id = KernelId::new(header.kernel_id);
+        let mut state = state_ref.lock().unwrap();
+        let Some(kernel) = state
+            .get_kernel_by_id_mut(kernel_id)
+            .filter(|kernel| kernel.check_secret(&header.secret))
+        else {
+            tracing::warn!(
+                "Rejected login of kernel {kernel_id}: unknown kernel or invalid secret"
+            );
+            bail!("Kernel login rejected");
+        };
+        if !kernel.is_init() {
+            bail!("Kernel {} is not in init state", kernel_id);
+        }
+        let kernel_ctx = kernel.kernel_ctx().copied();
+        if header.protocol_version != PROTOCOL_VERSION {
+            let message = format!(
+                "Kernel protocol version {} does not match server protocol version {}",
+                header.protocol_version, PROTOCOL_VERSION
+            );
+            tracing::error!("Kernel {kernel_id}: {message}");
+            state.stop_kernel(kernel_id);
+            if let Some(KernelCtx {
+                notebook_id,
+                run_id,
+                ..
+            }) = kernel_ctx
+            {
+                let crash = KernelCrash::new(message.clone());
+                kernel_crashed(&mut state, notebook_id, run_id, crash, String::new());
             }
-            _ => bail!("Invalid first message"),
+            bail!(message);
+        }
+        let FromKernelMessage::Login { capabilities, .. } = parse_from_kernel_message(&msg)? else {
+            bail!("Invalid first message");
+        };
+        tracing::debug!(
+            "New kernel connection logged as {kernel_id}, capabilities: {capabilities:?}"
+        );
+        let (c_sender, c_receiver) = unbounded_channel();
+        kernel.set_to_ready(c_sender, capabilities);
+        if let Some(kernel_ctx) = kernel_ctx {
+            kernel_ready(&mut state, &state_ref, &kernel_ctx);
+        } else {
+            tracing::debug!("Kernel {kernel_id} is ready in pool");
         }
+        (c_receiver, kernel_id)
     } else {
         tracing::debug!("connection closed without sending message");
         return Ok(());
     };
 
-    let r = tokio::select! {
-        r = async {
-            forward_sender(sender, c_receiver).await
-        } => r,
-        r = async {
-            recv_kernel_messages(receiver, state_ref, kernel_ctx).await
-        } => r
-    };
-    r
+    let recv = recv_kernel_messages(receiver, state_ref, kernel_id);
+    tokio::pin!(recv);
+    tokio::select! {
+        r = forward_sender(sender, c_receiver) => {
+            r?;
+            // Kernel handle was dropped, we still process messages until the kernel disconnects
+            recv.await
+        },
+        r = &mut recv => r
+    }
 }
 
 async fn forward_sender(
@@ -293,13 +905,34 @@ async fn forward_sender(
 async fn recv_kernel_messages(
     mut receiver: SplitStream<Codec>,
     state_ref: AppStateRef,
-    kernel_ctx: KernelCtx,
+    kernel_id: KernelId,
 ) -> anyhow::Result<()> {
+    let mut kernel_ctx = None;
     while let Some(msg) = receiver.next().await {
         let msg = msg?;
         let msg = parse_from_kernel_message(&msg)?;
-        log::debug!("Received kernel message {msg:?}");
-        process_kernel_message(&mut state_ref.lock().unwrap(), &kernel_ctx, msg)?;
+        if !matches!(msg, FromKernelMessage::HeartbeatResponse(_)) {
+            log::debug!("Received kernel message {msg:?}");
+        }
+        let mut state = state_ref.lock().unwrap();
+        let mut recovered = false;
+        if let Some(kernel) = state.get_kernel_by_id_mut(kernel_id) {
+            recovered = kernel.mark_seen();
+            // Kernel from the pool is bound to a run after login,
+            // the last binding is used when the handle is already gone
+            if let Some(ctx) = kernel.kernel_ctx() {
+                kernel_ctx = Some(*ctx);
+            }
+        }
+        let Some(kernel_ctx) = &kernel_ctx else {
+            tracing::debug!("Ignoring message of idle kernel {kernel_id}");
+            continue;
+        };
+        if recovered {
+            tracing::info!("Kernel {kernel_id} is responding again");
+            set_kernel_responsive(&mut state, kernel_ctx, true);
+        }
+        process_kernel_message(&mut state, &state_ref, kernel_ctx, msg)?;
     }
     Ok(())
 }
@@ -4,6 +4,8 @@ mod http;
 mod kernel;
 mod notebook;
 mod reactor;
+mod runtime;
+mod spool;
 mod state;
 mod storage;
 mod utils;
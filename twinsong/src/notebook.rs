@@ -1,20 +1,26 @@
 use crate::client_messages::{
     KernelStateDesc, NotebookDesc, RunDesc, ToClientMessage, serialize_client_message,
 };
+use crate::spool::{OutputSpool, Truncation};
+use crate::utils::terminal::{StyleSpan, Terminal};
 use anyhow::anyhow;
 use axum::extract::ws::Message;
 use comm::messages::{
-    CodeGroup, CodeLeaf, CodeNode, CodeScope, Exception, KernelOutputValue, OutputFlag,
-    OwnCodeScope,
+    CodeGroup, CodeLeaf, CodeNode, CodeScope, ComputeMsg, Exception, JObjectLimits,
+    KernelOutputValue, LogRecord, MimeBundle, OutputFlag, OwnCodeScope, StreamName,
 };
 use comm::scopes::{SerializedGlobals, SerializedGlobalsUpdate};
 use jiff::Timestamp;
 use nutype::nutype;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
 use tokio::sync::mpsc::UnboundedSender;
 use uuid::Uuid;
 
+const TEXT_PLAIN: &str = "text/plain";
+const TEXT_HTML: &str = "text/html";
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub(crate) enum EditorNode {
@@ -86,24 +92,166 @@ pub(crate) struct EditorCell {
     pub code: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum OutputValue {
-    Text { value: String },
-    Html { value: String },
-    Exception { value: Exception },
+    Stream {
+        name: StreamName,
+        value: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        spans: Vec<StyleSpan>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        truncated: Option<Truncation>,
+    },
+    Text {
+        value: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        truncated: Option<Truncation>,
+        /// MIME types of rich representations that were dropped as they were over the limit
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        dropped: Vec<String>,
+    },
+    Html {
+        value: String,
+    },
+    MimeBundle {
+        value: MimeBundle,
+    },
+    Exception {
+        value: Exception,
+    },
+    Log {
+        value: LogRecord,
+    },
     None,
 }
 
 impl OutputValue {
     pub fn new(value: KernelOutputValue) -> Self {
         match value {
-            KernelOutputValue::Text { value } => OutputValue::Text { value },
+            KernelOutputValue::Stream { name, value } => OutputValue::Stream {
+                name,
+                value,
+                spans: Vec::new(),
+                truncated: None,
+            },
+            KernelOutputValue::Text { value } => OutputValue::Text {
+                value,
+                truncated: None,
+                dropped: Vec::new(),
+            },
             KernelOutputValue::Html { value } => OutputValue::Html { value },
+            KernelOutputValue::MimeBundle { value } => OutputValue::MimeBundle { value },
             KernelOutputValue::Exception { value } => OutputValue::Exception { value },
+            KernelOutputValue::Log { value } => OutputValue::Log { value },
             KernelOutputValue::None => OutputValue::None,
         }
     }
+
+    /// Size in bytes as it is counted into output limits
+    fn size(&self) -> usize {
+        match self {
+            OutputValue::Stream { value, .. }
+            | OutputValue::Text { value, .. }
+            | OutputValue::Html { value } => value.len(),
+            OutputValue::MimeBundle { value } => value.values().map(|v| v.len()).sum(),
+            OutputValue::Exception { value } => value.message.len() + value.traceback.len(),
+            OutputValue::Log { value } => value.message.len(),
+            OutputValue::None => 0,
+        }
+    }
+
+    pub fn truncation(&self) -> Option<&Truncation> {
+        match self {
+            OutputValue::Stream { truncated, .. } | OutputValue::Text { truncated, .. } => {
+                truncated.as_ref()
+            }
+            _ => None,
+        }
+    }
+
+    /// Shortens a value over the limit, rich values that are too big are kept as a plain text
+    /// and their dropped MIME types are recorded, so the client can tell what is missing.
+    /// Streams are shortened while they are written, exceptions and log records
+    /// are always kept whole.
+    pub fn limit(self, limit: usize, spool: &OutputSpool) -> OutputValue {
+        if self.size() <= limit {
+            return self;
+        }
+        let (mut text, mut truncated, dropped) = match self {
+            OutputValue::Text {
+                value,
+                truncated,
+                dropped,
+            } => (value, truncated, dropped),
+            OutputValue::Html { value } => (value, None, vec![TEXT_HTML.to_string()]),
+            OutputValue::MimeBundle { mut value } => {
+                let text = value.remove(TEXT_PLAIN).unwrap_or_default();
+                (text, None, value.into_keys().collect())
+            }
+            value => return value,
+        };
+        spool.truncate_text(&mut text, &mut Vec::new(), &mut truncated, limit, limit / 2);
+        OutputValue::Text {
+            value: text,
+            truncated,
+            dropped,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputCellFlag {
+    /// Waiting in the queue of the run, it was not sent to the kernel yet
+    Pending,
+    Running,
+    Success,
+    Fail,
+    /// Removed from the queue before it was sent to the kernel
+    Cancelled,
+}
+
+impl OutputCellFlag {
+    pub fn is_final(&self) -> bool {
+        match self {
+            OutputCellFlag::Pending | OutputCellFlag::Running => false,
+            OutputCellFlag::Success | OutputCellFlag::Fail | OutputCellFlag::Cancelled => true,
+        }
+    }
+}
+
+impl From<OutputFlag> for OutputCellFlag {
+    fn from(flag: OutputFlag) -> Self {
+        match flag {
+            OutputFlag::Running => OutputCellFlag::Running,
+            OutputFlag::Success => OutputCellFlag::Success,
+            OutputFlag::Fail => OutputCellFlag::Fail,
+        }
+    }
+}
+
+/// Times of the computation of a cell, `started` is reported by the kernel
+/// as computations wait in the queue of the run before they are sent to the kernel
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
+pub(crate) struct CellTiming {
+    #[serde(default)]
+    pub queued: Option<Timestamp>,
+    #[serde(default)]
+    pub started: Option<Timestamp>,
+    #[serde(default)]
+    pub finished: Option<Timestamp>,
+    /// Milliseconds from the start to the end of the computation
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+}
+
+impl CellTiming {
+    fn finish(&mut self, time: Timestamp) {
+        self.finished = Some(time);
+        self.duration_ms = self
+            .started
+            .map(|started| time.duration_since(started).as_millis().max(0) as u64);
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -111,21 +259,58 @@ pub(crate) struct OutputCell {
     id: OutputCellId,
     // If flag is finished/failed then the last value is returned object/exception
     values: Vec<OutputValue>,
-    flag: OutputFlag,
+    flag: OutputCellFlag,
+    #[serde(default)]
+    timing: CellTiming,
     editor_node: EditorGroup,
     called_id: EditorId,
+    #[serde(skip)]
+    timeout: Option<Duration>,
 }
 
 impl OutputCell {
-    pub fn new(id: OutputCellId, editor_node: EditorGroup, called_id: EditorId) -> Self {
+    pub fn new(
+        id: OutputCellId,
+        editor_node: EditorGroup,
+        called_id: EditorId,
+        timeout: Option<Duration>,
+    ) -> Self {
         OutputCell {
             id,
             values: Vec::new(),
-            flag: OutputFlag::Running,
+            flag: OutputCellFlag::Pending,
+            timing: CellTiming {
+                queued: Some(Timestamp::now()),
+                ..Default::default()
+            },
             editor_node,
             called_id,
+            timeout,
         }
     }
+    pub fn id(&self) -> OutputCellId {
+        self.id
+    }
+    pub fn values(&self) -> &[OutputValue] {
+        &self.values
+    }
+    pub fn flag(&self) -> OutputCellFlag {
+        self.flag
+    }
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+    fn values_size(&self) -> usize {
+        self.values.iter().map(|v| v.size()).sum()
+    }
+}
+
+/// Marker of kernel restart in the output history of a run.
+/// `position` is the number of output cells created before the restart.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct KernelRestart {
+    position: usize,
+    time: Timestamp,
 }
 
 #[nutype(derive(
@@ -199,10 +384,59 @@ pub(crate) struct EditorId(Uuid);
 pub enum KernelState {
     Init(KernelId),
     Running(KernelId),
-    Crashed(String),
+    Crashed(KernelCrash),
     Closed,
 }
 
+/// Details about an abnormal end of a kernel.
+/// All fields except `message` are optional for crashes stored by older versions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct KernelCrash {
+    pub message: String,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub signal: Option<String>,
+    /// The kernel was killed by SIGKILL while the OOM killer was active in its cgroup
+    #[serde(default)]
+    pub oom: bool,
+    /// Cell that was computed when the kernel crashed
+    #[serde(default)]
+    pub cell_id: Option<OutputCellId>,
+    #[serde(default)]
+    pub time: Option<Timestamp>,
+}
+
+impl KernelCrash {
+    pub fn new(message: String) -> Self {
+        KernelCrash {
+            message,
+            exit_code: None,
+            signal: None,
+            oom: false,
+            cell_id: None,
+            time: Some(Timestamp::now()),
+        }
+    }
+}
+
+/// Output as it is sent to clients
+pub(crate) struct OutputChange {
+    pub value: OutputValue,
+    /// The value replaces the last value of the cell from this offset (in characters)
+    pub replace_from: Option<usize>,
+}
+
+/// Prompt of a computation that waits for a text from the user
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct InputRequest {
+    pub cell_id: OutputCellId,
+    pub request_id: Uuid,
+    pub prompt: String,
+    /// The answer is not echoed
+    pub password: bool,
+}
+
 // #[derive(Debug, Serialize, Deserialize, Default)]
 // #[serde(transparent)]
 // pub struct ScopedObjects(Vec<(ScopeId, Vec<(String, Arc<String>)>)>);
@@ -222,9 +456,25 @@ pub enum KernelState {
 pub(crate) struct Run {
     title: String,
     output_cells: Vec<OutputCell>,
+    kernel_restarts: Vec<KernelRestart>,
     kernel: KernelState,
+    /// Running kernel does not answer heartbeats
+    unresponsive: bool,
     queue: usize,
+    /// Computations waiting for the kernel, only one computation is sent to the kernel at a time
+    pending: VecDeque<ComputeMsg>,
+    /// Positions of outputs displayed with an id by the current kernel
+    displays: HashMap<String, Vec<(OutputCellId, usize)>>,
+    /// Input requested by the running cell that is not answered yet
+    input_request: Option<InputRequest>,
+    /// Cell failed by the server (timeout) that may still be computed by the kernel,
+    /// the next computation is sent after the kernel finishes it
+    interrupted: Option<OutputCellId>,
+    /// Terminal states of streams of the running cell
+    terminals: HashMap<StreamName, Terminal>,
     globals: SerializedGlobals,
+    /// Limits of objects sent to the globals inspector
+    jobject_limits: JObjectLimits,
     created: Timestamp,
 }
 
@@ -232,6 +482,7 @@ impl Run {
     pub fn new(
         title: String,
         output_cells: Vec<OutputCell>,
+        kernel_restarts: Vec<KernelRestart>,
         kernel: KernelState,
         globals: SerializedGlobals,
         created: Timestamp,
@@ -239,30 +490,141 @@ impl Run {
         Run {
             title,
             output_cells,
+            kernel_restarts,
             kernel,
+            unresponsive: false,
             queue: 0,
+            pending: VecDeque::new(),
+            displays: HashMap::new(),
+            input_request: None,
+            interrupted: None,
+            terminals: HashMap::new(),
             globals,
+            jobject_limits: JObjectLimits::default(),
             created,
         }
     }
     pub fn created(&self) -> Timestamp {
         self.created
     }
-    pub fn set_crashed_kernel(&mut self, message: String) {
+    /// Marks the kernel as crashed, it returns new flags of cells that were unfinished
+    pub fn set_crashed_kernel(
+        &mut self,
+        mut crash: KernelCrash,
+    ) -> HashMap<OutputCellId, OutputCellFlag> {
+        if matches!(self.kernel, KernelState::Running(_)) {
+            crash.cell_id = self.running_cell().map(|cell| cell.id());
+        }
+        let cell_flags = self.fail_unfinished_cells();
+        self.unresponsive = false;
+        self.kernel = KernelState::Crashed(crash);
+        cell_flags
+    }
+    /// The running cell fails and queued cells are cancelled as the kernel will not compute
+    /// them anymore, it returns new flags of changed cells
+    fn fail_unfinished_cells(&mut self) -> HashMap<OutputCellId, OutputCellFlag> {
+        let mut cell_flags = HashMap::new();
+        for cell in &mut self.output_cells {
+            match cell.flag {
+                OutputCellFlag::Running => {
+                    cell.flag = OutputCellFlag::Fail;
+                    cell.timing.finish(Timestamp::now());
+                }
+                OutputCellFlag::Pending => cell.flag = OutputCellFlag::Cancelled,
+                _ => continue,
+            }
+            cell_flags.insert(cell.id, cell.flag);
+        }
         self.queue = 0;
-        self.kernel = KernelState::Crashed(message)
+        self.pending.clear();
+        self.input_request = None;
+        self.interrupted = None;
+        self.terminals.clear();
+        cell_flags
+    }
+    pub fn restart_kernel(&mut self, kernel_id: KernelId) {
+        self.fail_unfinished_cells();
+        self.unresponsive = false;
+        self.kernel = KernelState::Init(kernel_id);
+        self.globals = SerializedGlobals::default();
+        self.displays.clear();
+        self.kernel_restarts.push(KernelRestart {
+            position: self.output_cells.len(),
+            time: Timestamp::now(),
+        });
+    }
+    /// Binds a detached kernel of a previous server,
+    /// outputs of cells that were computed meanwhile are not expected anymore
+    pub fn reattach_kernel(&mut self, kernel_id: KernelId) {
+        self.fail_unfinished_cells();
+        self.unresponsive = false;
+        self.kernel = KernelState::Init(kernel_id);
+    }
+    pub fn close_kernel(&mut self) {
+        self.fail_unfinished_cells();
+        self.unresponsive = false;
+        self.kernel = KernelState::Closed;
     }
     pub fn queue_increment(&mut self) {
         self.queue += 1;
     }
+    pub fn queue_len(&self) -> usize {
+        self.queue
+    }
     pub fn queue_decrement(&mut self) {
         assert!(self.queue > 0);
         self.queue -= 1;
     }
+    pub fn enqueue_compute(&mut self, compute: ComputeMsg) {
+        self.pending.push_back(compute);
+    }
+
+    /// Takes the next queued computation when the kernel is ready and computes nothing
+    pub fn start_next_compute(&mut self) -> Option<ComputeMsg> {
+        if !matches!(self.kernel, KernelState::Running(_))
+            || self.running_cell().is_some()
+            || self.interrupted.is_some()
+        {
+            return None;
+        }
+        let compute = self.pending.pop_front()?;
+        let cell_id = OutputCellId::new(compute.cell_id);
+        if let Some(cell) = self.find_output_cell_mut(cell_id) {
+            cell.flag = OutputCellFlag::Running;
+        }
+        Some(compute)
+    }
+
+    /// Removes a computation from the queue before it is sent to the kernel
+    pub fn cancel_compute(&mut self, cell_id: OutputCellId) -> anyhow::Result<()> {
+        let position = self
+            .pending
+            .iter()
+            .position(|compute| compute.cell_id == cell_id.into_inner())
+            .ok_or_else(|| anyhow!("Cell {cell_id} is not queued"))?;
+        self.pending.remove(position);
+        if let Some(cell) = self.find_output_cell_mut(cell_id) {
+            cell.flag = OutputCellFlag::Cancelled;
+        }
+        self.queue_decrement();
+        Ok(())
+    }
+
+    /// Cells of queued computations in the order they are sent to the kernel
+    pub fn queued_cells(&self) -> Vec<OutputCellId> {
+        self.pending
+            .iter()
+            .map(|compute| OutputCellId::new(compute.cell_id))
+            .collect()
+    }
+
     pub fn set_running_kernel(&mut self, kernel_id: KernelId) {
         assert!(matches!(self.kernel, KernelState::Init(id) if id == kernel_id));
         self.kernel = KernelState::Running(kernel_id);
     }
+    pub fn set_unresponsive(&mut self, value: bool) {
+        self.unresponsive = value;
+    }
     pub fn kernel_state(&self) -> &KernelState {
         &self.kernel
     }
@@ -272,6 +634,9 @@ impl Run {
     pub fn output_cells(&self) -> &[OutputCell] {
         &self.output_cells
     }
+    pub fn kernel_restarts(&self) -> &[KernelRestart] {
+        &self.kernel_restarts
+    }
     pub fn kernel_id(&mut self) -> Option<KernelId> {
         match &self.kernel {
             KernelState::Init(kernel_id) | KernelState::Running(kernel_id) => Some(*kernel_id),
@@ -279,6 +644,79 @@ impl Run {
         }
     }
 
+    /// Returns the cell that is currently computed by the kernel
+    pub fn running_cell(&self) -> Option<&OutputCell> {
+        self.output_cells
+            .iter()
+            .find(|c| c.flag == OutputCellFlag::Running)
+    }
+
+    pub fn find_output_cell(&self, cell_id: OutputCellId) -> Option<&OutputCell> {
+        self.output_cells.iter().rev().find(|c| c.id == cell_id)
+    }
+
+    fn find_output_cell_mut(&mut self, cell_id: OutputCellId) -> Option<&mut OutputCell> {
+        self.output_cells.iter_mut().rev().find(|c| c.id == cell_id)
+    }
+
+    /// Marks the running cell that was failed by the server as still computed by the kernel
+    pub fn set_interrupted(&mut self, cell_id: OutputCellId) {
+        self.interrupted = Some(cell_id);
+    }
+
+    /// Called on the final output of a cell that was already finished by the server,
+    /// returns true when the kernel is free for the next computation
+    pub fn finish_interrupted(&mut self, cell_id: OutputCellId) -> bool {
+        if self.interrupted == Some(cell_id) {
+            self.interrupted = None;
+            return true;
+        }
+        false
+    }
+
+    /// Records the start of the computation as reported by the kernel
+    pub fn set_cell_started(&mut self, cell_id: OutputCellId, time: Timestamp) {
+        if let Some(cell) = self
+            .find_output_cell_mut(cell_id)
+            .filter(|c| c.flag == OutputCellFlag::Running)
+        {
+            cell.timing.started = Some(time);
+        }
+    }
+
+    /// Updates the timing of the cell by a new output and returns it,
+    /// `finished` is the end of the computation reported by the kernel, now is used otherwise
+    pub fn update_cell_timing(
+        &mut self,
+        cell_id: OutputCellId,
+        flag: OutputFlag,
+        finished: Option<Timestamp>,
+    ) -> anyhow::Result<CellTiming> {
+        let cell = self
+            .find_output_cell_mut(cell_id)
+            .ok_or_else(|| anyhow!("Output cell {cell_id} not found"))?;
+        if flag.is_final() {
+            cell.timing.finish(finished.unwrap_or_else(Timestamp::now));
+        }
+        Ok(cell.timing)
+    }
+
+    pub fn set_input_request(&mut self, request: InputRequest) {
+        self.input_request = Some(request);
+    }
+
+    /// Removes the pending input request when it is answered
+    pub fn take_input_request(&mut self, request_id: Uuid) -> anyhow::Result<InputRequest> {
+        if self
+            .input_request
+            .as_ref()
+            .is_none_or(|r| r.request_id != request_id)
+        {
+            return Err(anyhow!("Input request {request_id} is not pending"));
+        }
+        Ok(self.input_request.take().unwrap())
+    }
+
     pub fn add_output_cell(&mut self, output_cell: OutputCell) {
         self.output_cells.push(output_cell);
     }
@@ -290,37 +728,186 @@ impl Run {
     pub fn globals(&self) -> &SerializedGlobals {
         &self.globals
     }
+    pub fn jobject_limits(&self) -> &JObjectLimits {
+        &self.jobject_limits
+    }
+    pub fn set_jobject_limits(&mut self, limits: JObjectLimits) {
+        self.jobject_limits = limits;
+    }
 
-    pub fn add_output(&mut self, cell_id: OutputCellId, value: OutputValue, flag: OutputFlag) {
-        if let Some(ref mut last) = self.output_cells.iter_mut().rev().find(|c| c.id == cell_id) {
-            if let (
-                OutputFlag::Running,
-                OutputValue::Text { value: new_text },
-                Some(OutputValue::Text { value: old_text }),
-            ) = (flag, &value, last.values.last_mut())
-            {
-                old_text.push_str(new_text);
-                last.flag = flag;
-                return;
+    /// Adds an output to the cell and returns the change that is sent to clients.
+    /// Chunks of a stream are interpreted as a terminal output and merged
+    /// into the last value when they come from the same stream
+    pub fn add_output(
+        &mut self,
+        cell_id: OutputCellId,
+        value: OutputValue,
+        flag: OutputFlag,
+        spool: &OutputSpool,
+    ) -> anyhow::Result<OutputChange> {
+        let cell = self
+            .output_cells
+            .iter_mut()
+            .rev()
+            .find(|c| c.id == cell_id)
+            .ok_or_else(|| anyhow!("Output cell {cell_id} not found"))?;
+        if flag.is_final()
+            && self
+                .input_request
+                .as_ref()
+                .is_some_and(|r| r.cell_id == cell_id)
+        {
+            self.input_request = None;
+        }
+        cell.flag = flag.into();
+        let change = if let OutputValue::Stream {
+            name, value: chunk, ..
+        } = value
+        {
+            let terminal = self.terminals.entry(name).or_default();
+            let merge = flag == OutputFlag::Running
+                && matches!(
+                    cell.values.last(),
+                    Some(OutputValue::Stream { name: last, .. }) if *last == name
+                );
+            if !merge {
+                terminal.start_text();
+                cell.values.push(OutputValue::Stream {
+                    name,
+                    value: String::new(),
+                    spans: Vec::new(),
+                    truncated: None,
+                });
+            }
+            let limit = spool
+                .limits
+                .value_limit(cell.values_size() - cell.values.last().unwrap().size());
+            let Some(OutputValue::Stream {
+                value: text,
+                spans,
+                truncated,
+                ..
+            }) = cell.values.last_mut()
+            else {
+                unreachable!()
+            };
+            let change = terminal.write(text, spans, &chunk);
+            if let Some(removal) = spool.truncate_text(text, spans, truncated, limit, limit / 4) {
+                // The middle of the stream was moved into the spool, so the whole value is sent
+                terminal.text_removed(&removal);
+                OutputChange {
+                    value: cell.values.last().unwrap().clone(),
+                    replace_from: merge.then_some(0),
+                }
+            } else {
+                OutputChange {
+                    value: OutputValue::Stream {
+                        name,
+                        value: text[change.byte_offset..].to_string(),
+                        // Spans are ordered, so the changed ones are found by bisection
+                        spans: spans
+                            [spans.partition_point(|span| span.end <= change.char_offset)..]
+                            .iter()
+                            .map(|span| StyleSpan {
+                                start: span.start.max(change.char_offset),
+                                ..span.clone()
+                            })
+                            .collect(),
+                        truncated: None,
+                    },
+                    replace_from: merge.then_some(change.char_offset),
+                }
             }
-            last.values.push(value);
-            last.flag = flag;
         } else {
-            panic!("Output cell with id {cell_id} not found");
+            let value = value.limit(spool.limits.value_limit(cell.values_size()), spool);
+            cell.values.push(value.clone());
+            OutputChange {
+                value,
+                replace_from: None,
+            }
+        };
+        if flag.is_final() {
+            self.terminals.clear();
+        }
+        Ok(change)
+    }
+
+    /// Appends an output displayed during the computation of the cell,
+    /// it returns the value shortened to what is left of the output limit of the cell
+    pub fn add_display(
+        &mut self,
+        cell_id: OutputCellId,
+        value: OutputValue,
+        display_id: Option<String>,
+        spool: &OutputSpool,
+    ) -> anyhow::Result<OutputValue> {
+        let cell = self
+            .output_cells
+            .iter_mut()
+            .rev()
+            .find(|c| c.id == cell_id)
+            .ok_or_else(|| anyhow!("Output cell {cell_id} not found"))?;
+        if let Some(display_id) = display_id {
+            self.displays
+                .entry(display_id)
+                .or_default()
+                .push((cell_id, cell.values.len()));
+        }
+        let value = value.limit(spool.limits.value_limit(cell.values_size()), spool);
+        cell.values.push(value.clone());
+        Ok(value)
+    }
+
+    /// Replaces outputs displayed with the id, it returns their positions
+    /// and the values shortened to the output limits of their cells
+    pub fn update_display(
+        &mut self,
+        display_id: &str,
+        value: &OutputValue,
+        spool: &OutputSpool,
+    ) -> Vec<(OutputCellId, usize, OutputValue)> {
+        let Some(positions) = self.displays.get(display_id) else {
+            return Vec::new();
+        };
+        let mut updated = Vec::with_capacity(positions.len());
+        for &(cell_id, index) in positions {
+            if let Some(cell) = self.output_cells.iter_mut().rev().find(|c| c.id == cell_id) {
+                if let Some(truncation) = cell.values[index].truncation() {
+                    spool.remove(vec![truncation.spool.clone()]);
+                }
+                let other_size = cell.values_size() - cell.values[index].size();
+                let value = value
+                    .clone()
+                    .limit(spool.limits.value_limit(other_size), spool);
+                cell.values[index] = value.clone();
+                updated.push((cell_id, index, value));
+            }
         }
+        updated
+    }
+
+    /// Files in the spool with omitted texts of outputs of the run
+    pub fn spool_files(&self) -> Vec<String> {
+        self.output_cells
+            .iter()
+            .flat_map(|cell| cell.values.iter())
+            .filter_map(|value| value.truncation().map(|t| t.spool.clone()))
+            .collect()
     }
 
     pub fn kernel_state_desc(&self) -> KernelStateDesc {
         match self.kernel_state() {
             KernelState::Init(_) => KernelStateDesc::Init,
             KernelState::Running(_) => {
-                if self.queue > 0 {
+                if self.unresponsive {
+                    KernelStateDesc::Unresponsive
+                } else if self.queue > 0 {
                     KernelStateDesc::Running
                 } else {
                     KernelStateDesc::Ready
                 }
             }
-            KernelState::Crashed(s) => KernelStateDesc::Crashed { message: s.clone() },
+            KernelState::Crashed(crash) => KernelStateDesc::Crashed(crash.clone()),
             KernelState::Closed => KernelStateDesc::Closed,
         }
     }
@@ -330,6 +917,8 @@ pub(crate) struct Notebook {
     pub editor_root: EditorGroup,
     pub editor_open_nodes: Vec<EditorId>,
     pub path: String,
+    /// Timeout (in seconds) for cells that do not specify their own
+    pub default_timeout: Option<u64>,
     pub runs: HashMap<RunId, Run>,
     pub run_order: Vec<RunId>,
     pub observer: Option<UnboundedSender<Message>>,
@@ -384,6 +973,7 @@ impl Notebook {
             path,
             editor_root,
             editor_open_nodes,
+            default_timeout: None,
             runs: Default::default(),
             run_order: Vec::new(),
             observer: None,
@@ -421,6 +1011,12 @@ impl Notebook {
             .ok_or_else(|| anyhow!(format!("Run {run_id} not found")))
     }
 
+    pub fn find_run_by_id(&self, run_id: RunId) -> anyhow::Result<&Run> {
+        self.runs
+            .get(&run_id)
+            .ok_or_else(|| anyhow!(format!("Run {run_id} not found")))
+    }
+
     pub fn remove_run_by_id(&mut self, run_id: RunId) -> anyhow::Result<Run> {
         self.run_order.retain(|r_id| run_id != *r_id);
         self.runs
@@ -428,13 +1024,17 @@ impl Notebook {
             .ok_or_else(|| anyhow!(format!("Run {run_id} not found")))
     }
 
+    pub fn runs_mut(&mut self) -> impl Iterator<Item = &mut Run> {
+        self.runs.values_mut()
+    }
+
     pub fn runs(&self) -> impl Iterator<Item = (RunId, &Run)> + '_ {
         self.run_order
             .iter()
             .map(|run_id| (*run_id, self.runs.get(run_id).unwrap()))
     }
 
-    pub fn notebook_desc(&self, notebook_id: NotebookId) -> NotebookDesc {
+    pub fn notebook_desc(&self, notebook_id: NotebookId) -> NotebookDesc<'_> {
         let runs = self
             .run_order
             .iter()
@@ -444,14 +1044,18 @@ impl Notebook {
                     id: *run_id,
                     title: &run.title,
                     output_cells: &run.output_cells,
+                    kernel_restarts: &run.kernel_restarts,
                     kernel_state: run.kernel_state_desc(),
                     globals: &run.globals,
+                    jobject_limits: &run.jobject_limits,
+                    input_request: run.input_request.as_ref(),
                 }
             })
             .collect::<Vec<_>>();
         NotebookDesc {
             id: notebook_id,
             path: &self.path,
+            default_timeout: self.default_timeout,
             editor_root: &self.editor_root,
             editor_open_nodes: &self.editor_open_nodes,
             runs,
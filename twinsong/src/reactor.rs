@@ -1,19 +1,26 @@
 use crate::client_messages::{
-    DirEntry, DirEntryType, ForkMsg, LoadNotebookMsg, RunCodeMsg, SaveNotebookMsg, ToClientMessage,
-    serialize_client_message,
+    DirEntry, DirEntryType, FetchOutputMsg, ForkMsg, InputReplyMsg, InspectVariableMsg,
+    LoadNotebookMsg, NotebookRunMsg, RunCellMsg, RunCodeMsg, SaveNotebookMsg, SetJObjectLimitsMsg,
+    SetNotebookTimeoutMsg, ToClientMessage, serialize_client_message,
+};
+use crate::kernel::{
+    KernelCtx, kernel_log_paths, read_log_tail, refill_kernel_pool, send_kernels_update,
+    spawn_kernel,
 };
-use crate::kernel::{KernelCtx, spawn_kernel};
 use crate::notebook::{
-    KernelId, KernelState, Notebook, NotebookId, OutputCell, OutputCellId, OutputValue, Run, RunId,
+    InputRequest, KernelCrash, KernelId, KernelState, Notebook, NotebookId, OutputCell,
+    OutputCellId, OutputValue, Run, RunId,
 };
 use crate::state::{AppState, AppStateRef};
 use crate::storage::{SerializedNotebook, deserialize_notebook, serialize_notebook};
 use anyhow::{anyhow, bail};
 use axum::extract::ws::Message;
-use comm::messages::{ComputeMsg, FromKernelMessage, ToKernelMessage};
+use comm::messages::{ComputeMsg, Exception, FromKernelMessage, OutputFlag, ToKernelMessage};
 use comm::scopes::SerializedGlobals;
+use futures_util::future::join_all;
 use jiff::Timestamp;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use tokio::spawn;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot;
@@ -40,6 +47,125 @@ pub(crate) fn new_notebook(
     save_helper(notebook_id, notebook, state_ref, true)
 }
 
+/// Id for a kernel of a run; an idle kernel from the pool is used when there is any
+fn acquire_kernel_id(state: &mut AppState) -> KernelId {
+    state
+        .take_pooled_kernel()
+        .unwrap_or_else(|| KernelId::new(Uuid::new_v4()))
+}
+
+pub(crate) fn kernel_ready(state: &mut AppState, state_ref: &AppStateRef, kernel_ctx: &KernelCtx) {
+    let notebook_id = kernel_ctx.notebook_id;
+    let run_id = kernel_ctx.run_id;
+    let notebook = state.notebook_by_id_mut(notebook_id);
+    let run = notebook.find_run_by_id_mut(run_id).unwrap();
+    run.set_running_kernel(kernel_ctx.kernel_id);
+    notebook.send_message(ToClientMessage::KernelReady {
+        notebook_id,
+        run_id,
+    });
+    if let Err(e) = dispatch_compute(state, state_ref, notebook_id, run_id) {
+        tracing::debug!("Sending queued computation failed: {e}");
+    }
+}
+
+fn spawn_run_kernel(state: &mut AppState, state_ref: &AppStateRef, kernel_ctx: KernelCtx) {
+    let kernel_id = kernel_ctx.kernel_id;
+    if let Some(kernel) = state.get_kernel_by_id_mut(kernel_id) {
+        // Kernel was taken from the pool
+        tracing::debug!(
+            "Binding kernel {kernel_id} from pool to run {}",
+            kernel_ctx.run_id
+        );
+        kernel.bind(kernel_ctx);
+        let is_init = kernel.is_init();
+        state.save_runtime_state();
+        if !is_init {
+            kernel_ready(state, state_ref, &kernel_ctx);
+        }
+    } else {
+        spawn_new_run_kernel(state, state_ref, kernel_ctx);
+    }
+    refill_kernel_pool(state, state_ref);
+}
+
+fn spawn_new_run_kernel(state: &mut AppState, state_ref: &AppStateRef, kernel_ctx: KernelCtx) {
+    let kernel_id = kernel_ctx.kernel_id;
+    let notebook_id = kernel_ctx.notebook_id;
+    let run_id = kernel_ctx.run_id;
+    match spawn_kernel(
+        state_ref,
+        kernel_id,
+        state.kernel_port(),
+        state.runtime_dir(),
+        state.detached_kernels(),
+    ) {
+        Ok(mut kernel) => {
+            kernel.bind(kernel_ctx);
+            state.add_kernel(kernel_id, kernel);
+        }
+        Err(e) => {
+            tracing::error!("Starting kernel failed {e}");
+            kernel_crashed(
+                state,
+                notebook_id,
+                run_id,
+                KernelCrash::new(e.to_string()),
+                String::new(),
+            );
+        }
+    }
+}
+
+/// Updates the responsiveness of the kernel bound to a run and informs clients
+pub(crate) fn set_kernel_responsive(
+    state: &mut AppState,
+    kernel_ctx: &KernelCtx,
+    responsive: bool,
+) {
+    let Ok(notebook) = state.find_notebook_by_id_mut(kernel_ctx.notebook_id) else {
+        return;
+    };
+    let Ok(run) = notebook.find_run_by_id_mut(kernel_ctx.run_id) else {
+        return;
+    };
+    if run.kernel_id() != Some(kernel_ctx.kernel_id) {
+        return;
+    }
+    run.set_unresponsive(!responsive);
+    let kernel_state = run.kernel_state_desc();
+    notebook.send_message(ToClientMessage::KernelStateChanged {
+        notebook_id: kernel_ctx.notebook_id,
+        run_id: kernel_ctx.run_id,
+        kernel_state,
+    });
+}
+
+/// Marks the kernel of a run as crashed and informs clients
+pub(crate) fn kernel_crashed(
+    state: &mut AppState,
+    notebook_id: NotebookId,
+    run_id: RunId,
+    crash: KernelCrash,
+    stderr: String,
+) {
+    let notebook = state.notebook_by_id_mut(notebook_id);
+    let run = notebook.find_run_by_id_mut(run_id).unwrap();
+    let cell_flags = run.set_crashed_kernel(crash);
+    // Crash is taken from the run as it is extended by the executed cell
+    let KernelState::Crashed(crash) = run.kernel_state() else {
+        unreachable!()
+    };
+    let crash = crash.clone();
+    notebook.send_message(ToClientMessage::KernelCrashed {
+        notebook_id,
+        run_id,
+        crash,
+        stderr,
+        cell_flags,
+    });
+}
+
 pub(crate) fn start_kernel(
     state: &mut AppState,
     state_ref: &AppStateRef,
@@ -47,56 +173,325 @@ pub(crate) fn start_kernel(
     run_id: RunId,
     run_title: String,
 ) -> anyhow::Result<KernelId> {
-    let kernel_port = state.kernel_port();
-    let notebook = state.find_notebook_by_id_mut(notebook_id)?;
-    let kernel_id = KernelId::new(Uuid::new_v4());
+    // Notebook is checked before a kernel is taken from the pool
+    state.find_notebook_by_id_mut(notebook_id)?;
+    let kernel_id = acquire_kernel_id(state);
     let kernel_ctx = KernelCtx {
         kernel_id,
         notebook_id,
         run_id,
     };
+    let notebook = state.notebook_by_id_mut(notebook_id);
     let run = Run::new(
         run_title,
         Vec::new(),
+        Vec::new(),
         KernelState::Init(kernel_ctx.kernel_id),
         SerializedGlobals::default(),
         Timestamp::now(),
     );
     notebook.add_run(run_id, run);
-    match spawn_kernel(state_ref, kernel_ctx, kernel_port) {
-        Ok(kernel) => {
-            state.add_kernel(kernel_id, kernel);
-        }
-        Err(e) => {
-            tracing::error!("Starting kernel failed {e}");
-            let run = notebook.find_run_by_id_mut(run_id).unwrap();
-            run.set_crashed_kernel(e.to_string());
-            notebook.send_message(ToClientMessage::KernelCrashed {
-                notebook_id,
-                run_id,
-                message: e.to_string(),
-            });
-        }
-    }
+    spawn_run_kernel(state, state_ref, kernel_ctx);
     Ok(kernel_id)
 }
 
-pub(crate) fn run_code(state: &mut AppState, msg: RunCodeMsg) -> anyhow::Result<()> {
+pub(crate) fn restart_kernel(
+    state: &mut AppState,
+    state_ref: &AppStateRef,
+    notebook_id: NotebookId,
+    run_id: RunId,
+) -> anyhow::Result<()> {
+    tracing::debug!("Restarting kernel of run {}", run_id);
+    let notebook = state.find_notebook_by_id_mut(notebook_id)?;
+    let old_kernel_id = notebook.find_run_by_id_mut(run_id)?.kernel_id();
+    let kernel_id = acquire_kernel_id(state);
+    let notebook = state.notebook_by_id_mut(notebook_id);
+    let run = notebook.find_run_by_id_mut(run_id).unwrap();
+    run.restart_kernel(kernel_id);
+    let run = notebook.find_run_by_id(run_id)?;
+    notebook.send_message(ToClientMessage::KernelRestarted {
+        notebook_id,
+        run_id,
+        restart: run.kernel_restarts().last().unwrap(),
+    });
+    if let Some(old_kernel_id) = old_kernel_id {
+        state.stop_kernel(old_kernel_id);
+    }
+    let kernel_ctx = KernelCtx {
+        kernel_id,
+        notebook_id,
+        run_id,
+    };
+    spawn_run_kernel(state, state_ref, kernel_ctx);
+    Ok(())
+}
+
+pub(crate) fn run_code(
+    state: &mut AppState,
+    state_ref: &AppStateRef,
+    msg: RunCodeMsg,
+) -> anyhow::Result<()> {
     tracing::debug!("Runnning code {:?}", msg);
     let notebook = state.find_notebook_by_id_mut(msg.notebook_id)?;
+    let timeout = msg
+        .timeout
+        .or(notebook.default_timeout)
+        .map(Duration::from_secs);
     let run = notebook.find_run_by_id_mut(msg.run_id)?;
     let code = msg.editor_node.to_code_group();
-    run.add_output_cell(OutputCell::new(msg.cell_id, msg.editor_node, msg.called_id));
+    run.add_output_cell(OutputCell::new(
+        msg.cell_id,
+        msg.editor_node,
+        msg.called_id,
+        timeout,
+    ));
     run.queue_increment();
+    run.enqueue_compute(ComputeMsg {
+        cell_id: msg.cell_id.into_inner(),
+        code,
+    });
+    dispatch_compute(state, state_ref, msg.notebook_id, msg.run_id)
+}
+
+/// Sends the next queued computation of a run when its kernel computes nothing
+fn dispatch_compute(
+    state: &mut AppState,
+    state_ref: &AppStateRef,
+    notebook_id: NotebookId,
+    run_id: RunId,
+) -> anyhow::Result<()> {
+    let notebook = state.find_notebook_by_id_mut(notebook_id)?;
+    let run = notebook.find_run_by_id_mut(run_id)?;
+    let Some(compute) = run.start_next_compute() else {
+        return Ok(());
+    };
+    watch_running_cell(state_ref, notebook_id, run_id, run);
     if let Some(kernel) = run
         .kernel_id()
         .and_then(|kernel_id| state.get_kernel_by_id_mut(kernel_id))
     {
-        kernel.send_message(ToKernelMessage::Compute(ComputeMsg {
-            cell_id: msg.cell_id.into_inner(),
-            code,
-        }))
+        kernel.send_message(ToKernelMessage::Compute(compute))
+    }
+    Ok(())
+}
+
+pub(crate) fn cancel_compute(state: &mut AppState, msg: RunCellMsg) -> anyhow::Result<()> {
+    tracing::debug!("Cancelling computation of cell {}", msg.cell_id);
+    let notebook = state.find_notebook_by_id_mut(msg.notebook_id)?;
+    let run = notebook.find_run_by_id_mut(msg.run_id)?;
+    run.cancel_compute(msg.cell_id)?;
+    let kernel_state = run.kernel_state_desc();
+    notebook.send_message(ToClientMessage::ComputeCancelled {
+        notebook_id: msg.notebook_id,
+        run_id: msg.run_id,
+        cell_id: msg.cell_id,
+        kernel_state,
+    });
+    Ok(())
+}
+
+pub(crate) fn input_reply(state: &mut AppState, msg: InputReplyMsg) -> anyhow::Result<()> {
+    // The value itself is never logged, it may be a password
+    tracing::debug!("Input reply for request {}", msg.request_id);
+    let notebook = state.find_notebook_by_id_mut(msg.notebook_id)?;
+    let run = notebook.find_run_by_id_mut(msg.run_id)?;
+    run.take_input_request(msg.request_id)?;
+    let kernel_id = run
+        .kernel_id()
+        .ok_or_else(|| anyhow!("Run {} has no kernel", msg.run_id))?;
+    notebook.send_message(ToClientMessage::InputAnswered {
+        notebook_id: msg.notebook_id,
+        run_id: msg.run_id,
+        request_id: msg.request_id,
+    });
+    if let Some(kernel) = state.get_kernel_by_id_mut(kernel_id) {
+        kernel.send_message(ToKernelMessage::InputReply {
+            request_id: msg.request_id,
+            value: msg.value,
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn fetch_output(
+    state: &mut AppState,
+    msg: FetchOutputMsg,
+    sender: &UnboundedSender<Message>,
+) -> anyhow::Result<()> {
+    let notebook = state.find_notebook_by_id_mut(msg.notebook_id)?;
+    let run = notebook.find_run_by_id(msg.run_id)?;
+    let truncation = run
+        .find_output_cell(msg.cell_id)
+        .and_then(|cell| cell.values().get(msg.index))
+        .and_then(|value| value.truncation())
+        .ok_or_else(|| {
+            anyhow!(
+                "Output {} of cell {} is not truncated",
+                msg.index,
+                msg.cell_id
+            )
+        })?
+        .clone();
+    let notebook_path = notebook.path.clone();
+    let omitted = truncation.omitted;
+    // The page is read outside of the state lock
+    let page = state
+        .spool_writer()
+        .read_page(&notebook_path, truncation, msg.offset, msg.size);
+    let sender = sender.clone();
+    spawn(async move {
+        let message = match page.await.map_err(anyhow::Error::from).flatten() {
+            Ok((text, next_offset)) => serialize_client_message(ToClientMessage::OutputPage {
+                notebook_id: msg.notebook_id,
+                run_id: msg.run_id,
+                cell_id: msg.cell_id,
+                index: msg.index,
+                offset: msg.offset,
+                next_offset,
+                omitted,
+                text,
+            }),
+            Err(e) => {
+                tracing::error!("Reading of output page failed: {e}");
+                serialize_client_message(ToClientMessage::Error {
+                    message: &e.to_string(),
+                })
+            }
+        };
+        if let Ok(message) = message {
+            let _ = sender.send(message);
+        }
+    });
+    Ok(())
+}
+
+pub(crate) fn inspect_variable(
+    state: &mut AppState,
+    msg: InspectVariableMsg,
+    sender: &UnboundedSender<Message>,
+) -> anyhow::Result<()> {
+    let notebook = state.find_notebook_by_id_mut(msg.notebook_id)?;
+    let run = notebook.find_run_by_id_mut(msg.run_id)?;
+    let kernel_id = run
+        .kernel_id()
+        .ok_or_else(|| anyhow!("Run {} has no kernel", msg.run_id))?;
+    let limits = *run.jobject_limits();
+    let kernel = state
+        .get_kernel_by_id_mut(kernel_id)
+        .ok_or_else(|| anyhow!("Kernel {kernel_id} not found"))?;
+    kernel.inspect(msg, limits, sender.clone());
+    Ok(())
+}
+
+pub(crate) fn compute_queue(
+    state: &mut AppState,
+    msg: NotebookRunMsg,
+    sender: &UnboundedSender<Message>,
+) -> anyhow::Result<()> {
+    let notebook = state.find_notebook_by_id_mut(msg.notebook_id)?;
+    let run = notebook.find_run_by_id(msg.run_id)?;
+    let _ = sender.send(serialize_client_message(ToClientMessage::ComputeQueue {
+        notebook_id: msg.notebook_id,
+        run_id: msg.run_id,
+        cells: run.queued_cells(),
+    })?);
+    Ok(())
+}
+
+/// Starts the timeout of the cell that is currently computed in the run (if it has any)
+pub(crate) fn watch_running_cell(
+    state_ref: &AppStateRef,
+    notebook_id: NotebookId,
+    run_id: RunId,
+    run: &Run,
+) {
+    let KernelState::Running(kernel_id) = run.kernel_state() else {
+        return;
+    };
+    let Some(cell) = run.running_cell() else {
+        return;
+    };
+    let Some(timeout) = cell.timeout() else {
+        return;
+    };
+    let kernel_id = *kernel_id;
+    let cell_id = cell.id();
+    let state_ref = state_ref.clone();
+    spawn(async move {
+        tokio::time::sleep(timeout).await;
+        let mut state = state_ref.lock().unwrap();
+        if let Err(e) = cell_timeout(&mut state, notebook_id, run_id, kernel_id, cell_id, timeout) {
+            tracing::debug!("Cell timeout failed: {e}");
+        }
+    });
+}
+
+fn cell_timeout(
+    state: &mut AppState,
+    notebook_id: NotebookId,
+    run_id: RunId,
+    kernel_id: KernelId,
+    cell_id: OutputCellId,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let spool = state.output_spool(notebook_id)?;
+    let notebook = state.find_notebook_by_id_mut(notebook_id)?;
+    let run = notebook.find_run_by_id_mut(run_id)?;
+    if run.kernel_id() != Some(kernel_id) || run.running_cell().map(|c| c.id()) != Some(cell_id) {
+        return Ok(());
+    }
+    tracing::debug!("Cell {cell_id} timed out");
+    let value = OutputValue::Exception {
+        value: Exception {
+            message: format!(
+                "TimeoutError: Execution exceeded {} seconds",
+                timeout.as_secs()
+            ),
+            traceback: String::new(),
+        },
+    };
+    run.queue_decrement();
+    run.set_interrupted(cell_id);
+    let timing = run.update_cell_timing(cell_id, OutputFlag::Fail, None)?;
+    let kernel_state = run.kernel_state_desc();
+    let change = run.add_output(cell_id, value, OutputFlag::Fail, &spool)?;
+    notebook.send_message(ToClientMessage::Output {
+        notebook_id,
+        run_id,
+        cell_id,
+        value: &change.value,
+        replace_from: change.replace_from,
+        flag: OutputFlag::Fail,
+        timing,
+        update: None,
+        kernel_state,
+    });
+    if let Some(kernel) = state.get_kernel_by_id_mut(kernel_id) {
+        kernel.send_message(ToKernelMessage::Interrupt {
+            cell_id: Some(cell_id.into_inner()),
+        });
     }
+    // The next computation is dispatched when the kernel sends the final output of the cell
+    Ok(())
+}
+
+pub(crate) fn set_notebook_timeout(
+    state: &mut AppState,
+    msg: SetNotebookTimeoutMsg,
+) -> anyhow::Result<()> {
+    let notebook = state.find_notebook_by_id_mut(msg.notebook_id)?;
+    notebook.default_timeout = msg.timeout;
+    Ok(())
+}
+
+pub(crate) fn set_jobject_limits(
+    state: &mut AppState,
+    msg: SetJObjectLimitsMsg,
+) -> anyhow::Result<()> {
+    let notebook = state.find_notebook_by_id_mut(msg.notebook_id)?;
+    notebook
+        .find_run_by_id_mut(msg.run_id)?
+        .set_jobject_limits(msg.limits);
     Ok(())
 }
 
@@ -120,7 +515,7 @@ async fn fork_process(
         )?;
         state
             .get_kernel_by_id_mut(kernel_id)
-            .unwrap()
+            .ok_or_else(|| anyhow!("Kernel was not started"))?
             .load_state(path)
     };
     let result = receiver.await?;
@@ -131,7 +526,7 @@ async fn fork_process(
         notebook.send_message(ToClientMessage::NewGlobals {
             notebook_id: msg.notebook_id,
             run_id: msg.new_run_id,
-            globals: result,
+            globals: &result,
         });
     }
     Ok(())
@@ -167,32 +562,78 @@ pub(crate) fn fork_run(
     Ok(())
 }
 
+/// Converts a time reported by the kernel, it is None when it is out of the supported range
+fn kernel_time(time: SystemTime) -> Option<Timestamp> {
+    Timestamp::try_from(time).ok()
+}
+
 pub(crate) fn process_kernel_message(
     state: &mut AppState,
+    state_ref: &AppStateRef,
     kernel_ctx: &KernelCtx,
     msg: FromKernelMessage,
 ) -> anyhow::Result<()> {
     match msg {
         FromKernelMessage::Login { .. } => bail!("Process is already logged"),
+        FromKernelMessage::HeartbeatResponse(_) => {
+            // Last seen time of the kernel is updated for every message
+        }
         FromKernelMessage::Output {
             value,
             cell_id,
             flag,
             update,
+            finished,
         } => {
             let value = OutputValue::new(value);
+            let cell_id = OutputCellId::new(cell_id);
+            let spool = state.output_spool(kernel_ctx.notebook_id)?;
             let notebook = state.find_notebook_by_id_mut(kernel_ctx.notebook_id)?;
             let run = notebook.find_run_by_id_mut(kernel_ctx.run_id)?;
+            if run.kernel_id() != Some(kernel_ctx.kernel_id) {
+                tracing::debug!(
+                    "Ignoring output of replaced kernel {}",
+                    kernel_ctx.kernel_id
+                );
+                return Ok(());
+            }
+            if run
+                .find_output_cell(cell_id)
+                .is_none_or(|c| c.flag().is_final())
+            {
+                // Cell was already finished by the server (timeout) or it is unknown
+                // (a detached kernel re-attached to a notebook reloaded from disk),
+                // only globals are kept
+                tracing::debug!("Ignoring output of finished or unknown cell {cell_id}");
+                let kernel_free = flag.is_final() && run.finish_interrupted(cell_id);
+                if let Some(update) = update {
+                    run.update_globals(update);
+                    let run = notebook.find_run_by_id(kernel_ctx.run_id)?;
+                    notebook.send_message(ToClientMessage::NewGlobals {
+                        notebook_id: kernel_ctx.notebook_id,
+                        run_id: kernel_ctx.run_id,
+                        globals: run.globals(),
+                    });
+                }
+                if kernel_free {
+                    dispatch_compute(state, state_ref, kernel_ctx.notebook_id, kernel_ctx.run_id)?;
+                }
+                return Ok(());
+            }
             if flag.is_final() {
                 run.queue_decrement();
             }
+            let timing = run.update_cell_timing(cell_id, flag, finished.and_then(kernel_time))?;
             let kernel_state = run.kernel_state_desc();
+            let change = run.add_output(cell_id, value, flag, &spool)?;
             notebook.send_message(ToClientMessage::Output {
                 notebook_id: kernel_ctx.notebook_id,
                 run_id: kernel_ctx.run_id,
-                cell_id: OutputCellId::new(cell_id),
-                value: &value,
+                cell_id,
+                value: &change.value,
+                replace_from: change.replace_from,
                 flag,
+                timing,
                 update: update.as_ref(),
                 kernel_state,
             });
@@ -201,7 +642,116 @@ pub(crate) fn process_kernel_message(
             if let Some(update) = update {
                 run.update_globals(update)
             }
-            run.add_output(OutputCellId::new(cell_id), value, flag);
+            if flag.is_final() {
+                dispatch_compute(state, state_ref, kernel_ctx.notebook_id, kernel_ctx.run_id)?;
+            }
+        }
+        FromKernelMessage::ComputeStarted { cell_id, time } => {
+            let run = state
+                .find_notebook_by_id_mut(kernel_ctx.notebook_id)?
+                .find_run_by_id_mut(kernel_ctx.run_id)?;
+            if run.kernel_id() == Some(kernel_ctx.kernel_id) {
+                let time = kernel_time(time).unwrap_or_else(Timestamp::now);
+                run.set_cell_started(OutputCellId::new(cell_id), time);
+            }
+        }
+        FromKernelMessage::Display {
+            cell_id,
+            value,
+            display_id,
+            update,
+        } => {
+            let cell_id = OutputCellId::new(cell_id);
+            let spool = state.output_spool(kernel_ctx.notebook_id)?;
+            let notebook = state.find_notebook_by_id_mut(kernel_ctx.notebook_id)?;
+            let value = OutputValue::MimeBundle { value };
+            let run = notebook.find_run_by_id_mut(kernel_ctx.run_id)?;
+            if run.kernel_id() != Some(kernel_ctx.kernel_id) {
+                return Ok(());
+            }
+            if update {
+                let Some(display_id) = display_id else {
+                    return Ok(());
+                };
+                for (cell_id, index, value) in run.update_display(&display_id, &value, &spool) {
+                    notebook.send_message(ToClientMessage::OutputReplaced {
+                        notebook_id: kernel_ctx.notebook_id,
+                        run_id: kernel_ctx.run_id,
+                        cell_id,
+                        index,
+                        value: &value,
+                    });
+                }
+                return Ok(());
+            }
+            if run
+                .find_output_cell(cell_id)
+                .is_none_or(|c| c.flag().is_final())
+            {
+                tracing::debug!("Ignoring display of finished cell {cell_id}");
+                return Ok(());
+            }
+            let timing = run.update_cell_timing(cell_id, OutputFlag::Running, None)?;
+            let kernel_state = run.kernel_state_desc();
+            let value = run.add_display(cell_id, value, display_id, &spool)?;
+            notebook.send_message(ToClientMessage::Output {
+                notebook_id: kernel_ctx.notebook_id,
+                run_id: kernel_ctx.run_id,
+                cell_id,
+                value: &value,
+                replace_from: None,
+                flag: OutputFlag::Running,
+                timing,
+                update: None,
+                kernel_state,
+            });
+        }
+        FromKernelMessage::InputRequest {
+            cell_id,
+            request_id,
+            prompt,
+            password,
+        } => {
+            let notebook = state.find_notebook_by_id_mut(kernel_ctx.notebook_id)?;
+            let run = notebook.find_run_by_id_mut(kernel_ctx.run_id)?;
+            if run.kernel_id() != Some(kernel_ctx.kernel_id) {
+                return Ok(());
+            }
+            let request = InputRequest {
+                cell_id: OutputCellId::new(cell_id),
+                request_id,
+                prompt,
+                password,
+            };
+            notebook.send_message(ToClientMessage::InputRequest {
+                notebook_id: kernel_ctx.notebook_id,
+                run_id: kernel_ctx.run_id,
+                request: &request,
+            });
+            let run = notebook.find_run_by_id_mut(kernel_ctx.run_id)?;
+            run.set_input_request(request);
+        }
+        FromKernelMessage::InspectResponse { request_id, result } => {
+            let Some((msg, sender)) = state
+                .get_kernel_by_id_mut(kernel_ctx.kernel_id)
+                .and_then(|kernel| kernel.take_inspect_request(request_id))
+            else {
+                return Ok(());
+            };
+            let (object, error) = match result {
+                Ok(object) => (Some(object), None),
+                Err(error) => (None, Some(error)),
+            };
+            let _ = sender.send(serialize_client_message(ToClientMessage::InspectResult {
+                notebook_id: msg.notebook_id,
+                run_id: msg.run_id,
+                scope: msg.scope,
+                variable: msg.variable,
+                path: msg.path,
+                offset: msg.offset,
+                object,
+                error,
+            })?);
         }
         FromKernelMessage::SaveStateResponse { path: _, result } => {
             if let Some(kernel) = state.get_kernel_by_id_mut(kernel_ctx.kernel_id) {
@@ -243,10 +793,10 @@ fn save_helper(
             if let Some(notebook) = state.get_notebook_by_id(notebook_id) {
                 notebook.send_message(ToClientMessage::SaveCompleted { notebook_id, error });
             }
-        } else if let Ok(message) = query_helper(&mut state) {
-            if let Some(notebook) = state.get_notebook_by_id(notebook_id) {
-                notebook.send_raw_message(message)
-            }
+        } else if let Ok(message) = query_helper(&mut state)
+            && let Some(notebook) = state.get_notebook_by_id(notebook_id)
+        {
+            notebook.send_raw_message(message)
         }
     });
     Ok(())
@@ -345,14 +895,61 @@ pub(crate) fn query_dir(
     Ok(())
 }
 
+pub(crate) fn kernel_log(
+    state: &mut AppState,
+    kernel_id: KernelId,
+    sender: &UnboundedSender<Message>,
+) -> anyhow::Result<()> {
+    let (stdout_path, stderr_path) = kernel_log_paths(state.runtime_dir(), kernel_id);
+    let sender = sender.clone();
+    spawn(async move {
+        let message = match (
+            read_log_tail(&stdout_path).await,
+            read_log_tail(&stderr_path).await,
+        ) {
+            (Ok(stdout), Ok(stderr)) => serialize_client_message(ToClientMessage::KernelLogTail {
+                kernel_id,
+                stdout,
+                stderr,
+            }),
+            (Err(e), _) | (_, Err(e)) => serialize_client_message(ToClientMessage::Error {
+                message: &format!("Failed to read kernel log: {e}"),
+            }),
+        };
+        if let Ok(message) = message {
+            let _ = sender.send(message);
+        }
+    });
+    Ok(())
+}
+
+pub(crate) fn interrupt_run(
+    state: &mut AppState,
+    notebook_id: NotebookId,
+    run_id: RunId,
+) -> anyhow::Result<()> {
+    tracing::debug!("Interrupting run {}", run_id);
+    let notebook = state.find_notebook_by_id_mut(notebook_id)?;
+    let run = notebook.find_run_by_id_mut(run_id)?;
+    if let Some(kernel) = run
+        .kernel_id()
+        .and_then(|kernel_id| state.get_kernel_by_id_mut(kernel_id))
+    {
+        kernel.send_message(ToKernelMessage::Interrupt { cell_id: None });
+    }
+    Ok(())
+}
+
 pub(crate) fn close_run(
     state: &mut AppState,
     notebook_id: NotebookId,
     run_id: RunId,
 ) -> anyhow::Result<()> {
     tracing::debug!("Closing run {}", run_id);
+    let spool = state.output_spool(notebook_id)?;
     let notebook = state.find_notebook_by_id_mut(notebook_id)?;
     let run = notebook.remove_run_by_id(run_id)?;
+    spool.remove(run.spool_files());
     match run.kernel_state() {
         KernelState::Init(kernel_id) | KernelState::Running(kernel_id) => {
             let kernel_id = *kernel_id;
@@ -362,3 +959,114 @@ pub(crate) fn close_run(
     }
     Ok(())
 }
+
+/// Gracefully stops a kernel, its run is kept with closed kernel
+pub(crate) fn stop_kernel(
+    state: &mut AppState,
+    state_ref: &AppStateRef,
+    kernel_id: KernelId,
+) -> anyhow::Result<()> {
+    tracing::debug!("Stopping kernel {}", kernel_id);
+    let kernel = state.find_kernel_by_id_mut(kernel_id)?;
+    if kernel.is_stopping() {
+        // Only killing makes sense for a kernel that is already stopping
+        return Ok(());
+    }
+    let kernel_ctx = kernel.kernel_ctx().copied();
+    let done = kernel.shutdown();
+    if let Some(kernel_ctx) = kernel_ctx {
+        close_run_kernel(state, &kernel_ctx);
+        send_kernels_update(state, kernel_ctx.notebook_id);
+    }
+    // Handle is kept until the process is gone, so the kernel can be still killed
+    let state_ref = state_ref.clone();
+    spawn(async move {
+        let _ = done.await;
+        let mut state = state_ref.lock().unwrap();
+        state.remove_kernel(kernel_id);
+        if let Some(kernel_ctx) = kernel_ctx {
+            send_kernels_update(&state, kernel_ctx.notebook_id);
+        }
+    });
+    Ok(())
+}
+
+/// Immediately kills a kernel, its run is kept with closed kernel
+pub(crate) fn kill_kernel(state: &mut AppState, kernel_id: KernelId) -> anyhow::Result<()> {
+    tracing::debug!("Killing kernel {}", kernel_id);
+    let kernel_ctx = state
+        .find_kernel_by_id_mut(kernel_id)?
+        .kernel_ctx()
+        .copied();
+    state.stop_kernel(kernel_id);
+    if let Some(kernel_ctx) = kernel_ctx {
+        close_run_kernel(state, &kernel_ctx);
+    }
+    Ok(())
+}
+
+fn close_run_kernel(state: &mut AppState, kernel_ctx: &KernelCtx) {
+    let KernelCtx {
+        kernel_id,
+        notebook_id,
+        run_id,
+    } = *kernel_ctx;
+    let Ok(notebook) = state.find_notebook_by_id_mut(notebook_id) else {
+        return;
+    };
+    let Ok(run) = notebook.find_run_by_id_mut(run_id) else {
+        return;
+    };
+    if run.kernel_id() != Some(kernel_id) {
+        // Run was already closed, restarted or removed
+        return;
+    }
+    run.close_kernel();
+    notebook.send_message(ToClientMessage::KernelClosed {
+        notebook_id,
+        run_id,
+    });
+}
+
+pub(crate) async fn shutdown_server(state_ref: &AppStateRef) {
+    let kernels = {
+        let mut state = state_ref.lock().unwrap();
+        state.set_shutting_down();
+        for notebook in state.notebooks() {
+            notebook.send_message(ToClientMessage::ServerShutdown);
+        }
+        if state.detached_kernels() {
+            tracing::info!("Kernels are detached, they are left running");
+            Vec::new()
+        } else {
+            state.take_kernels()
+        }
+    };
+    tracing::debug!("Stopping {} kernel(s)", kernels.len());
+    join_all(kernels.into_iter().map(|mut kernel| kernel.shutdown())).await;
+
+    let notebooks: Vec<_> = {
+        let mut state = state_ref.lock().unwrap();
+        for notebook in state.notebooks_mut() {
+            for run in notebook.runs_mut() {
+                run.close_kernel();
+            }
+        }
+        state
+            .notebooks()
+            .filter_map(|notebook| match serialize_notebook(notebook) {
+                Ok(serialized) => Some((PathBuf::from(&notebook.path), serialized)),
+                Err(e) => {
+                    tracing::error!("Serializing notebook {} failed: {e}", notebook.path);
+                    None
+                }
+            })
+            .collect()
+    };
+    for (path, serialized_notebook) in notebooks {
+        tracing::debug!("Saving notebook as {}", path.display());
+        if let Err(e) = serialized_notebook.save(&path).await {
+            tracing::error!("Saving notebook as {} failed: {e}", path.display());
+        }
+    }
+}
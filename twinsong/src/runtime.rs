@@ -0,0 +1,57 @@
+use crate::notebook::{KernelId, RunId};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+/// File in the runtime directory that describes kernels of the running server
+const RUNTIME_STATE_FILE: &str = "runtime.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct KernelBinding {
+    /// Path of the notebook as it was loaded by the server
+    pub notebook: String,
+    pub run_id: RunId,
+    /// Used when the run was not saved before the server ended
+    pub run_title: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct KernelRecord {
+    pub kernel_id: KernelId,
+    pub pid: u32,
+    pub secret: String,
+    /// None for idle kernels in the pool
+    #[serde(default)]
+    pub binding: Option<KernelBinding>,
+}
+
+/// State that is needed for re-attaching detached kernels after a restart of the server
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RuntimeState {
+    pub kernel_port: u16,
+    pub kernels: Vec<KernelRecord>,
+}
+
+impl RuntimeState {
+    pub fn load(runtime_dir: &Path) -> anyhow::Result<Option<RuntimeState>> {
+        let data = match std::fs::read_to_string(runtime_dir.join(RUNTIME_STATE_FILE)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(serde_json::from_str(&data)?))
+    }
+
+    /// Replaces the state file, it contains secrets of kernels so it is readable only by owner
+    pub fn save(&self, runtime_dir: &Path) -> anyhow::Result<()> {
+        let tmp_path = runtime_dir.join(format!("{RUNTIME_STATE_FILE}.tmp"));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(serde_json::to_string(self)?.as_bytes())?;
+        std::fs::rename(&tmp_path, runtime_dir.join(RUNTIME_STATE_FILE))?;
+        Ok(())
+    }
+}
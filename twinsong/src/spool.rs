@@ -0,0 +1,288 @@
+use crate::utils::terminal::StyleSpan;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// Values that have to be shown at least partially are not shortened below this size
+/// even when the output limit of the cell is exhausted
+const MIN_OUTPUT_LIMIT: usize = 1024;
+
+/// The biggest page of an omitted text that is sent to a client
+pub(crate) const MAX_PAGE_SIZE: usize = 1024 * 1024;
+
+/// Size limits of outputs in bytes
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct OutputLimits {
+    /// Limit of a single output value
+    pub output: usize,
+    /// Limit of all values of a cell
+    pub cell: usize,
+}
+
+impl OutputLimits {
+    /// Limit of a value when other values of the cell take `other_size` bytes
+    pub fn value_limit(&self, other_size: usize) -> usize {
+        self.output
+            .min(self.cell.saturating_sub(other_size).max(MIN_OUTPUT_LIMIT))
+    }
+}
+
+/// Text that was removed from the middle of an output value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Truncation {
+    /// Position (in characters) in the value where the omitted text belongs
+    pub offset: usize,
+    /// Size of the omitted text in bytes
+    pub omitted: usize,
+    /// File with the omitted text in the spool directory of the notebook
+    pub spool: String,
+}
+
+/// Part of a text that was moved into the spool, offsets are positions of its start
+pub(crate) struct TextRemoval {
+    pub byte_offset: usize,
+    pub char_offset: usize,
+    pub bytes: usize,
+    pub chars: usize,
+}
+
+type PageResult = anyhow::Result<(String, usize)>;
+
+enum SpoolCommand {
+    Append {
+        dir: PathBuf,
+        filename: String,
+        text: String,
+    },
+    Read {
+        dir: PathBuf,
+        truncation: Truncation,
+        offset: usize,
+        size: usize,
+        reply: oneshot::Sender<PageResult>,
+    },
+    Remove {
+        dir: PathBuf,
+        filenames: Vec<String>,
+    },
+}
+
+/// Performs file operations of spools in a dedicated thread, so the disk is not accessed
+/// while the state is locked. Operations are done in the order of requests,
+/// so a page is always read after the text was written.
+#[derive(Clone)]
+pub(crate) struct SpoolWriter {
+    sender: UnboundedSender<SpoolCommand>,
+}
+
+impl SpoolWriter {
+    pub fn start() -> Self {
+        let (sender, mut receiver) = unbounded_channel();
+        std::thread::spawn(move || {
+            while let Some(command) = receiver.blocking_recv() {
+                process_spool_command(command);
+            }
+        });
+        SpoolWriter { sender }
+    }
+
+    fn send(&self, command: SpoolCommand) {
+        if self.sender.send(command).is_err() {
+            tracing::error!("Spool writer has ended");
+        }
+    }
+
+    /// Reads a page of an omitted text, see `read_spool_page`
+    pub fn read_page(
+        &self,
+        notebook_path: &str,
+        truncation: Truncation,
+        offset: usize,
+        size: usize,
+    ) -> oneshot::Receiver<PageResult> {
+        let (reply, receiver) = oneshot::channel();
+        self.send(SpoolCommand::Read {
+            dir: spool_dir(notebook_path),
+            truncation,
+            offset,
+            size,
+            reply,
+        });
+        receiver
+    }
+}
+
+fn process_spool_command(command: SpoolCommand) {
+    match command {
+        SpoolCommand::Append {
+            dir,
+            filename,
+            text,
+        } => {
+            if let Err(e) = append_text(&dir, &filename, &text) {
+                tracing::error!("Cannot write omitted output into spool: {e}");
+            }
+        }
+        SpoolCommand::Read {
+            dir,
+            truncation,
+            offset,
+            size,
+            reply,
+        } => {
+            let _ = reply.send(read_spool_page(&dir, &truncation, offset, size));
+        }
+        SpoolCommand::Remove { dir, filenames } => {
+            for filename in filenames {
+                if let Err(e) = std::fs::remove_file(dir.join(&filename)) {
+                    tracing::debug!("Cannot remove spool file {filename}: {e}");
+                }
+            }
+            // Fails when other outputs are still spooled
+            let _ = std::fs::remove_dir(&dir);
+        }
+    }
+}
+
+fn append_text(dir: &Path, filename: &str, text: &str) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(filename))?;
+    file.write_all(text.as_bytes())
+}
+
+/// Directory next to the notebook where texts omitted from outputs are stored
+pub(crate) struct OutputSpool {
+    pub limits: OutputLimits,
+    dir: PathBuf,
+    writer: SpoolWriter,
+}
+
+impl OutputSpool {
+    pub fn new(limits: OutputLimits, notebook_path: &str, writer: SpoolWriter) -> Self {
+        OutputSpool {
+            limits,
+            dir: spool_dir(notebook_path),
+            writer,
+        }
+    }
+
+    /// Removes omitted texts of outputs that were dropped,
+    /// the spool directory is removed when it becomes empty
+    pub fn remove(&self, filenames: Vec<String>) {
+        if !filenames.is_empty() {
+            self.writer.send(SpoolCommand::Remove {
+                dir: self.dir.clone(),
+                filenames,
+            });
+        }
+    }
+
+    fn append(&self, filename: &str, text: &str) {
+        self.writer.send(SpoolCommand::Append {
+            dir: self.dir.clone(),
+            filename: filename.to_string(),
+            text: text.to_string(),
+        });
+    }
+
+    /// Shortens `text` that is longer than `limit` bytes by moving its middle part
+    /// into the spool; the first half of the limit and `tail_size` bytes at the end are kept.
+    pub fn truncate_text(
+        &self,
+        text: &mut String,
+        spans: &mut Vec<StyleSpan>,
+        truncated: &mut Option<Truncation>,
+        limit: usize,
+        tail_size: usize,
+    ) -> Option<TextRemoval> {
+        if text.len() <= limit {
+            return None;
+        }
+        let head_end = match truncated {
+            Some(t) => text
+                .char_indices()
+                .nth(t.offset)
+                .map(|(i, _)| i)
+                .unwrap_or(text.len()),
+            None => text.floor_char_boundary(limit / 2),
+        };
+        let tail_start = text.ceil_char_boundary(text.len().saturating_sub(tail_size));
+        if tail_start <= head_end {
+            return None;
+        }
+        let truncation = truncated.get_or_insert_with(|| Truncation {
+            offset: text[..head_end].chars().count(),
+            omitted: 0,
+            spool: format!("{}.txt", Uuid::new_v4()),
+        });
+        let omitted = &text[head_end..tail_start];
+        self.append(&truncation.spool, omitted);
+        let removed_bytes = omitted.len();
+        let removed_chars = omitted.chars().count();
+        truncation.omitted += removed_bytes;
+        let start = truncation.offset;
+        let end = start + removed_chars;
+        let shift = |x: usize| {
+            if x <= start {
+                x
+            } else if x <= end {
+                start
+            } else {
+                x - removed_chars
+            }
+        };
+        for span in spans.iter_mut() {
+            span.start = shift(span.start);
+            span.end = shift(span.end);
+        }
+        spans.retain(|span| span.start < span.end);
+        text.replace_range(head_end..tail_start, "");
+        Some(TextRemoval {
+            byte_offset: head_end,
+            char_offset: start,
+            bytes: removed_bytes,
+            chars: removed_chars,
+        })
+    }
+}
+
+pub(crate) fn spool_dir(notebook_path: &str) -> PathBuf {
+    PathBuf::from(format!("{notebook_path}.spool"))
+}
+
+/// Reads a page of an omitted text, the page is aligned to whole characters.
+/// Returns the text and the offset of the next page.
+fn read_spool_page(
+    dir: &Path,
+    truncation: &Truncation,
+    offset: usize,
+    size: usize,
+) -> anyhow::Result<(String, usize)> {
+    let mut file = std::fs::File::open(dir.join(&truncation.spool))?;
+    let size = size
+        .clamp(4, MAX_PAGE_SIZE)
+        .min(truncation.omitted.saturating_sub(offset));
+    file.seek(SeekFrom::Start(offset as u64))?;
+    let mut data = vec![0; size];
+    file.read_exact(&mut data)?;
+    // A page may start or end inside of a multibyte character
+    let start = data
+        .iter()
+        .position(|b| (b & 0xC0) != 0x80)
+        .unwrap_or(data.len());
+    let mut end = data.len();
+    let text = loop {
+        match std::str::from_utf8(&data[start..end]) {
+            Ok(text) => break text.to_string(),
+            Err(e) if e.error_len().is_none() => end = start + e.valid_up_to(),
+            Err(e) => return Err(e.into()),
+        }
+    };
+    Ok((text, offset + end))
+}
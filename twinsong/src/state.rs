@@ -1,19 +1,48 @@
 use crate::client_messages::KernelInfo;
 use crate::kernel::KernelHandle;
 use crate::notebook::{KernelId, Notebook, NotebookId};
+use crate::runtime::{KernelBinding, KernelRecord, RuntimeState};
+use crate::spool::{OutputLimits, OutputSpool, SpoolWriter};
 use anyhow::anyhow;
 use rand::Rng;
 use rand::distr::Alphanumeric;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+pub(crate) enum RuntimeDir {
+    /// Removed when the server ends
+    Temporary(TempDir),
+    /// Kept for the next server, detached kernels still write their logs there
+    Persistent(PathBuf),
+}
+
+impl RuntimeDir {
+    pub fn path(&self) -> &Path {
+        match self {
+            RuntimeDir::Temporary(dir) => dir.path(),
+            RuntimeDir::Persistent(path) => path,
+        }
+    }
+}
 
 pub(crate) struct AppState {
     notebooks: HashMap<NotebookId, Notebook>,
     kernels: HashMap<KernelId, KernelHandle>,
+    /// Idle kernels that are not bound to any run yet
+    kernel_pool: VecDeque<KernelId>,
+    kernel_pool_size: usize,
+    /// Kernels are not stopped with the server and a restarted server re-attaches them
+    detached_kernels: bool,
+    output_limits: OutputLimits,
+    spool_writer: SpoolWriter,
     id_counter: u32,
     kernel_port: u16,
     http_port: u16,
     secret_key: String,
+    shutting_down: bool,
+    runtime_dir: Option<RuntimeDir>,
 }
 
 pub(crate) type AppStateRef = Arc<Mutex<AppState>>;
@@ -27,25 +56,70 @@ pub fn generate_key() -> String {
 }
 
 impl AppState {
-    pub fn new(http_port: u16, secret_key: Option<String>) -> Self {
+    pub fn new(
+        http_port: u16,
+        secret_key: Option<String>,
+        kernel_port: u16,
+        kernel_pool_size: usize,
+        detached_kernels: bool,
+        output_limits: OutputLimits,
+    ) -> Self {
         AppState {
             notebooks: HashMap::new(),
             kernels: HashMap::new(),
+            kernel_pool: VecDeque::new(),
+            kernel_pool_size,
+            detached_kernels,
+            output_limits,
+            spool_writer: SpoolWriter::start(),
             id_counter: 0,
-            kernel_port: 0,
+            kernel_port,
             http_port,
             secret_key: secret_key.unwrap_or_else(generate_key),
+            shutting_down: false,
+            runtime_dir: None,
         }
     }
 
+    pub(crate) fn spool_writer(&self) -> &SpoolWriter {
+        &self.spool_writer
+    }
+
+    /// Spool where omitted texts of outputs of the notebook are written
+    pub(crate) fn output_spool(&self, notebook_id: NotebookId) -> anyhow::Result<OutputSpool> {
+        let notebook = self
+            .get_notebook_by_id(notebook_id)
+            .ok_or(anyhow!("Notebook not found"))?;
+        Ok(OutputSpool::new(
+            self.output_limits,
+            &notebook.path,
+            self.spool_writer.clone(),
+        ))
+    }
+
     pub(crate) fn secret_key(&self) -> &str {
         &self.secret_key
     }
 
     pub(crate) fn kernel_list(&self) -> Vec<KernelInfo> {
+        self.kernels
+            .values()
+            .filter_map(|kernel_handle| {
+                let kernel_ctx = kernel_handle.kernel_ctx()?;
+                let run = self
+                    .notebooks
+                    .get(&kernel_ctx.notebook_id)?
+                    .find_run_by_id(kernel_ctx.run_id)
+                    .ok()?;
+                kernel_handle.kernel_info(run)
+            })
+            .collect()
+    }
+
+    pub(crate) fn kernel_pids(&self) -> Vec<(KernelId, u32)> {
         self.kernels
             .iter()
-            .map(|(kernel_id, kernel_handle)| kernel_handle.kernel_info(*kernel_id))
+            .map(|(kernel_id, kernel_handle)| (*kernel_id, kernel_handle.pid()))
             .collect()
     }
 
@@ -74,13 +148,118 @@ impl AppState {
 
     pub fn add_kernel(&mut self, kernel_id: KernelId, kernel: KernelHandle) {
         assert!(self.kernels.insert(kernel_id, kernel).is_none());
+        self.save_runtime_state();
+    }
+
+    pub fn add_pooled_kernel(&mut self, kernel_id: KernelId, kernel: KernelHandle) {
+        self.kernels.insert(kernel_id, kernel);
+        self.kernel_pool.push_back(kernel_id);
+        self.save_runtime_state();
+    }
+
+    /// Removes an idle kernel from the pool, its handle is kept in kernels
+    pub fn take_pooled_kernel(&mut self) -> Option<KernelId> {
+        self.kernel_pool.pop_front()
+    }
+
+    pub fn kernel_pool_len(&self) -> usize {
+        self.kernel_pool.len()
+    }
+
+    pub fn kernel_pool_size(&self) -> usize {
+        self.kernel_pool_size
+    }
+
+    /// Pids of idle kernels in the pool that are already logged in
+    pub fn kernel_pool_ready(&self) -> Vec<u32> {
+        self.kernel_pool
+            .iter()
+            .filter_map(|kernel_id| self.kernels.get(kernel_id))
+            .filter(|kernel| !kernel.is_init())
+            .map(|kernel| kernel.pid())
+            .collect()
     }
 
     pub fn stop_kernel(&mut self, kernel_id: KernelId) {
+        self.kernel_pool.retain(|id| *id != kernel_id);
         if let Some(kernel_handle) = self.kernels.remove(&kernel_id) {
             tracing::debug!("Stopping kernel {}", kernel_id);
             kernel_handle.stop();
         }
+        self.save_runtime_state();
+    }
+
+    pub fn remove_kernel(&mut self, kernel_id: KernelId) {
+        self.kernel_pool.retain(|id| *id != kernel_id);
+        self.kernels.remove(&kernel_id);
+        self.save_runtime_state();
+    }
+
+    pub fn detached_kernels(&self) -> bool {
+        self.detached_kernels
+    }
+
+    fn runtime_state(&self) -> RuntimeState {
+        let kernels = self
+            .kernels
+            .iter()
+            .filter_map(|(kernel_id, kernel_handle)| {
+                let binding = if let Some(kernel_ctx) = kernel_handle.kernel_ctx() {
+                    // Kernels of removed runs are being stopped, they are not recorded
+                    let notebook = self.notebooks.get(&kernel_ctx.notebook_id)?;
+                    let run = notebook.find_run_by_id(kernel_ctx.run_id).ok()?;
+                    Some(KernelBinding {
+                        notebook: notebook.path.clone(),
+                        run_id: kernel_ctx.run_id,
+                        run_title: run.title().to_string(),
+                    })
+                } else {
+                    None
+                };
+                Some(KernelRecord {
+                    kernel_id: *kernel_id,
+                    pid: kernel_handle.pid(),
+                    secret: kernel_handle.secret().to_string(),
+                    binding,
+                })
+            })
+            .collect();
+        RuntimeState {
+            kernel_port: self.kernel_port,
+            kernels,
+        }
+    }
+
+    /// Records kernels into the runtime directory, so they survive a restart of the server.
+    /// It has to be called whenever a kernel is added, bound to a run or removed.
+    pub fn save_runtime_state(&self) {
+        if !self.detached_kernels {
+            return;
+        }
+        if let Err(e) = self.runtime_state().save(self.runtime_dir()) {
+            tracing::error!("Saving runtime state failed: {e}");
+        }
+    }
+
+    pub fn take_kernels(&mut self) -> Vec<KernelHandle> {
+        self.kernel_pool.clear();
+        self.kernels.drain().map(|(_, kernel)| kernel).collect()
+    }
+
+    pub fn notebooks(&self) -> impl Iterator<Item = &Notebook> {
+        self.notebooks.values()
+    }
+
+    pub fn notebooks_mut(&mut self) -> impl Iterator<Item = &mut Notebook> {
+        self.notebooks.values_mut()
+    }
+
+    pub fn set_shutting_down(&mut self) {
+        self.shutting_down = true;
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down
     }
 
     pub fn set_kernel_port(&mut self, kernel_port: u16) {
@@ -91,6 +270,18 @@ impl AppState {
         self.kernel_port
     }
 
+    pub fn set_runtime_dir(&mut self, runtime_dir: RuntimeDir) {
+        self.runtime_dir = Some(runtime_dir);
+    }
+
+    /// Directory for files of the running server (e.g. kernel logs)
+    pub fn runtime_dir(&self) -> &Path {
+        self.runtime_dir
+            .as_ref()
+            .expect("Runtime dir is not initialized")
+            .path()
+    }
+
     pub fn http_port(&self) -> u16 {
         self.http_port
     }
@@ -99,6 +290,14 @@ impl AppState {
         self.kernels.get_mut(&id).ok_or(anyhow!("Kernel not found"))
     }
 
+    pub fn kernels_mut(&mut self) -> impl Iterator<Item = (&KernelId, &mut KernelHandle)> {
+        self.kernels.iter_mut()
+    }
+
+    pub fn get_kernel_by_id(&self, id: KernelId) -> Option<&KernelHandle> {
+        self.kernels.get(&id)
+    }
+
     pub fn get_kernel_by_id_mut(&mut self, id: KernelId) -> Option<&mut KernelHandle> {
         self.kernels.get_mut(&id)
     }
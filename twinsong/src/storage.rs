@@ -1,5 +1,8 @@
-use crate::notebook::{EditorGroup, KernelState, Notebook, OutputCell, Run, RunId};
+use crate::notebook::{
+    EditorGroup, KernelCrash, KernelRestart, KernelState, Notebook, OutputCell, Run, RunId,
+};
 use anyhow::bail;
+use comm::messages::JObjectLimits;
 use comm::scopes::SerializedGlobals;
 use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
@@ -13,7 +16,7 @@ const VERSION_STRING: &str = "twinsong 0.0.1";
 #[serde(tag = "type")]
 enum KernelStateStore {
     Closed,
-    Crashed { message: String },
+    Crashed(KernelCrash),
 }
 
 #[derive(Debug, Serialize)]
@@ -23,12 +26,17 @@ struct RunStore<'a> {
     created: Timestamp,
     kernel_state: KernelStateStore,
     output_cells: &'a [OutputCell],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    kernel_restarts: &'a [KernelRestart],
     globals: &'a SerializedGlobals,
+    jobject_limits: &'a JObjectLimits,
 }
 
 #[derive(Debug, Serialize)]
 struct NotebookStore<'a> {
     version: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default_timeout: Option<u64>,
     editor_root: &'a EditorGroup,
 }
 
@@ -40,13 +48,21 @@ struct RunLoad {
     output_cells: Vec<OutputCell>,
     kernel_state: KernelStateStore,
 
+    #[serde(default)]
+    kernel_restarts: Vec<KernelRestart>,
+
     #[serde(default)]
     globals: SerializedGlobals,
+
+    #[serde(default)]
+    jobject_limits: JObjectLimits,
 }
 
 #[derive(Debug, Deserialize)]
 struct NotebookLoad {
     version: String,
+    #[serde(default)]
+    default_timeout: Option<u64>,
     editor_root: EditorGroup,
 }
 
@@ -131,11 +147,13 @@ pub(crate) fn serialize_notebook(notebook: &Notebook) -> anyhow::Result<Serializ
                 id: run_id,
                 created: run.created(),
                 kernel_state: match run.kernel_state() {
-                    KernelState::Crashed(s) => KernelStateStore::Crashed { message: s.clone() },
+                    KernelState::Crashed(crash) => KernelStateStore::Crashed(crash.clone()),
                     _ => KernelStateStore::Closed,
                 },
                 output_cells: run.output_cells(),
+                kernel_restarts: run.kernel_restarts(),
                 globals: run.globals(),
+                jobject_limits: run.jobject_limits(),
             };
             let data = toml::to_string(&store)?;
             Ok((create_run_filename(run.title(), run_id), data))
@@ -143,6 +161,7 @@ pub(crate) fn serialize_notebook(notebook: &Notebook) -> anyhow::Result<Serializ
         .collect::<anyhow::Result<_>>()?;
     let s_notebook = NotebookStore {
         version: VERSION_STRING,
+        default_timeout: notebook.default_timeout,
         editor_root: &notebook.editor_root,
     };
     Ok(SerializedNotebook {
@@ -161,19 +180,19 @@ pub(crate) fn deserialize_notebook(
     let mut runs: HashMap<RunId, Run> = HashMap::new();
     for (_, run_data) in &serialized_notebook.runs {
         let run_load: RunLoad = toml::from_str(run_data)?;
-        runs.insert(
-            run_load.id,
-            Run::new(
-                run_load.title,
-                run_load.output_cells,
-                match run_load.kernel_state {
-                    KernelStateStore::Closed => KernelState::Closed,
-                    KernelStateStore::Crashed { message } => KernelState::Crashed(message),
-                },
-                run_load.globals,
-                run_load.created,
-            ),
+        let mut run = Run::new(
+            run_load.title,
+            run_load.output_cells,
+            run_load.kernel_restarts,
+            match run_load.kernel_state {
+                KernelStateStore::Closed => KernelState::Closed,
+                KernelStateStore::Crashed(crash) => KernelState::Crashed(crash),
+            },
+            run_load.globals,
+            run_load.created,
         );
+        run.set_jobject_limits(run_load.jobject_limits);
+        runs.insert(run_load.id, run);
     }
     let mut run_order: Vec<_> = runs.keys().copied().collect();
     run_order.sort_unstable_by_key(|id| runs.get(id).unwrap().created());
@@ -182,6 +201,7 @@ pub(crate) fn deserialize_notebook(
         editor_root: store.editor_root,
         editor_open_nodes: vec![root_id],
         path: String::new(),
+        default_timeout: store.default_timeout,
         runs,
         run_order,
         observer: None,
@@ -0,0 +1,173 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct ProcessUsage {
+    /// Resident set size in bytes
+    pub rss: u64,
+    /// User and system CPU time in seconds
+    pub cpu_time: f64,
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn process_usage(pid: u32) -> Option<ProcessUsage> {
+    // Times in /proc/<pid>/stat are in clock ticks; USER_HZ is 100 on all Linux platforms
+    const USER_HZ: f64 = 100.0;
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // Process name may contain spaces, so fields are counted from the end of the name;
+    // utime and stime are 14th and 15th field of the line
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    let rss_kb: u64 = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(ProcessUsage {
+        rss: rss_kb * 1024,
+        cpu_time: (utime + stime) as f64 / USER_HZ,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn process_usage(_pid: u32) -> Option<ProcessUsage> {
+    None
+}
+
+/// Number of processes killed by the OOM killer in the memory cgroup of a process,
+/// it is read when the process starts, so its later increase can be detected
+#[derive(Debug)]
+pub(crate) struct OomCounter {
+    path: PathBuf,
+    kills: u64,
+}
+
+impl OomCounter {
+    /// Returns None when the cgroup of the process or its counter is not available
+    #[cfg(target_os = "linux")]
+    pub fn new(pid: u32) -> Option<Self> {
+        let cgroups = std::fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+        // Line "0::<path>" is the unified hierarchy (cgroup v2),
+        // "<id>:...memory...:<path>" is the memory controller of cgroup v1
+        cgroups
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, ':');
+                let (id, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+                let path = path.trim_start_matches('/');
+                if id == "0" && controllers.is_empty() {
+                    Some(
+                        PathBuf::from("/sys/fs/cgroup")
+                            .join(path)
+                            .join("memory.events"),
+                    )
+                } else if controllers.split(',').any(|c| c == "memory") {
+                    Some(
+                        PathBuf::from("/sys/fs/cgroup/memory")
+                            .join(path)
+                            .join("memory.oom_control"),
+                    )
+                } else {
+                    None
+                }
+            })
+            .find_map(|path| {
+                let kills = read_oom_kills(&path)?;
+                Some(OomCounter { path, kills })
+            })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(_pid: u32) -> Option<Self> {
+        None
+    }
+
+    /// Checks if the OOM killer killed a process in the cgroup since the counter was created
+    pub fn increased(&self) -> bool {
+        read_oom_kills(&self.path).is_some_and(|kills| kills > self.kills)
+    }
+}
+
+/// Reads "oom_kill" from memory.events (cgroup v2) or memory.oom_control (cgroup v1)
+fn read_oom_kills(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path)
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+pub(crate) const SIGKILL: i32 = 9;
+
+/// Name of a signal, numbers that differ between platforms are not named
+pub(crate) fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        8 => "SIGFPE",
+        SIGKILL => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        _ => return format!("signal {signal}"),
+    };
+    name.to_string()
+}
+
+/// Checks if a process exists, it is used for kernels that are not children of this server
+#[cfg(unix)]
+pub(crate) fn process_exists(pid: u32) -> bool {
+    // SAFETY: Signal 0 only checks that the process exists and may be signalled
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn process_exists(_pid: u32) -> bool {
+    false
+}
+
+/// Checks that the process is the kernel with the given id by its initial environment,
+/// so a reused pid of a finished kernel is not confused with it.
+/// Returns None when it cannot be checked on the platform.
+#[cfg(target_os = "linux")]
+pub(crate) fn is_kernel_process(pid: u32, kernel_id: &str) -> Option<bool> {
+    let Ok(environ) = std::fs::read(format!("/proc/{pid}/environ")) else {
+        // The process is gone or it belongs to another user
+        return Some(false);
+    };
+    let entry = format!("KERNEL_ID={kernel_id}");
+    Some(
+        environ
+            .split(|c| *c == 0)
+            .any(|item| item == entry.as_bytes()),
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn is_kernel_process(_pid: u32, _kernel_id: &str) -> Option<bool> {
+    None
+}
+
+/// Kills a process that is not a child of this server
+#[cfg(unix)]
+pub(crate) fn kill_process(pid: u32) {
+    // SAFETY: Sending a signal has no memory safety requirements
+    unsafe {
+        libc::kill(pid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn kill_process(_pid: u32) {}
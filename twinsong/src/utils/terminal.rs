@@ -0,0 +1,457 @@
+use crate::spool::TextRemoval;
+use serde::{Deserialize, Serialize};
+
+/// Longest escape sequence that is buffered, longer ones are dropped
+const MAX_ESCAPE_LEN: usize = 512;
+
+/// How far behind the end of the line the cursor may be moved,
+/// so a cursor movement cannot pad the line by an arbitrary number of spaces
+const MAX_CURSOR_GAP: usize = 256;
+
+/// Colour set by an ANSI escape sequence, an index into the 256 colour palette or RGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Color {
+    Indexed(u8),
+    Rgb([u8; 3]),
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextStyle {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fg: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bg: Option<Color>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub bold: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub dim: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub italic: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub underline: bool,
+}
+
+impl TextStyle {
+    /// Applies parameters of SGR sequence (`ESC [ ... m`)
+    fn apply_sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = TextStyle::default();
+            return;
+        }
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = TextStyle::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.fg = Some(Color::Indexed((param - 30) as u8)),
+                38 => self.fg = parse_extended_color(&mut params),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(Color::Indexed((param - 40) as u8)),
+                48 => self.bg = parse_extended_color(&mut params),
+                49 => self.bg = None,
+                90..=97 => self.fg = Some(Color::Indexed((param - 90 + 8) as u8)),
+                100..=107 => self.bg = Some(Color::Indexed((param - 100 + 8) as u8)),
+                _ => { /* Unsupported attributes are ignored */ }
+            }
+        }
+    }
+}
+
+/// Parses `5;n` (palette) or `2;r;g;b` (RGB) after 38 or 48
+fn parse_extended_color(params: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let mut next = || params.next().map(|p| p.min(255) as u8);
+    match next()? {
+        5 => Some(Color::Indexed(next()?)),
+        2 => Some(Color::Rgb([next()?, next()?, next()?])),
+        _ => None,
+    }
+}
+
+/// Style of a part of a text, offsets are in characters
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StyleSpan {
+    pub start: usize,
+    pub end: usize,
+    #[serde(flatten)]
+    pub style: TextStyle,
+}
+
+/// Removes styles of the text after `offset`, only the spans at the end are visited
+pub(crate) fn truncate_spans(spans: &mut Vec<StyleSpan>, offset: usize) {
+    while spans.last().is_some_and(|span| span.start >= offset) {
+        spans.pop();
+    }
+    if let Some(last) = spans.last_mut() {
+        last.end = last.end.min(offset);
+    }
+}
+
+/// Position in the text from which it was changed by a write
+#[derive(Debug)]
+pub(crate) struct TextChange {
+    pub char_offset: usize,
+    pub byte_offset: usize,
+}
+
+/// Interprets terminal control characters in a text that is written in chunks.
+///
+/// Finished lines are only appended to the text, the last line may be rewritten by
+/// carriage return, backspace, cursor movement and "erase line" sequences
+/// (as progress bars do). Colours and attributes are kept as style spans.
+#[derive(Debug, Default)]
+pub(crate) struct Terminal {
+    /// Characters of the last line, that is not finished yet
+    line: Vec<(char, TextStyle)>,
+    cursor: usize,
+    style: TextStyle,
+    /// Unfinished escape sequence (without ESC) at the end of the previous chunk
+    escape: Option<String>,
+    /// Position of the last line in the text
+    line_start_byte: usize,
+    line_start_char: usize,
+    /// Number of characters of the last line that are rendered in the text
+    rendered: usize,
+    /// The first column of the last line that was changed since it was rendered
+    dirty: usize,
+    /// Position of the first change of the current write
+    change: Option<TextChange>,
+}
+
+impl Terminal {
+    /// Starts writing into a new empty text, the current style is kept
+    pub fn start_text(&mut self) {
+        self.line.clear();
+        self.cursor = 0;
+        self.line_start_byte = 0;
+        self.line_start_char = 0;
+        self.rendered = 0;
+        self.dirty = 0;
+    }
+
+    /// Updates the position of the last line after a part of the text was removed.
+    /// When the removed part reaches into the last line, the line is cut: the text
+    /// up to the end of the removed part is finished and only the rest may be rewritten.
+    pub fn text_removed(&mut self, removal: &TextRemoval) {
+        let removal_end = removal.char_offset + removal.chars;
+        if removal_end <= self.line_start_char {
+            self.line_start_byte -= removal.bytes;
+            self.line_start_char -= removal.chars;
+            return;
+        }
+        let columns = removal_end - self.line_start_char;
+        self.line.drain(..columns);
+        self.cursor = self.cursor.saturating_sub(columns);
+        self.rendered -= columns;
+        self.dirty = self.dirty.saturating_sub(columns);
+        self.line_start_byte = removal.byte_offset;
+        self.line_start_char = removal.char_offset;
+    }
+
+    /// Writes a chunk at the end of `text` that was produced by previous writes.
+    /// Only the part of the last line from the first changed column is rendered again,
+    /// so appending to a long line does not render it all the time.
+    pub fn write(
+        &mut self,
+        text: &mut String,
+        spans: &mut Vec<StyleSpan>,
+        chunk: &str,
+    ) -> TextChange {
+        self.change = None;
+        for c in chunk.chars() {
+            if let Some(escape) = &mut self.escape {
+                escape.push(c);
+                if escape_finished(escape) {
+                    let escape = self.escape.take().unwrap();
+                    self.apply_escape(&escape);
+                } else if escape.len() > MAX_ESCAPE_LEN {
+                    self.escape = None;
+                }
+                continue;
+            }
+            match c {
+                '\x1b' => self.escape = Some(String::new()),
+                '\r' => self.cursor = 0,
+                '\x08' => self.cursor = self.cursor.saturating_sub(1),
+                '\n' => self.finish_line(text, spans),
+                '\x07' => { /* Bell */ }
+                c => self.put_char(c),
+            }
+        }
+        self.render_line(text, spans);
+        self.change.take().unwrap()
+    }
+
+    /// Marks a change of the line from the column
+    fn touch(&mut self, column: usize) {
+        self.dirty = self.dirty.min(column);
+    }
+
+    /// Moves the cursor, at most `MAX_CURSOR_GAP` columns behind the end of the line
+    fn move_cursor(&mut self, column: usize) {
+        self.cursor = column.min(self.line.len() + MAX_CURSOR_GAP);
+    }
+
+    fn put_char(&mut self, c: char) {
+        self.move_cursor(self.cursor);
+        self.touch(self.cursor.min(self.line.len()));
+        while self.line.len() < self.cursor {
+            self.line.push((' ', TextStyle::default()));
+        }
+        if self.cursor < self.line.len() {
+            self.line[self.cursor] = (c, self.style);
+        } else {
+            self.line.push((c, self.style));
+        }
+        self.cursor += 1;
+    }
+
+    fn finish_line(&mut self, text: &mut String, spans: &mut Vec<StyleSpan>) {
+        self.render_line(text, spans);
+        text.push('\n');
+        self.line_start_byte = text.len();
+        self.line_start_char += self.line.len() + 1;
+        self.line.clear();
+        self.cursor = 0;
+        self.rendered = 0;
+        self.dirty = 0;
+    }
+
+    /// Replaces the rendered part of the last line from the first changed column
+    fn render_line(&mut self, text: &mut String, spans: &mut Vec<StyleSpan>) {
+        let from = self.dirty.min(self.rendered);
+        // Characters are removed from the end, so appending costs nothing
+        let mut byte_offset = text.len();
+        for _ in from..self.rendered {
+            byte_offset -= text[..byte_offset].chars().next_back().unwrap().len_utf8();
+        }
+        text.truncate(byte_offset);
+        truncate_spans(spans, self.line_start_char + from);
+        if self.change.is_none() {
+            self.change = Some(TextChange {
+                char_offset: self.line_start_char + from,
+                byte_offset,
+            });
+        }
+        for (i, (c, style)) in self.line.iter().enumerate().skip(from) {
+            text.push(*c);
+            if *style == TextStyle::default() {
+                continue;
+            }
+            let offset = self.line_start_char + i;
+            match spans.last_mut() {
+                Some(last) if last.end == offset && last.style == *style => last.end += 1,
+                _ => spans.push(StyleSpan {
+                    start: offset,
+                    end: offset + 1,
+                    style: *style,
+                }),
+            }
+        }
+        self.rendered = self.line.len();
+        self.dirty = self.rendered;
+    }
+
+    fn apply_escape(&mut self, escape: &str) {
+        // Only CSI sequences (`ESC [ params final`) are interpreted
+        let Some(body) = escape.strip_prefix('[') else {
+            return;
+        };
+        let Some(command) = body.chars().last() else {
+            return;
+        };
+        let params: Vec<u16> = body[..body.len() - command.len_utf8()]
+            .split(';')
+            .filter(|p| !p.is_empty())
+            .map(|p| p.parse().unwrap_or(0))
+            .collect();
+        let count = params.first().copied().unwrap_or(1).max(1) as usize;
+        match command {
+            'm' => self.style.apply_sgr(&params),
+            'K' => match params.first().copied().unwrap_or(0) {
+                0 => {
+                    if self.cursor < self.line.len() {
+                        self.touch(self.cursor);
+                        self.line.truncate(self.cursor);
+                    }
+                }
+                1 => {
+                    self.touch(0);
+                    let end = (self.cursor + 1).min(self.line.len());
+                    for cell in &mut self.line[..end] {
+                        *cell = (' ', TextStyle::default());
+                    }
+                }
+                _ => {
+                    self.touch(0);
+                    self.line.clear();
+                }
+            },
+            'C' => self.move_cursor(self.cursor.saturating_add(count)),
+            'D' => self.cursor = self.cursor.saturating_sub(count),
+            'G' => self.move_cursor(count - 1),
+            _ => { /* Other sequences (e.g. moving to other lines) are dropped */ }
+        }
+    }
+}
+
+/// Checks if an escape sequence (without ESC) is complete
+fn escape_finished(escape: &str) -> bool {
+    let mut chars = escape.chars();
+    match chars.next() {
+        // CSI, ends by a character in range '@'..='~'
+        Some('[') => chars.last().is_some_and(|c| ('@'..='~').contains(&c)),
+        // OSC (e.g. title of a window), ends by BEL or ST
+        Some(']') => escape.ends_with('\x07') || escape.ends_with("\x1b\\"),
+        // Character set selection has one more character
+        Some('('..='/') => escape.chars().count() >= 2,
+        Some(_) => true,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_chunks(chunks: &[&str]) -> (String, Vec<StyleSpan>) {
+        let mut terminal = Terminal::default();
+        let mut text = String::new();
+        let mut spans = Vec::new();
+        for chunk in chunks {
+            terminal.write(&mut text, &mut spans, chunk);
+        }
+        (text, spans)
+    }
+
+    fn span(start: usize, end: usize, style: TextStyle) -> StyleSpan {
+        StyleSpan { start, end, style }
+    }
+
+    const RED: TextStyle = TextStyle {
+        fg: Some(Color::Indexed(1)),
+        bg: None,
+        bold: false,
+        dim: false,
+        italic: false,
+        underline: false,
+    };
+
+    #[test]
+    fn appended_chunks_change_only_the_end() {
+        let mut terminal = Terminal::default();
+        let mut text = String::new();
+        let mut spans = Vec::new();
+        let change = terminal.write(&mut text, &mut spans, "ab");
+        assert_eq!((change.char_offset, change.byte_offset), (0, 0));
+        let change = terminal.write(&mut text, &mut spans, "čd\nef");
+        assert_eq!((change.char_offset, change.byte_offset), (2, 2));
+        let change = terminal.write(&mut text, &mut spans, "g");
+        assert_eq!((change.char_offset, change.byte_offset), (7, 8));
+        assert_eq!(text, "abčd\nefg");
+    }
+
+    #[test]
+    fn carriage_return_rewrites_line() {
+        let mut terminal = Terminal::default();
+        let mut text = String::new();
+        let mut spans = Vec::new();
+        terminal.write(&mut text, &mut spans, "first\n 10%");
+        let change = terminal.write(&mut text, &mut spans, "\r 20%");
+        assert_eq!((change.char_offset, change.byte_offset), (6, 6));
+        assert_eq!(text, "first\n 20%");
+        let change = terminal.write(&mut text, &mut spans, "\r100%\ndone");
+        assert_eq!(change.char_offset, 6);
+        assert_eq!(text, "first\n100%\ndone");
+    }
+
+    #[test]
+    fn partial_rewrite_keeps_start_of_line() {
+        let mut terminal = Terminal::default();
+        let mut text = String::new();
+        let mut spans = Vec::new();
+        terminal.write(&mut text, &mut spans, "žluťoučký");
+        let change = terminal.write(&mut text, &mut spans, "\x08\x08\x08ab");
+        assert_eq!(change.char_offset, 6);
+        assert_eq!(change.byte_offset, "žluťou".len());
+        assert_eq!(text, "žluťouabý");
+        let change = terminal.write(&mut text, &mut spans, "\r\x1b[3C\x1b[K");
+        assert_eq!(change.char_offset, 3);
+        assert_eq!(text, "žlu");
+    }
+
+    #[test]
+    fn styles_are_merged_between_chunks() {
+        let (text, spans) = write_chunks(&["a\x1b[31mb", "c\x1b[", "0md\x1b[31me"]);
+        assert_eq!(text, "abcde");
+        assert_eq!(spans, vec![span(1, 3, RED), span(4, 5, RED)]);
+        let (text, spans) = write_chunks(&["\x1b[31mred\x1b[0m", "\rx"]);
+        assert_eq!(text, "xed");
+        assert_eq!(spans, vec![span(1, 3, RED)]);
+    }
+
+    #[test]
+    fn chunks_are_rendered_as_whole_text() {
+        let input = "a\x1b[1mb\rc\x1b[0m\nč\x1b[32m\x08\x08xy\x1b[2Kz\r\x1b[4Gw\n\x1b[31mend";
+        let whole = write_chunks(&[input]);
+        let chars: Vec<String> = input.chars().map(|c| c.to_string()).collect();
+        let chunks: Vec<&str> = chars.iter().map(|c| c.as_str()).collect();
+        assert_eq!(write_chunks(&chunks), whole);
+        for split in 1..input.len() {
+            if input.is_char_boundary(split) {
+                assert_eq!(write_chunks(&[&input[..split], &input[split..]]), whole);
+            }
+        }
+    }
+
+    #[test]
+    fn removed_text_cuts_unfinished_line() {
+        let mut terminal = Terminal::default();
+        let mut text = String::new();
+        let mut spans = Vec::new();
+        terminal.write(&mut text, &mut spans, "ab\ncdefgh");
+        // "b\ncde" is removed, so only "fgh" remains in the line
+        text.replace_range(1..6, "");
+        terminal.text_removed(&TextRemoval {
+            byte_offset: 1,
+            char_offset: 1,
+            bytes: 5,
+            chars: 5,
+        });
+        let change = terminal.write(&mut text, &mut spans, "\rX\x08\x08Y");
+        assert_eq!((change.char_offset, change.byte_offset), (1, 1));
+        assert_eq!(text, "aYgh");
+        terminal.text_removed(&TextRemoval {
+            byte_offset: 0,
+            char_offset: 0,
+            bytes: 1,
+            chars: 1,
+        });
+        text.remove(0);
+        terminal.write(&mut text, &mut spans, "\rZ\n!");
+        assert_eq!(text, "Zgh\n!");
+    }
+
+    #[test]
+    fn cursor_movement_is_clamped() {
+        let (text, _) = write_chunks(&["ab\x1b[60000Cc"]);
+        assert_eq!(text, format!("ab{}c", " ".repeat(MAX_CURSOR_GAP)));
+        let (text, _) = write_chunks(&["ab\x1b[60000Gc"]);
+        assert_eq!(text, format!("ab{}c", " ".repeat(MAX_CURSOR_GAP)));
+        let (text, _) = write_chunks(&["ab\x1b[5Gc\x1b[2Dd"]);
+        assert_eq!(text, "ab dc");
+    }
+}